@@ -0,0 +1,215 @@
+//! What the machine graft runs on supports. Each tool is probed the first
+//! time a feature needs it, so commands that need nothing spawn nothing,
+//! and features are turned off up front with a reason instead of failing
+//! halfway through an operation.
+
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+use anyhow::{Result, bail};
+use tracing::debug;
+
+use crate::process::{Cmd, ProcessRunner};
+
+/// Oldest git with `worktree add`/`remove`/`prune` as graft uses them.
+pub const MIN_GIT: Version = Version(2, 17, 0);
+/// `git worktree repair`.
+const GIT_WORKTREE_REPAIR: Version = Version(2, 30, 0);
+/// `git sparse-checkout set --cone`.
+const GIT_SPARSE_CONE: Version = Version(2, 25, 0);
+/// First zellij release with `--new-session-with-layout` (`-n`).
+pub const ZELLIJ_NEW_SESSION_FLAG: Version = Version(0, 33, 0);
+/// `zellij action list-clients`.
+const ZELLIJ_LIST_CLIENTS: Version = Version(0, 41, 0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version(pub u32, pub u32, pub u32);
+
+impl Version {
+    /// Finds the first version-looking word in `--version` output, e.g.
+    /// `git version 2.39.5` or `Docker version 24.0.5, build ced0996`.
+    fn parse(output: &str) -> Option<Version> {
+        let word = output
+            .split_whitespace()
+            .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))?;
+        let mut parts = word.split('.').map(|part| {
+            let digits = part
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(part.len());
+            part[..digits].parse().unwrap_or(0)
+        });
+        Some(Version(
+            parts.next()?,
+            parts.next().unwrap_or(0),
+            parts.next().unwrap_or(0),
+        ))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// Something graft can do only when the right tool is installed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Feature {
+    Worktrees,
+    WorktreeRepair,
+    SparseCone,
+    Sessions,
+    SessionLayouts,
+    SessionClients,
+    PullRequests,
+    Direnv,
+    Docker,
+    Sccache,
+    Ccache,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 11] = [
+        Feature::Worktrees,
+        Feature::WorktreeRepair,
+        Feature::SparseCone,
+        Feature::Sessions,
+        Feature::SessionLayouts,
+        Feature::SessionClients,
+        Feature::PullRequests,
+        Feature::Direnv,
+        Feature::Docker,
+        Feature::Sccache,
+        Feature::Ccache,
+    ];
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Feature::Worktrees => "worktrees",
+            Feature::WorktreeRepair => "worktree repair",
+            Feature::SparseCone => "sparse checkouts (cone mode)",
+            Feature::Sessions => "zellij sessions",
+            Feature::SessionLayouts => "session layouts",
+            Feature::SessionClients => "attached/detached session state",
+            Feature::PullRequests => "GitHub pull requests",
+            Feature::Direnv => "direnv",
+            Feature::Docker => "docker",
+            Feature::Sccache => "sccache",
+            Feature::Ccache => "ccache",
+        };
+        f.write_str(name)
+    }
+}
+
+pub struct Capabilities {
+    zellij_bin: String,
+    runner: Arc<dyn ProcessRunner>,
+    git: OnceLock<Option<Version>>,
+    zellij: OnceLock<Option<Version>>,
+    gh: OnceLock<Option<Version>>,
+    direnv: OnceLock<Option<Version>>,
+    docker: OnceLock<Option<Version>>,
+    sccache: OnceLock<Option<Version>>,
+    ccache: OnceLock<Option<Version>>,
+}
+
+impl Capabilities {
+    pub fn new(zellij_bin: &str, runner: Arc<dyn ProcessRunner>) -> Self {
+        Capabilities {
+            zellij_bin: zellij_bin.to_string(),
+            runner,
+            git: OnceLock::new(),
+            zellij: OnceLock::new(),
+            gh: OnceLock::new(),
+            direnv: OnceLock::new(),
+            docker: OnceLock::new(),
+            sccache: OnceLock::new(),
+            ccache: OnceLock::new(),
+        }
+    }
+
+    pub fn git(&self) -> Option<Version> {
+        *self.git.get_or_init(|| self.probe("git"))
+    }
+
+    pub fn zellij(&self) -> Option<Version> {
+        *self.zellij.get_or_init(|| self.probe(&self.zellij_bin))
+    }
+
+    pub fn zellij_bin(&self) -> &str {
+        &self.zellij_bin
+    }
+
+    /// Why `feature` is unavailable, or `None` when it works.
+    pub fn missing(&self, feature: Feature) -> Option<String> {
+        let needs = |tool: &str, found: Option<Version>, min: Option<Version>| match (found, min) {
+            (None, _) => Some(format!("{tool} was not found")),
+            (Some(found), Some(min)) if found < min => {
+                Some(format!("{tool} {found} is older than {min}"))
+            }
+            _ => None,
+        };
+        match feature {
+            Feature::Worktrees => needs("git", self.git(), Some(MIN_GIT)),
+            Feature::WorktreeRepair => needs("git", self.git(), Some(GIT_WORKTREE_REPAIR)),
+            Feature::SparseCone => needs("git", self.git(), Some(GIT_SPARSE_CONE)),
+            Feature::Sessions => needs(&self.zellij_bin, self.zellij(), None),
+            Feature::SessionLayouts => needs(
+                &self.zellij_bin,
+                self.zellij(),
+                Some(ZELLIJ_NEW_SESSION_FLAG),
+            ),
+            Feature::SessionClients => {
+                needs(&self.zellij_bin, self.zellij(), Some(ZELLIJ_LIST_CLIENTS))
+            }
+            Feature::PullRequests => needs("gh", *self.gh.get_or_init(|| self.probe("gh")), None),
+            Feature::Direnv => needs(
+                "direnv",
+                *self.direnv.get_or_init(|| self.probe("direnv")),
+                None,
+            ),
+            Feature::Docker => needs(
+                "docker",
+                *self.docker.get_or_init(|| self.probe("docker")),
+                None,
+            ),
+            Feature::Sccache => needs(
+                "sccache",
+                *self.sccache.get_or_init(|| self.probe("sccache")),
+                None,
+            ),
+            Feature::Ccache => needs(
+                "ccache",
+                *self.ccache.get_or_init(|| self.probe("ccache")),
+                None,
+            ),
+        }
+    }
+
+    fn probe(&self, tool: &str) -> Option<Version> {
+        tool_version(self.runner.as_ref(), tool)
+    }
+
+    /// Fails with "`feature` disabled because …" unless it is available.
+    pub fn require(&self, feature: Feature) -> Result<()> {
+        match self.missing(feature) {
+            Some(reason) => bail!("{feature} disabled because {reason}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Version reported by `<tool> --version`; `None` if it isn't installed.
+fn tool_version(runner: &dyn ProcessRunner, tool: &str) -> Option<Version> {
+    let mut command = Cmd::new(tool);
+    command.arg("--version");
+    let output = runner.output(&command).ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let version = Version::parse(&String::from_utf8_lossy(&output.stdout));
+    debug!("probed {tool}: {version:?}");
+    version
+}
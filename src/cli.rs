@@ -1,39 +1,697 @@
-use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::state::Purpose;
 
 #[derive(Parser, Debug)]
-#[command(name = "graft", about = "Git worktree + Zellij session orchestrator")]
+#[command(
+    name = "graft",
+    about = "Git worktree + Zellij session orchestrator",
+    after_help = "`graft <branch> [OPTIONS] [-- <CMD>...]` is shorthand for `graft open`."
+)]
 pub struct Cli {
+    /// Operate on the repository at this path instead of the current directory
+    #[arg(short = 'C', long, global = true, value_name = "PATH")]
+    pub repo: Option<PathBuf>,
+
+    /// Show spawned commands and provisioning decisions (-vv for more)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Only print warnings and errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
     #[command(subcommand)]
     pub command: Command,
 }
 
+// Parsed once per run; boxing `OpenArgs` would save nothing.
 #[derive(Subcommand, Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum Command {
-    /// Open (or create) a worktree and attach a Zellij session
-    Open {
-        ticket: String,
+    /// Open (or create) a worktree and attach a Zellij session; `graft new
+    /// --issue <id>` names the branch after an issue
+    #[command(visible_alias = "new")]
+    Open(OpenArgs),
 
-        #[arg(short, long)]
-        ephemeral: bool,
+    /// Attach to a worktree's session; without a target, list sessions,
+    /// including ones graft didn't start whose directory is in a known repository
+    Attach(AttachArgs),
 
-        #[arg(long)]
-        delete_branch: bool,
-    },
+    /// Bring a worktree's running session to the front: switch this Zellij
+    /// client to it instead of attaching a second time inside the current one
+    Focus(FocusArgs),
+
+    /// Go back to the worktree used before the current one, like `cd -`
+    Last,
+
+    /// List worktrees by when they were last used and pick one to switch to
+    Recent(RecentArgs),
+
+    /// Check out a tag or commit into a detached worktree
+    Checkout(CheckoutArgs),
+
+    /// Check out a Gerrit change's patchset and open a review session
+    Change(ChangeArgs),
+
+    /// Move the uncommitted changes of the current checkout to a new branch
+    /// and worktree, leaving the checkout clean
+    Take(TakeArgs),
 
     /// Remove worktree and optionally delete branch
-    Rm {
-        ticket: String,
+    Rm(RmArgs),
 
-        #[arg(long)]
-        delete_branch: bool,
-    },
+    /// Restore the most recently trashed worktree
+    Undo,
+
+    /// Prune stale worktree metadata and sessions
+    Gc(GcArgs),
+
+    /// Rename a branch together with its worktree directory, session and
+    /// graft's records
+    Mv(MvArgs),
+
+    /// Bring a worktree created outside graft (`git worktree add`) under its
+    /// management: sessions, `ls` details and removal with `rm`
+    Adopt(AdoptArgs),
+
+    /// Lock a worktree so rm and pruning leave it alone
+    Lock(LockArgs),
+
+    /// Unlock a worktree locked with `graft lock`
+    Unlock(UnlockArgs),
+
+    /// Save a worktree's unpushed commits and uncommitted changes to
+    /// `.git/graft/archive`, then remove it, its session and its branch
+    Archive(ArchiveArgs),
+
+    /// Bring back an archived branch and worktree with its uncommitted
+    /// changes; lists the archives without a branch
+    Unarchive(UnarchiveArgs),
+
+    /// Make the main checkout's tracked files read-only
+    Protect,
+
+    /// Make the main checkout writable again after `graft protect`
+    Unprotect,
 
     /// List worktrees
-    Ls {
-        #[arg(long)]
-        prune_worktrees: bool,
+    Ls(LsArgs),
+
+    /// Manage the repositories `graft ls --all` covers
+    #[command(subcommand)]
+    Repo(RepoCommand),
+
+    /// List registered repositories with their running sessions and switch
+    /// to one
+    Repos(ReposArgs),
+
+    /// Add `git graft`, `git graft-open` and `git graft-rm` aliases to your
+    /// git config
+    InstallGitAliases(InstallGitAliasesArgs),
+
+    /// Inspect the effective configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+
+    /// Show disk usage per worktree
+    Du,
+
+    /// Summarize the worktrees graft manages, or how long operations took
+    Stats(StatsArgs),
+
+    /// Time spent attached to each worktree's session or shell, and how
+    /// often
+    Report(ReportArgs),
+
+    /// Show the worktree the current directory is in: branch, session,
+    /// age, expiry and local changes
+    Status(StatusArgs),
+
+    /// Leave a note on a worktree for next time, shown by `graft status`
+    /// and the banner; without text, list its notes
+    Note(NoteArgs),
+
+    /// Print a one-line segment for shell prompts: branch, `*` when there
+    /// are uncommitted changes and `●`/`○` inside this worktree's/another
+    /// Zellij session
+    Prompt,
+
+    /// Evict least recently used worktrees until they fit in a size budget
+    Clean(CleanArgs),
+
+    /// Dump branches, worktrees and sessions as tab-separated lines for fuzzy pickers
+    CompletionsData,
+
+    /// Fetch, prune deleted remote branches and fast-forward clean worktrees
+    Sync,
+
+    /// Keep a worktree following its remote branch, fast-forwarding while clean
+    Watch(WatchArgs),
+
+    /// Open a branch from a Zellij keybinding: ask for it (or read it from
+    /// stdin), then switch to its session or open it in a tab
+    ZellijPipe(ZellijPipeArgs),
+
+    /// Answer JSON-RPC requests (list, status, open, remove) on a Unix
+    /// socket, for editor extensions and scripts
+    Serve(ServeArgs),
+
+    /// Set up a repository: exclude the worktree directory from `git status`
+    /// and write a commented `.graft.toml`, `.graft/layout.kdl` and sample hooks
+    Init,
+
+    /// Repair worktree links and graft state after the repository moved, or
+    /// after other tools mangled a worktree's `.git` file, HEAD or branch
+    Repair,
+
+    /// Tour open, ls and rm in a throwaway repository
+    Demo(DemoArgs),
+
+    /// Check the environment and the repository's worktrees and suggest
+    /// fixes for common setup problems
+    Doctor,
+
+    /// Anything that isn't a subcommand is a branch to open
+    #[command(external_subcommand)]
+    Branch(Vec<String>),
+}
+
+/// Parses `graft <branch> …` the same way as `graft open <branch> …`.
+#[derive(Parser, Debug)]
+#[command(name = "graft")]
+struct BranchShorthand {
+    #[command(flatten)]
+    open: OpenArgs,
+}
+
+/// What makes no sense for sessions nobody attaches to.
+const BATCH_CONFLICTS: [&str; 10] = [
+    "ticket",
+    "editor",
+    "ephemeral",
+    "cleanup",
+    "delete_branch",
+    "then",
+    "after",
+    "attach",
+    "layout",
+    "command",
+];
+
+impl OpenArgs {
+    pub fn from_shorthand(args: Vec<String>) -> OpenArgs {
+        let argv = std::iter::once("graft".to_string()).chain(args);
+        BranchShorthand::parse_from(argv).open
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct OpenArgs {
+    /// Branch to open, also as `refs/heads/<branch>` or `<remote>/<branch>`;
+    /// a worktree's number in `graft ls` or the start of its name;
+    /// `pr:<n>` or `mr:<n>` for a pull or merge request; or a pull request,
+    /// commit, branch or Gerrit change URL
+    #[arg(required_unless_present_any = ["stdin", "file", "issue", "after"])]
+    pub ticket: Option<String>,
+
+    /// Name the branch after this issue (`1234`, `PROJ-12`, `ENG-7`) from
+    /// the `[issues]` template, and link the issue in `graft ls`
+    #[arg(long, value_name = "ID", conflicts_with_all = ["ticket", "stdin", "file"])]
+    pub issue: Option<String>,
+
+    /// Open every branch named on stdin, one per line, each with a session
+    /// started in the background (none with `--no-attach`)
+    #[arg(long, conflicts_with_all = BATCH_CONFLICTS)]
+    pub stdin: bool,
+
+    /// Like `--stdin`, with the branch names read from this file
+    #[arg(long, value_name = "PATH", conflicts_with_all = BATCH_CONFLICTS, conflicts_with = "stdin")]
+    pub file: Option<PathBuf>,
+
+    /// Clean up when the session exits (same as `--then rm`); what gets
+    /// cleaned up comes from `--cleanup` or the `cleanup` config key
+    #[arg(short, long)]
+    pub ephemeral: bool,
+
+    /// Look the branch up on this remote only, instead of the configured
+    /// fetch remote, push remote and then every other remote
+    #[arg(long)]
+    pub remote: Option<String>,
+
+    /// Don't ask remotes whether the branch exists; use or create a local
+    /// branch. Defaults to the `offline` config key
+    #[arg(long, conflicts_with = "remote")]
+    pub offline: bool,
+
+    /// What to clean up when the session exits; implies `--ephemeral`
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub cleanup: Vec<CleanupStep>,
+
+    /// Also delete the branch on cleanup (same as adding `branch` to `--cleanup`)
+    #[arg(long)]
+    pub delete_branch: bool,
+
+    /// What to do when the worktree path exists but isn't a worktree;
+    /// defaults to the `existing_dir` config key
+    #[arg(long, value_enum)]
+    pub existing: Option<ExistingDir>,
+
+    /// Actions to run, in order, once the session exits
+    #[arg(long = "then", value_enum, value_delimiter = ',')]
+    pub then: Vec<PostExit>,
+
+    /// Open nothing; wait for this worktree's running session to end and
+    /// run the `--then` actions on it
+    #[arg(long, value_name = "BRANCH", conflicts_with_all = ["ticket", "issue", "attach", "no_attach"])]
+    pub after: Option<String>,
+
+    /// Let `graft gc` remove the worktree after this long (`12h`, `7d`);
+    /// defaults to the `ttl` config for the worktree's purpose
+    #[arg(long, value_name = "DURATION")]
+    pub ttl: Option<String>,
+
+    /// What the worktree is for; inferred from the branch prefix by default
+    #[arg(long, value_enum)]
+    pub purpose: Option<Purpose>,
+
+    /// Don't ask before adding the worktree directory to `.git/info/exclude`
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// How to enter the worktree; defaults to the `attach` config key
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub attach: Option<AttachMode>,
+
+    /// Set a variable in a new session, the shell and hooks for this run
+    /// only; repeatable
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env_var)]
+    pub env: Vec<(String, String)>,
+
+    /// Only print the worktree's path (same as `--attach none`)
+    #[arg(long, conflicts_with = "attach")]
+    pub no_attach: bool,
+
+    /// Inside Zellij, open the worktree as a tab of the current session
+    /// instead of a session of its own (`session_strategy = "tabs"`)
+    #[arg(long, conflicts_with = "no_attach")]
+    pub tab: bool,
+
+    /// Start a new session with this layout: the name of one in
+    /// `.graft/layouts/`, or a path. Overrides `[policy]` rules and the
+    /// `layout` config key
+    #[arg(long, value_name = "NAME", conflicts_with = "command")]
+    pub layout: Option<PathBuf>,
+
+    /// Also open the worktree in the editor (the `editor` config key, else
+    /// `$VISUAL` or `$EDITOR`) before entering it. Editors with a window of
+    /// their own return at once; one in the terminal runs first
+    #[arg(long)]
+    pub editor: bool,
+
+    /// Check out submodules in a new worktree; defaults to the `submodules`
+    /// config key
+    #[arg(long)]
+    pub submodules: bool,
+
+    /// Check out only these directories in a new worktree (cone-mode sparse
+    /// checkout), or those of a `[sparse]` profile named here
+    #[arg(long, value_name = "PATH", num_args = 1.., value_delimiter = ',')]
+    pub sparse: Vec<String>,
+
+    /// Command to run in the session's first pane, or in place of the shell
+    /// with `--attach shell` (after `--`)
+    #[arg(last = true, value_name = "CMD")]
+    pub command: Vec<String>,
+}
+
+/// Follow-up actions run after a session exits. A failing action stops
+/// the chain, so `push,rm` never removes a worktree whose push failed.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PostExit {
+    /// Push the branch to the remote and set its upstream
+    Push,
+    /// Fast-forward the base branch in the main checkout to this branch
+    Merge,
+    /// Run the cleanup steps (session and worktree by default)
+    Rm,
+    /// Run `graft gc`: remove expired worktrees and stale sessions
+    Gc,
+}
+
+/// How `graft open` enters the worktree once it exists.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AttachMode {
+    /// Attach the worktree's Zellij session
+    #[default]
+    Zellij,
+    /// Run `$SHELL` (or the command) in the worktree, without Zellij
+    Shell,
+    /// Print the worktree's path and exit, e.g. for `cd "$(graft <branch> --no-attach)"`
+    None,
+    /// Open the worktree in the `editor` config command (else `$VISUAL` or
+    /// `$EDITOR`) instead of a session
+    Editor,
+    /// Open the worktree in a new WezTerm tab (`wezterm cli spawn`)
+    Wezterm,
+    /// Open the worktree in a new kitty tab; needs kitty's remote control
+    Kitty,
+}
+
+/// How to handle a file or directory that already occupies a new
+/// worktree's path.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExistingDir {
+    /// Stop with an error
+    #[default]
+    Fail,
+    /// Create the worktree and keep the directory's files on top of it
+    Adopt,
+    /// Rename it to `<path>.bak-<timestamp>` and start fresh
+    Move,
+}
+
+/// One part of cleaning up after a session. Removing the worktree always
+/// ends its session too.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CleanupStep {
+    /// Kill the Zellij session
+    Session,
+    /// Remove the worktree directory
+    Worktree,
+    /// Delete the local branch (needs `worktree`)
+    Branch,
+    /// Delete ignored build output, keeping the worktree
+    Artifacts,
+}
+
+#[derive(Args, Debug)]
+pub struct DemoArgs {
+    /// Leave the sandbox repository behind to keep experimenting
+    #[arg(long)]
+    pub keep: bool,
+}
 
-        #[arg(long)]
-        prune_sessions: bool,
+#[derive(Args, Debug)]
+pub struct AttachArgs {
+    /// Branch of a worktree, or the name of any Zellij session
+    pub target: Option<String>,
+
+    /// Make a foreign session the worktree's session from now on, so open,
+    /// ls and rm use it instead of starting a `wt-` one
+    #[arg(long, requires = "target")]
+    pub adopt: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct FocusArgs {
+    /// Branch of the worktree whose session to switch to
+    pub branch: String,
+}
+
+#[derive(Args, Debug)]
+pub struct RecentArgs {
+    /// Only list; don't offer to switch
+    #[arg(long)]
+    pub list: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct CheckoutArgs {
+    /// Tag, commit SHA or any other revision
+    pub rev: String,
+
+    /// Keep the worktree after the session exits (removed by default)
+    #[arg(long)]
+    pub keep: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct TakeArgs {
+    /// New branch for the changes, started at the current checkout's HEAD
+    pub branch: String,
+}
+
+#[derive(Args, Debug)]
+pub struct ChangeArgs {
+    /// Change number with an optional patchset (`12345/2`), or its Gerrit
+    /// URL; the latest patchset by default
+    pub change: String,
+
+    /// Use a detached worktree, removed when the session exits, instead of
+    /// a `change/<number>/<patchset>` branch
+    #[arg(long)]
+    pub detach: bool,
+
+    /// Keep the detached worktree after the session exits
+    #[arg(long, requires = "detach")]
+    pub keep: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct RmArgs {
+    /// Branches, glob patterns over worktree names (`'review/*'`) or
+    /// worktree paths (`.worktrees/foo`, from the `-C` directory if given)
+    #[arg(required_unless_present_any = ["interactive", "all"])]
+    pub tickets: Vec<String>,
+
+    /// Take the arguments as Zellij session names (`wt-foo`) and remove the
+    /// worktrees they belong to
+    #[arg(long, conflicts_with_all = ["interactive", "all"])]
+    pub by_session: bool,
+
+    /// Pick the worktrees to remove from a checklist
+    #[arg(short, long, conflicts_with = "tickets")]
+    pub interactive: bool,
+
+    /// Remove every worktree and its session, except locked ones and ones
+    /// with uncommitted or untracked files
+    #[arg(long, conflicts_with_all = ["tickets", "interactive"])]
+    pub all: bool,
+
+    /// With `--all`, include worktrees with uncommitted or untracked files,
+    /// after confirming them by name
+    #[arg(long, requires = "all")]
+    pub force: bool,
+
+    /// Don't ask before removing several worktrees
+    #[arg(short, long)]
+    pub yes: bool,
+
+    #[arg(long)]
+    pub delete_branch: bool,
+
+    /// Move the worktree to the trash instead of deleting it (see `graft undo`)
+    #[arg(long)]
+    pub trash: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ZellijPipeArgs {
+    /// Branch to open; asked for, or read from stdin, when not given
+    pub branch: Option<String>,
+
+    /// Open the worktree as a tab in the current session instead of
+    /// switching to its own; the default with `session_strategy = "tabs"`
+    #[arg(long)]
+    pub tab: bool,
+
+    /// Print a keybinding for Zellij's `config.kdl` that runs this
+    #[arg(long, conflicts_with_all = ["branch", "tab"])]
+    pub keybinding: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// Socket to listen on; defaults to `.git/graft/graft.sock`
+    #[arg(long, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct WatchArgs {
+    pub branch: String,
+
+    /// Seconds between fetches
+    #[arg(long, default_value_t = 60)]
+    pub interval: u64,
+}
+
+#[derive(Args, Debug)]
+pub struct InstallGitAliasesArgs {
+    /// Only for this repository, instead of in the global config
+    #[arg(long)]
+    pub local: bool,
+
+    /// Remove the aliases again
+    #[arg(long)]
+    pub uninstall: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ReposArgs {
+    /// Only list; don't offer to switch
+    #[arg(long)]
+    pub list: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct StatusArgs {
+    /// Print one JSON object, for shell prompts and scripts
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct NoteArgs {
+    /// The note
+    pub text: Vec<String>,
+
+    /// Worktree to note instead of the one the current directory is in
+    #[arg(long, short)]
+    pub branch: Option<String>,
+
+    /// Drop note N, numbered as in the list, once it is done
+    #[arg(long, value_name = "N", conflicts_with = "text")]
+    pub done: Option<usize>,
+}
+
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    /// Show recorded operation timings and their trend (needs `timings = true`)
+    #[arg(long)]
+    pub timings: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ReportArgs {
+    /// Only the last stretch of time, e.g. `1d` or `2w`; everything
+    /// recorded by default
+    #[arg(long, value_name = "DURATION")]
+    pub since: Option<String>,
+
+    /// Print JSON instead of a table
+    #[arg(long, conflicts_with = "csv")]
+    pub json: bool,
+
+    /// Print CSV (`branch,attaches,seconds`) instead of a table
+    #[arg(long)]
+    pub csv: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct GcArgs {
+    /// Permanently delete everything in the trash
+    #[arg(long)]
+    pub empty_trash: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct CleanArgs {
+    /// Size budget for all worktrees, e.g. 20G (default: max_total_size config)
+    #[arg(long, value_name = "SIZE")]
+    pub max_total_size: Option<String>,
+
+    /// Only print what would be removed
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AdoptArgs {
+    /// The worktree's directory
+    pub path: PathBuf,
+
+    /// What the worktree is for; inferred from the branch prefix by default
+    #[arg(long, value_enum)]
+    pub purpose: Option<Purpose>,
+}
+
+#[derive(Args, Debug)]
+pub struct MvArgs {
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Args, Debug)]
+pub struct LockArgs {
+    pub ticket: String,
+
+    /// Why the worktree must be kept, shown when something skips it
+    #[arg(long)]
+    pub reason: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct UnlockArgs {
+    pub ticket: String,
+}
+
+#[derive(Args, Debug)]
+pub struct ArchiveArgs {
+    pub branch: String,
+}
+
+#[derive(Args, Debug)]
+pub struct UnarchiveArgs {
+    pub branch: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct LsArgs {
+    #[arg(long)]
+    pub prune_worktrees: bool,
+
+    #[arg(long)]
+    pub prune_sessions: bool,
+
+    /// List every repository registered with `graft repo add`
+    #[arg(long)]
+    pub all: bool,
+
+    /// Group worktrees under their `/`-separated prefixes (`feature/`, `hotfix/`)
+    #[arg(long)]
+    pub tree: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RepoCommand {
+    /// Register a repository (default: the current one)
+    Add { path: Option<PathBuf> },
+
+    /// Unregister a repository (default: the current one)
+    Remove { path: Option<PathBuf> },
+
+    /// List registered repositories
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print the effective configuration (user config plus `.graft.toml`)
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Toml)]
+        format: ExportFormat,
     },
 }
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ExportFormat {
+    /// `config.toml` syntax
+    Toml,
+    /// A Nix attribute set, e.g. for home-manager's `pkgs.formats.toml`
+    Nix,
+}
+
+fn parse_env_var(text: &str) -> Result<(String, String), String> {
+    match text.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{text}'")),
+    }
+}
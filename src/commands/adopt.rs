@@ -0,0 +1,36 @@
+use anyhow::{Context as _, Result, bail};
+use tracing::info;
+
+use super::Context;
+use super::open::record;
+use crate::cli::AdoptArgs;
+use crate::state::Purpose;
+
+/// Records a worktree git knows about but graft didn't create, wherever it
+/// lives, so it gets a session, shows up as managed and `rm` may delete it.
+pub fn run(ctx: &Context, args: AdoptArgs) -> Result<()> {
+    let _lock = ctx.lock()?;
+    let path = args
+        .path
+        .canonicalize()
+        .with_context(|| format!("{} does not exist", args.path.display()))?;
+    let worktrees = ctx.git.worktrees()?;
+    let Some(worktree) = worktrees
+        .iter()
+        .find(|worktree| worktree.path.canonicalize().is_ok_and(|p| p == path))
+    else {
+        bail!(
+            "{} is not a worktree of {}; `git worktree list` shows them",
+            path.display(),
+            ctx.git.root().display()
+        );
+    };
+    let label = worktree.label();
+    if ctx.state()?.worktrees.contains_key(label) {
+        bail!("{label} is already managed by graft");
+    }
+    let purpose = args.purpose.unwrap_or_else(|| Purpose::infer(label));
+    record(ctx, label, &worktree.path, purpose)?;
+    info!("Adopted {} at {}", label, worktree.path.display());
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as _, Result};
+use tracing::info;
+
+use crate::cli::InstallGitAliasesArgs;
+use crate::git::run_ok_in;
+use crate::process::{Cmd, ProcessRunner, SystemRunner};
+
+/// Alias name and the graft subcommand it runs; `git graft` takes any.
+const ALIASES: &[(&str, &str)] = &[("graft", ""), ("graft-open", " open"), ("graft-rm", " rm")];
+
+/// Writes shell aliases (`!graft open`) into the global or repository git
+/// config. Git runs them from the top of the working tree, where graft
+/// finds the repository as usual.
+pub fn run(repo: Option<&Path>, args: InstallGitAliasesArgs) -> Result<()> {
+    let runner = SystemRunner;
+    let dir = repo.map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+    let scope = if args.local { "--local" } else { "--global" };
+    let program = program()?;
+
+    for (name, subcommand) in ALIASES {
+        let key = format!("alias.{name}");
+        if args.uninstall {
+            // Only ours; a same-named alias of someone else's stays.
+            if current(&runner, &dir, Some(scope), &key).is_some_and(|value| ours(&value)) {
+                run_ok_in(&runner, &dir, &["config", scope, "--unset", &key])?;
+                info!("Removed git {name}");
+            }
+            continue;
+        }
+        let value = format!("!{program}{subcommand}");
+        run_ok_in(&runner, &dir, &["config", scope, &key, &value])?;
+        println!("git {:<12} runs {}{}", name, program, subcommand);
+    }
+    Ok(())
+}
+
+/// `graft` when that is this binary on `$PATH`, else its full path, so the
+/// aliases work without graft on `$PATH` too.
+fn program() -> Result<String> {
+    let exe = env::current_exe().context("cannot tell where the graft binary is")?;
+    let on_path = env::var_os("PATH").is_some_and(|paths| {
+        env::split_paths(&paths).any(|dir| {
+            dir.join("graft")
+                .canonicalize()
+                .is_ok_and(|found| exe.canonicalize().is_ok_and(|exe| exe == found))
+        })
+    });
+    if on_path {
+        return Ok("graft".to_string());
+    }
+    let exe = exe.to_string_lossy();
+    Ok(format!("'{}'", exe.replace('\'', r"'\''")))
+}
+
+/// The aliases of graft's that are set for `dir`, with what they run.
+pub(super) fn installed(runner: &dyn ProcessRunner, dir: &Path) -> Vec<(&'static str, String)> {
+    ALIASES
+        .iter()
+        .filter_map(|(name, _)| {
+            let value = current(runner, dir, None, &format!("alias.{name}"))?;
+            ours(&value).then(|| (*name, value.trim_start_matches('!').to_string()))
+        })
+        .collect()
+}
+
+fn ours(value: &str) -> bool {
+    value.starts_with('!') && value.contains("graft")
+}
+
+/// The alias's value in `scope`, else in whichever config sets it.
+fn current(
+    runner: &dyn ProcessRunner,
+    dir: &Path,
+    scope: Option<&str>,
+    key: &str,
+) -> Option<String> {
+    let mut command = Cmd::new("git");
+    command.arg("-C").arg(dir).arg("config").args(scope);
+    command.args(["--get", key]);
+    let output = runner.output(&command).ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,262 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as _, Result, bail};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::Context;
+use super::{protect, rm};
+use crate::cli::{ArchiveArgs, UnarchiveArgs};
+use crate::hooks;
+use crate::process::Cmd;
+use crate::progress;
+use crate::state::{self, WorktreeRecord};
+use crate::units::format_age;
+
+/// Inside the state directory, one subdirectory per archived branch.
+const ARCHIVE_DIR: &str = "archive";
+const MANIFEST: &str = "archive.json";
+const BUNDLE: &str = "commits.bundle";
+const CHANGES: &str = "changes.tar.gz";
+/// Paths for tar to pack, NUL-separated.
+const FILE_LIST: &str = "files";
+
+/// What an archive holds, besides the bundle and tarball next to it.
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    branch: String,
+    /// Commit the branch was on.
+    head: String,
+    /// Commit the bundle builds on; it must still exist to restore.
+    basis: Option<String>,
+    /// Commits in `commits.bundle`; none means there is no bundle.
+    commits: usize,
+    /// Changed and untracked files in `changes.tar.gz`.
+    changed: Vec<PathBuf>,
+    /// Tracked files deleted but not committed.
+    deleted: Vec<PathBuf>,
+    archived_at: u64,
+    record: Option<WorktreeRecord>,
+}
+
+/// Packs the branch's unpushed commits into a bundle and its uncommitted
+/// files into a tarball, then removes the worktree, session and branch.
+pub fn archive(ctx: &Context, args: ArchiveArgs) -> Result<()> {
+    let branch = args.branch;
+    let _lock = ctx.lock()?;
+    let Some(worktree) = ctx
+        .git
+        .worktrees()?
+        .into_iter()
+        .find(|worktree| worktree.branch.as_deref() == Some(branch.as_str()))
+    else {
+        bail!("no worktree for branch '{branch}'");
+    };
+    if ctx.git.lock_reason(&branch)?.is_some() {
+        bail!("{branch} is locked; run `graft unlock {branch}` first");
+    }
+    let dir = archive_path(ctx, &branch);
+    if dir.exists() {
+        bail!(
+            "{branch} is already archived in {}; restore or delete it first",
+            dir.display()
+        );
+    }
+    fs::create_dir_all(&dir)?;
+
+    let saved = save(ctx, &branch, &worktree.path, &dir);
+    // Nothing may be removed unless everything was saved.
+    let manifest = match saved {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            let _ = fs::remove_dir_all(&dir);
+            return Err(err.context(format!("failed to archive {branch}; nothing was removed")));
+        }
+    };
+    if let Err(err) = rm::remove(ctx, &branch, true, false) {
+        // Keep the archive once the worktree is gone, whatever else failed.
+        if worktree.path.exists() {
+            let _ = fs::remove_dir_all(&dir);
+        }
+        return Err(err);
+    }
+    info!(
+        "Archived {} to {}: {} unpushed commit(s), {} changed and {} deleted file(s)",
+        branch,
+        dir.display(),
+        manifest.commits,
+        manifest.changed.len(),
+        manifest.deleted.len()
+    );
+    Ok(())
+}
+
+/// Writes the bundle, tarball and manifest for `branch` into `dir`.
+fn save(ctx: &Context, branch: &str, worktree: &Path, dir: &Path) -> Result<Manifest> {
+    let bundled = ctx.git.bundle_unpushed(branch, &dir.join(BUNDLE))?;
+    let (mut changed, deleted) = ctx.git.uncommitted_files(worktree)?;
+    // Decrypted secrets stay out of the tarball; unarchiving fetches them again.
+    let secrets = &ctx.config.secrets.files;
+    changed.retain(|path| {
+        !secrets
+            .iter()
+            .any(|file| file.strip_prefix(".").unwrap_or(file) == path)
+    });
+    if !changed.is_empty() {
+        let list = dir.join(FILE_LIST);
+        let mut names = Vec::new();
+        for path in &changed {
+            names.extend_from_slice(path.as_os_str().as_encoded_bytes());
+            names.push(0);
+        }
+        fs::write(&list, names)?;
+        let mut tar = Cmd::new("tar");
+        tar.arg("-czf")
+            .arg(dir.join(CHANGES))
+            .arg("-C")
+            .arg(worktree)
+            .arg("--null")
+            .arg("-T")
+            .arg(&list);
+        run_tar(ctx, &tar)?;
+        fs::remove_file(list)?;
+    }
+    let manifest = Manifest {
+        branch: branch.to_string(),
+        head: ctx.git.resolve_commit(branch)?.to_string(),
+        basis: bundled.basis,
+        commits: bundled.commits,
+        changed,
+        deleted,
+        archived_at: state::now(),
+        record: ctx.state()?.worktrees.get(branch).cloned(),
+    };
+    fs::write(dir.join(MANIFEST), serde_json::to_string_pretty(&manifest)?)?;
+    Ok(manifest)
+}
+
+/// Recreates the branch from the bundle, checks it out again and unpacks
+/// the uncommitted files over it (unstaged, even those that were staged),
+/// then drops the archive.
+pub fn unarchive(ctx: &Context, args: UnarchiveArgs) -> Result<()> {
+    let Some(branch) = args.branch else {
+        return list(ctx);
+    };
+    let _lock = ctx.lock()?;
+    let dir = archive_path(ctx, &branch);
+    let manifest: Manifest = match fs::read_to_string(dir.join(MANIFEST)) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .with_context(|| format!("corrupt archive manifest in {}", dir.display()))?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            bail!("no archive for '{branch}'; `graft unarchive` lists them")
+        }
+        Err(err) => return Err(err).context("failed to read the archive"),
+    };
+    if ctx.git.has_branch(&branch) {
+        bail!("branch {branch} exists again; rename or delete it to restore the archived one");
+    }
+
+    if manifest.commits > 0 {
+        ctx.git.unbundle(&dir.join(BUNDLE))?;
+    }
+    let head = ctx
+        .git
+        .resolve_commit(&manifest.head)
+        .with_context(|| format!("the commit {branch} was on is gone"))?;
+    ctx.git.create_branch_at(&branch, head)?;
+    if ctx.config.track_upstream {
+        ctx.git.track_remote_branch(&branch)?;
+    }
+    let worktree = progress::spin(&format!("Checking out {branch}"), || {
+        ctx.git.ensure_worktree(&branch)
+    })?;
+    let path = worktree.path().to_path_buf();
+    if !manifest.changed.is_empty() {
+        let mut tar = Cmd::new("tar");
+        tar.arg("-xzf").arg(dir.join(CHANGES)).arg("-C").arg(&path);
+        run_tar(ctx, &tar)?;
+    }
+    for deleted in &manifest.deleted {
+        match fs::remove_file(path.join(deleted)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+    if let Err(err) = hooks::post_create(
+        ctx.git.runner(),
+        &ctx.git.root(),
+        &ctx.config.secrets,
+        &branch,
+        &path,
+        &ctx.worktree_env(&branch, &path),
+    ) {
+        warn!("{err:#}");
+    }
+
+    if let Some(mut record) = manifest.record {
+        let mut state = ctx.state()?;
+        record.path = path.clone();
+        // An adopted session went with the worktree.
+        record.session = Some(state.new_session(&branch));
+        state.worktrees.insert(branch.clone(), record);
+        state.save()?;
+    }
+    fs::remove_dir_all(&dir)?;
+    info!("Restored {} at {}", branch, path.display());
+    protect::refresh(ctx);
+    Ok(())
+}
+
+/// Archived branches, newest first.
+fn list(ctx: &Context) -> Result<()> {
+    let mut manifests: Vec<Manifest> = Vec::new();
+    if let Ok(entries) = fs::read_dir(ctx.git.state_dir().join(ARCHIVE_DIR)) {
+        for entry in entries.flatten() {
+            let Ok(contents) = fs::read_to_string(entry.path().join(MANIFEST)) else {
+                continue;
+            };
+            match serde_json::from_str(&contents) {
+                Ok(manifest) => manifests.push(manifest),
+                Err(err) => warn!("skipping {}: {err}", entry.path().display()),
+            }
+        }
+    }
+    if manifests.is_empty() {
+        println!("No archived branches.");
+        return Ok(());
+    }
+    manifests.sort_by_key(|manifest| std::cmp::Reverse(manifest.archived_at));
+    let now = state::now();
+    for manifest in manifests {
+        println!(
+            "  {:<30} {:>5} ago  {} commit(s), {} changed file(s)",
+            manifest.branch,
+            format_age(now.saturating_sub(manifest.archived_at)),
+            manifest.commits,
+            manifest.changed.len() + manifest.deleted.len()
+        );
+    }
+    Ok(())
+}
+
+fn run_tar(ctx: &Context, tar: &Cmd) -> Result<()> {
+    let output = ctx.git.runner().output(tar).context("failed to run tar")?;
+    if !output.status.success() {
+        bail!(
+            "{} failed: {}",
+            tar,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// `.git/graft/archive/<branch>`, with `/` as `-`.
+fn archive_path(ctx: &Context, branch: &str) -> PathBuf {
+    ctx.git
+        .state_dir()
+        .join(ARCHIVE_DIR)
+        .join(branch.replace('/', "-"))
+}
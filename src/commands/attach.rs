@@ -0,0 +1,119 @@
+use std::path::PathBuf;
+
+use anyhow::{Context as _, Result, bail};
+use tracing::info;
+
+use super::Context;
+use super::open::{attach, record};
+use crate::cli::{AttachArgs, AttachMode};
+use crate::registry::Registry;
+use crate::state::Purpose;
+use crate::zellij;
+
+pub fn run(ctx: &Context, args: AttachArgs) -> Result<()> {
+    let Some(target) = args.target else {
+        return list(ctx);
+    };
+    let worktrees = ctx.git.worktrees()?;
+    if let Some(worktree) = worktrees.iter().find(|w| w.label() == target) {
+        ctx.touch(&target)?;
+        return attach(
+            ctx,
+            &target,
+            &worktree.path,
+            AttachMode::Zellij,
+            &[],
+            &[],
+            &[],
+        );
+    }
+
+    if !ctx.zellij.sessions().contains(&target) {
+        bail!("no worktree or session named '{target}'");
+    }
+    let cwd = zellij::session_cwd(&target);
+    if args.adopt {
+        let cwd = cwd.with_context(|| {
+            format!("cannot tell which directory session '{target}' was started in")
+        })?;
+        let Some(worktree) = worktrees.iter().find(|w| cwd.starts_with(&w.path)) else {
+            bail!(
+                "session '{target}' runs in {}, which is not inside a worktree of this repository",
+                cwd.display()
+            );
+        };
+        let label = worktree.label();
+        let lock = ctx.lock()?;
+        record(ctx, label, &worktree.path, Purpose::infer(label))?;
+        let mut state = ctx.state()?;
+        if let Some(record) = state.worktrees.get_mut(label) {
+            record.session = Some(target.clone());
+        }
+        state.save()?;
+        info!("Adopted session {} for {}", target, label);
+        drop(lock);
+        return attach(
+            ctx,
+            label,
+            &worktree.path,
+            AttachMode::Zellij,
+            &[],
+            &[],
+            &[],
+        );
+    }
+
+    let dir = cwd
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(|| ctx.git.root());
+    ctx.zellij
+        .start_session(&target, &dir, None, &ctx.session_env())
+}
+
+/// Graft's sessions, then sessions started by hand inside this or any
+/// registered repository, with how to adopt them.
+fn list(ctx: &Context) -> Result<()> {
+    let sessions = ctx.zellij.sessions();
+    let state = ctx.state()?;
+    let mut known = Vec::new();
+    for worktree in ctx.git.worktrees()? {
+        let session = state.session(worktree.label());
+        if sessions.contains(&session) {
+            println!("  {:<30} {}", worktree.label(), session);
+            known.push(session);
+        }
+    }
+
+    let root = ctx.git.root();
+    // (directory, repository it belongs to); worktrees may live elsewhere.
+    let mut repos: Vec<(PathBuf, PathBuf)> = vec![
+        (root.clone(), root.clone()),
+        (ctx.git.worktree_dir(), root.clone()),
+    ];
+    let registered = Registry::load().map(|r| r.repos).unwrap_or_default();
+    repos.extend(registered.into_iter().map(|repo| (repo.clone(), repo)));
+    let mut foreign = Vec::new();
+    for session in &sessions {
+        if known.contains(session) || session.starts_with(&zellij::session_prefix()) {
+            continue;
+        }
+        let Some(cwd) = zellij::session_cwd(session) else {
+            continue;
+        };
+        if let Some((_, repo)) = repos.iter().find(|(dir, _)| cwd.starts_with(dir)) {
+            foreign.push((session, cwd.clone(), repo.clone()));
+        }
+    }
+    if !foreign.is_empty() {
+        println!("\nNot started by graft:");
+        for (session, cwd, repo) in foreign {
+            let adopt = if repo == root {
+                format!("graft attach {session} --adopt")
+            } else {
+                format!("graft -C {} attach {session} --adopt", repo.display())
+            };
+            println!("  {:<30} {}  (adopt: {})", session, cwd.display(), adopt);
+        }
+    }
+    Ok(())
+}
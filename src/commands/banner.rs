@@ -0,0 +1,48 @@
+//! With `banner = true`, entering a worktree first says where it stands,
+//! for picking up work left a week ago.
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use anyhow::Result;
+
+use super::Context;
+use crate::state;
+use crate::units::format_age;
+
+/// A few lines: the branch against its base and upstream, the last
+/// commit, local changes, then the worktree's notes.
+pub fn text(ctx: &Context, name: &str, path: &Path) -> Result<String> {
+    let now = state::now();
+    let tip = ctx.git.tip(path)?;
+    let changes = ctx.git.inspect(path)?;
+    let mut text = name.to_string();
+    if let Some((ahead, behind)) = ctx.git.versus_base(path)? {
+        let base = ctx.git.base_branch();
+        write!(text, "  {ahead} ahead of {base}, {behind} behind")?;
+    }
+    match tip.upstream {
+        Some((0, 0)) => text.push_str("  (up to date with upstream)"),
+        Some((ahead, behind)) => write!(text, "  (upstream: {ahead} ahead, {behind} behind)")?,
+        None => text.push_str("  (no upstream)"),
+    }
+    write!(
+        text,
+        "\nlast commit {} ago: {}",
+        format_age(now.saturating_sub(tip.time)),
+        tip.subject
+    )?;
+    if changes.dirty_files + changes.untracked_files > 0 {
+        write!(
+            text,
+            "\n{} modified, {} untracked",
+            changes.dirty_files, changes.untracked_files
+        )?;
+    }
+    if let Some(record) = ctx.state()?.worktrees.get(name) {
+        for (i, note) in record.notes.iter().enumerate() {
+            write!(text, "\n  {}. {}", i + 1, note)?;
+        }
+    }
+    Ok(text)
+}
@@ -0,0 +1,124 @@
+use anyhow::{Context as _, Result, bail};
+use tracing::{info, warn};
+
+use super::Context;
+use super::open::{attach, record};
+use crate::cli::{AttachMode, ChangeArgs, CleanupStep, PostExit};
+use crate::hooks;
+use crate::progress;
+use crate::state::Purpose;
+
+pub fn run(ctx: &Context, args: ChangeArgs) -> Result<()> {
+    open(ctx, args, ctx.config.attach)
+}
+
+/// [`run`], attaching with `mode`; `graft open` hands change URLs here.
+pub(super) fn open(ctx: &Context, args: ChangeArgs, mode: AttachMode) -> Result<()> {
+    let (change, patchset) = parse_change(&args.change)?;
+    let remote = ctx.git.fetch_remote();
+    let patchset = match patchset {
+        Some(patchset) => patchset,
+        None => latest_patchset(ctx, remote, change)?,
+    };
+    let refname = change_ref(change, patchset);
+    // FETCH_HEAD is shared, so hold the lock from the fetch on.
+    let lock = ctx.lock()?;
+    info!("Fetching change {change}, patchset {patchset}");
+    progress::spin("Fetching", || {
+        ctx.git.run_remote(&["fetch", remote, &refname])
+    })
+    .with_context(|| format!("failed to fetch {refname} from {remote}"))?;
+    let oid = ctx.git.resolve_commit("FETCH_HEAD")?;
+
+    let (name, path, created) = if args.detach {
+        let name = format!("change-{change}-{patchset}");
+        let path = ctx.git.worktree_path(&name);
+        let created = !path.exists();
+        if created {
+            progress::spin(&format!("Checking out {name}"), || {
+                ctx.git.create_detached_worktree(&name, oid)
+            })?;
+        }
+        (name, path, created)
+    } else {
+        let name = format!("change/{change}/{patchset}");
+        if !ctx.git.has_branch(&name) {
+            ctx.git.create_branch_at(&name, oid)?;
+        }
+        let created = !ctx.git.has_worktree(&name);
+        let worktree = progress::spin(&format!("Checking out {name}"), || {
+            ctx.git.ensure_worktree(&name)
+        })?;
+        let path = worktree.path().to_path_buf();
+        (name, path, created)
+    };
+    if created
+        && let Err(err) = hooks::post_create(
+            ctx.git.runner(),
+            &ctx.git.root(),
+            &ctx.config.secrets,
+            &name,
+            &path,
+            &ctx.worktree_env(&name, &path),
+        )
+    {
+        warn!("{err:#}");
+    }
+    record(ctx, &name, &path, Purpose::Review)?;
+
+    // Like `graft checkout`, a detached review is gone when its session is.
+    let then: &[PostExit] = if args.detach && !args.keep {
+        &[PostExit::Rm]
+    } else {
+        &[]
+    };
+    drop(lock);
+    attach(
+        ctx,
+        &name,
+        &path,
+        mode,
+        &[],
+        then,
+        &[CleanupStep::Session, CleanupStep::Worktree],
+    )
+}
+
+/// `12345`, `12345/2` or a change URL ending in `+/12345[/2]`.
+fn parse_change(input: &str) -> Result<(u64, Option<u32>)> {
+    let spec = input
+        .rsplit_once("/+/")
+        .map_or(input, |(_, spec)| spec)
+        .trim_end_matches('/');
+    let (change, patchset) = match spec.split_once('/') {
+        Some((change, patchset)) => (change, Some(patchset)),
+        None => (spec, None),
+    };
+    let Ok(change) = change.parse() else {
+        bail!("'{input}' is not a change number (expected 12345 or 12345/2)");
+    };
+    let patchset = match patchset.map(str::parse) {
+        Some(Ok(0)) | Some(Err(_)) => bail!("'{input}' has no valid patchset number"),
+        Some(Ok(patchset)) => Some(patchset),
+        None => None,
+    };
+    Ok((change, patchset))
+}
+
+/// Gerrit's ref for a patchset: `refs/changes/<last two digits>/<change>/<patchset>`.
+fn change_ref(change: u64, patchset: u32) -> String {
+    format!("refs/changes/{:02}/{change}/{patchset}", change % 100)
+}
+
+fn latest_patchset(ctx: &Context, remote: &str, change: u64) -> Result<u32> {
+    let pattern = format!("refs/changes/{:02}/{change}/*", change % 100);
+    let out = ctx
+        .git
+        .run_remote(&["ls-remote", remote, &pattern])
+        .with_context(|| format!("failed to list the patchsets of change {change}"))?;
+    // Patchset refs end in a number; `…/meta` holds review metadata.
+    out.lines()
+        .filter_map(|line| line.rsplit('/').next()?.parse().ok())
+        .max()
+        .with_context(|| format!("change {change} not found on {remote}"))
+}
@@ -0,0 +1,51 @@
+use anyhow::Result;
+use tracing::warn;
+
+use super::Context;
+use super::open::{attach, record};
+use crate::cli::{AttachMode, CheckoutArgs, CleanupStep, PostExit};
+use crate::hooks;
+use crate::progress;
+use crate::state::Purpose;
+
+pub fn run(ctx: &Context, args: CheckoutArgs) -> Result<()> {
+    open(ctx, args, ctx.config.attach)
+}
+
+/// [`run`], attaching with `mode`; `graft open` hands commit URLs here.
+pub(super) fn open(ctx: &Context, args: CheckoutArgs, mode: AttachMode) -> Result<()> {
+    let oid = ctx.git.resolve_commit(&args.rev)?;
+    // The short SHA names both the worktree directory and the session.
+    let name = ctx.git.short_id(oid)?;
+
+    let lock = ctx.lock()?;
+    let path = ctx.git.worktree_path(&name);
+    if !path.exists() {
+        progress::spin(&format!("Checking out {name}"), || {
+            ctx.git.create_detached_worktree(&name, oid)
+        })?;
+        if let Err(err) = hooks::post_create(
+            ctx.git.runner(),
+            &ctx.git.root(),
+            &ctx.config.secrets,
+            &name,
+            &path,
+            &ctx.worktree_env(&name, &path),
+        ) {
+            warn!("{err:#}");
+        }
+    }
+    record(ctx, &name, &path, Purpose::Review)?;
+    // Nothing to keep without a branch, so clean up unless asked not to.
+    let then: &[PostExit] = if args.keep { &[] } else { &[PostExit::Rm] };
+    drop(lock);
+    attach(
+        ctx,
+        &name,
+        &path,
+        mode,
+        &[],
+        then,
+        &[CleanupStep::Session, CleanupStep::Worktree],
+    )
+}
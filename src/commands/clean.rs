@@ -0,0 +1,89 @@
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Result, bail};
+use tracing::{info, warn};
+
+use super::Context;
+use super::du::measure;
+use super::rm::remove;
+use crate::cli::CleanArgs;
+use crate::config::NestedRepos;
+use crate::units::{format_size, parse_size};
+
+pub fn run(ctx: &Context, args: CleanArgs) -> Result<()> {
+    let Some(budget) = args
+        .max_total_size
+        .as_deref()
+        .or(ctx.config.max_total_size.as_deref())
+    else {
+        bail!("no size budget: pass --max-total-size or set max_total_size in the config");
+    };
+    let budget = parse_size(budget)?;
+    let _lock = ctx.lock()?;
+
+    let state = ctx.state()?;
+    let skip_nested = ctx.config.nested_repos == NestedRepos::Skip;
+    let mut usage = measure(ctx.git.worktrees()?, skip_nested);
+    let mut total: u64 = usage.iter().map(|(_, size)| size).sum();
+    println!(
+        "Worktrees use {} of a {} budget",
+        format_size(total),
+        format_size(budget)
+    );
+
+    // Least recently used first: graft's own record, else directory mtime.
+    let last_used = |label: &str, path: &std::path::Path| {
+        state
+            .worktrees
+            .get(label)
+            .map(|record| record.last_used.unwrap_or(record.created_at))
+            .or_else(|| {
+                let modified = path.metadata().and_then(|m| m.modified()).ok()?;
+                Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+            })
+            .unwrap_or_default()
+    };
+    usage.sort_by_key(|(worktree, _)| last_used(worktree.label(), &worktree.path));
+
+    let sessions = ctx.zellij.sessions();
+    for (worktree, size) in &usage {
+        if total <= budget {
+            break;
+        }
+        let label = worktree.label();
+        let skip = if ctx.git.lock_reason(label)?.is_some() {
+            Some("locked".to_string())
+        } else if ctx.config.policy(label).protected == Some(true) {
+            Some("protected by policy".to_string())
+        } else if sessions.contains(&state.session(label)) {
+            Some("session is running".to_string())
+        } else {
+            let status = ctx.git.inspect(&worktree.path)?;
+            match (
+                status.dirty_files + status.untracked_files,
+                status.nested_unpushed,
+            ) {
+                (0, 0) => None,
+                (0, n) => Some(format!("{n} unpushed commit(s) in nested repositories")),
+                (n, _) => Some(format!("{n} uncommitted or untracked file(s)")),
+            }
+        };
+        if let Some(reason) = skip {
+            info!("Skipping {} ({})", label, reason);
+            continue;
+        }
+
+        if args.dry_run {
+            println!("Would remove {} ({})", label, format_size(*size));
+        } else {
+            info!("Removing {} ({})", label, format_size(*size));
+            remove(ctx, label, false, false)?;
+        }
+        total = total.saturating_sub(*size);
+    }
+
+    if total > budget {
+        warn!("still over budget: {} in use", format_size(total));
+    }
+    Ok(())
+}
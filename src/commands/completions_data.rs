@@ -0,0 +1,59 @@
+//! Output format, one entry per line: `<kind>\t<name>\t<detail>`.
+//!
+//! - `worktree\t<branch>\t<path>`
+//! - `branch\t<branch>\t<worktree path, or empty>`
+//! - `session\t<session>\t<branch, or empty if not a graft session>`
+//! - `recent\t<branch>\t<path>`, most recently used first
+//! - `alias\t<git alias>\t<what it runs>`, for `graft install-git-aliases`
+//!
+//! e.g. `graft completions-data | awk -F'\t' '$1 == "worktree"' | fzf --with-nth 2`
+
+use std::collections::HashMap;
+use std::io::{self, BufWriter, Write};
+
+use anyhow::Result;
+
+use super::{Context, aliases};
+
+pub fn run(ctx: &Context) -> Result<()> {
+    let worktrees = ctx.git.worktrees()?;
+    let branches = ctx.git.branches()?;
+    let sessions = ctx.zellij.sessions();
+    let state = ctx.state()?;
+
+    let mut paths = HashMap::new();
+    let mut session_branches = HashMap::new();
+    for branch in &branches {
+        session_branches.insert(state.session(branch), branch.as_str());
+    }
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    for worktree in &worktrees {
+        let name = worktree.label();
+        let path = worktree.path.display().to_string();
+        writeln!(out, "worktree\t{}\t{}", name, path)?;
+        paths.insert(name.to_string(), path);
+    }
+    for branch in &branches {
+        let path = paths.get(branch).map(String::as_str).unwrap_or("");
+        writeln!(out, "branch\t{}\t{}", branch, path)?;
+    }
+    for session in &sessions {
+        let branch = state
+            .name_for_session(session)
+            .or_else(|| session_branches.get(session).copied())
+            .unwrap_or("");
+        writeln!(out, "session\t{}\t{}", session, branch)?;
+    }
+    for name in &state.recent {
+        // Only worktrees that are still there.
+        if let Some(path) = paths.get(name) {
+            writeln!(out, "recent\t{}\t{}", name, path)?;
+        }
+    }
+    for (alias, runs) in aliases::installed(ctx.git.runner(), &ctx.git.root()) {
+        writeln!(out, "alias\t{}\t{}", alias, runs)?;
+    }
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use anyhow::Result;
+use toml::Value;
+
+use super::Context;
+use crate::cli::{ConfigCommand, ExportFormat};
+
+const NIX_HEADER: &str = "\
+# graft configuration, e.g. for home-manager:
+#   xdg.configFile.\"graft/config.toml\".source =
+#     (pkgs.formats.toml { }).generate \"graft-config\" (import ./graft.nix);
+";
+
+pub fn run(ctx: &Context, command: ConfigCommand) -> Result<()> {
+    match command {
+        ConfigCommand::Export { format } => {
+            let config = Value::try_from(&ctx.config)?;
+            match format {
+                ExportFormat::Toml => print!("{}", toml::to_string_pretty(&config)?),
+                ExportFormat::Nix => println!("{NIX_HEADER}{}", nix_value(&config, 0)),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Renders a TOML value as a Nix expression, indenting nested sets.
+fn nix_value(value: &Value, depth: usize) -> String {
+    let indent = "  ".repeat(depth + 1);
+    let close = "  ".repeat(depth);
+    match value {
+        Value::String(s) => nix_string(s),
+        Value::Integer(i) => i.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Datetime(d) => nix_string(&d.to_string()),
+        Value::Array(items) if items.is_empty() => "[ ]".to_string(),
+        Value::Array(items) => {
+            let items: Vec<String> = items
+                .iter()
+                .map(|item| format!("{indent}{}\n", nix_value(item, depth + 1)))
+                .collect();
+            format!("[\n{}{close}]", items.concat())
+        }
+        Value::Table(table) if table.is_empty() => "{ }".to_string(),
+        Value::Table(table) => {
+            let entries: Vec<String> = table
+                .iter()
+                .map(|(key, value)| {
+                    format!(
+                        "{indent}{} = {};\n",
+                        nix_key(key),
+                        nix_value(value, depth + 1)
+                    )
+                })
+                .collect();
+            format!("{{\n{}{close}}}", entries.concat())
+        }
+    }
+}
+
+fn nix_key(key: &str) -> String {
+    let mut chars = key.chars();
+    let plain = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '\''));
+    if plain {
+        key.to_string()
+    } else {
+        nix_string(key)
+    }
+}
+
+fn nix_string(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace("${", "\\${")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
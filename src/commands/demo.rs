@@ -0,0 +1,180 @@
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as _, Result};
+
+use super::Context;
+use super::open::record;
+use super::rm::remove;
+use super::{du, ls};
+use crate::cli::{DemoArgs, LsArgs};
+use crate::git::run_ok_in;
+use crate::process::SystemRunner;
+use crate::state::Purpose;
+use crate::zellij;
+
+/// Sandbox repository, deleted on drop unless `--keep` was given.
+struct Sandbox {
+    root: PathBuf,
+    keep: bool,
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        if self.keep {
+            println!("\nThe sandbox is still at {}", self.root.display());
+        } else {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+}
+
+/// Walks through open, ls and rm in a throwaway repository. Sessions are
+/// described rather than started, so the tour never takes over the terminal.
+pub fn run(args: DemoArgs) -> Result<()> {
+    let sandbox = Sandbox {
+        root: env::temp_dir().join(format!("graft-demo-{}", std::process::id())),
+        keep: args.keep,
+    };
+    let repo = sandbox.root.join("project");
+    create_repository(&repo)?;
+    let ctx = Context::new(Some(&repo))?;
+    let list = || {
+        let args = LsArgs {
+            prune_worktrees: false,
+            prune_sessions: false,
+            all: false,
+            tree: false,
+        };
+        ls::run(&ctx, args)
+    };
+
+    step(
+        "A fresh repository",
+        &format!("cd {}", repo.display()),
+        "This is a brand-new git repository with one commit on main. graft keeps \
+         every worktree under .worktrees/ inside it, which the repo ignores.",
+    )?;
+
+    let branch = "feature/login";
+    step(
+        "Open a branch",
+        &format!("graft {branch}"),
+        &format!(
+            "graft creates the branch from main, checks it out in .worktrees/{branch} \
+             and attaches a Zellij session named {}. Run it again later to come \
+             back to the same session. (The demo skips attaching.)",
+            zellij::session_name(branch)
+        ),
+    )?;
+    ctx.git.ensure_branch(branch, false, None)?;
+    let worktree = ctx.git.ensure_worktree(branch)?;
+    record(&ctx, branch, worktree.path(), Purpose::infer(branch))?;
+    fs::write(worktree.path().join("login.rs"), "fn login() {}\n")?;
+
+    step(
+        "Open another one",
+        "graft hotfix/crash",
+        "Each branch gets its own directory, so switching costs nothing: no \
+         stash, no rebuild. The hotfix/ prefix marks its purpose.",
+    )?;
+    ctx.git.ensure_branch("hotfix/crash", false, None)?;
+    let hotfix = ctx.git.ensure_worktree("hotfix/crash")?;
+    record(
+        &ctx,
+        "hotfix/crash",
+        hotfix.path(),
+        Purpose::infer("hotfix/crash"),
+    )?;
+
+    step(
+        "List worktrees",
+        "graft ls",
+        "One line per worktree: `*` when its session is running, then the \
+         branch, its purpose and where it lives.",
+    )?;
+    list()?;
+
+    step(
+        "See what they cost",
+        "graft du",
+        "Worktrees share the object database but not build output; `graft du` \
+         and `graft clean --max-total-size 20G` keep that in check.",
+    )?;
+    du::run(&ctx)?;
+
+    step(
+        "Remove one",
+        "graft rm hotfix/crash --delete-branch",
+        "Kills the session, deletes the directory and, with --delete-branch, \
+         the branch. `graft <branch> -e` does this automatically when the \
+         session exits.",
+    )?;
+    remove(&ctx, "hotfix/crash", true, false)?;
+    list()?;
+
+    println!("\nThat's the loop: `graft <branch>` to work, `graft rm` when done.");
+    println!("See `graft --help` for sync, clean, lock, checkout and more.");
+    Ok(())
+}
+
+fn create_repository(repo: &Path) -> Result<()> {
+    fs::create_dir_all(repo).context("failed to create the sandbox")?;
+    fs::write(repo.join("README.md"), "# Demo project\n")?;
+    fs::write(repo.join(".gitignore"), ".worktrees/\n")?;
+    run_ok_in(&SystemRunner, repo, &["init", "-q"])?;
+    run_ok_in(
+        &SystemRunner,
+        repo,
+        &["symbolic-ref", "HEAD", "refs/heads/main"],
+    )?;
+    run_ok_in(&SystemRunner, repo, &["add", "."])?;
+    run_ok_in(
+        &SystemRunner,
+        repo,
+        &[
+            "-c",
+            "user.name=graft demo",
+            "-c",
+            "user.email=demo@example.invalid",
+            "commit",
+            "-q",
+            "-m",
+            "Initial commit",
+        ],
+    )?;
+    Ok(())
+}
+
+/// Prints one annotated step and, on a terminal, waits for Enter.
+fn step(title: &str, command: &str, explanation: &str) -> Result<()> {
+    println!("\n== {title} ==\n$ {command}");
+    println!("{}", wrap(explanation, 72));
+    if io::stdin().is_terminal() {
+        println!("[Enter to continue]");
+        io::stdin().lock().read_line(&mut String::new())?;
+    }
+    Ok(())
+}
+
+fn wrap(text: &str, width: usize) -> String {
+    let mut lines = vec![String::new()];
+    for word in text.split_whitespace() {
+        let line = lines.last_mut().expect("at least one line");
+        if !line.is_empty() && line.len() + word.len() + 1 > width {
+            lines.push(word.to_string());
+        } else {
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+    }
+    lines
+        .iter()
+        .map(|line| format!("  {line}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,251 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use super::Context;
+use crate::capabilities::{Capabilities, Feature, MIN_GIT, ZELLIJ_NEW_SESSION_FLAG};
+use crate::cli::AttachMode;
+use crate::config::{self, CompileCache, LAYOUT_FILE, REPO_DIR};
+use crate::git::Git;
+use crate::process::SystemRunner;
+
+pub fn run(repo: Option<&Path>) -> Result<()> {
+    let loaded = Context::new(repo);
+    let ctx = loaded.as_ref().ok();
+    let mut problems = 0;
+    let fallback;
+    let capabilities = match ctx {
+        Some(ctx) => &ctx.capabilities,
+        None => {
+            fallback = Capabilities::new("zellij", Arc::new(SystemRunner));
+            &fallback
+        }
+    };
+
+    match capabilities.git() {
+        Some(version) if version >= MIN_GIT => ok(&format!("git {version}")),
+        Some(version) => {
+            problems += 1;
+            fail(
+                &format!("git {version} is too old"),
+                &format!("upgrade to git {MIN_GIT} or newer"),
+            );
+        }
+        None => {
+            problems += 1;
+            fail("git not found on PATH", "install git");
+        }
+    }
+
+    let zellij_bin = capabilities.zellij_bin();
+    match capabilities.zellij() {
+        Some(version) if version >= ZELLIJ_NEW_SESSION_FLAG => ok(&format!(
+            "zellij {version} (supports -n/--new-session-with-layout)"
+        )),
+        Some(version) => {
+            problems += 1;
+            fail(
+                &format!("zellij {version} predates -n/--new-session-with-layout"),
+                &format!(
+                    "upgrade zellij to {ZELLIJ_NEW_SESSION_FLAG} or newer; \
+                     older releases only understand --layout"
+                ),
+            );
+        }
+        None if ctx.is_some_and(|ctx| ctx.config.attach != AttachMode::Zellij) => ok(&format!(
+            "{zellij_bin} not found (not needed without attach = \"zellij\")"
+        )),
+        None => {
+            problems += 1;
+            fail(
+                &format!("{zellij_bin} not found"),
+                "install zellij (https://zellij.dev/documentation/installation), \
+                 set [zellij] bin in the config, or set attach = \"shell\" to work without it",
+            );
+        }
+    }
+
+    // Informational: graft turns these off on its own when their tool is missing.
+    println!();
+    for feature in Feature::ALL {
+        match capabilities.missing(feature) {
+            None => ok(&feature.to_string()),
+            Some(reason) => println!("[off]  {feature} disabled because {reason}"),
+        }
+    }
+    println!();
+
+    let Some(ctx) = ctx else {
+        problems += 1;
+        match loaded {
+            Err(err) if Git::new(repo.unwrap_or(Path::new("."))).is_ok() => fail(
+                &format!("could not load graft's settings: {err:#}"),
+                "fix the config file or GRAFT_* variable named above",
+            ),
+            _ => fail(
+                "not inside a git repository",
+                "run graft doctor from the repository you want to manage",
+            ),
+        }
+        return finish(problems);
+    };
+    let git = &ctx.git;
+
+    let worktree_dir = git.worktree_dir();
+    if worktree_dir.is_dir() {
+        ok(&format!("worktree directory {}", worktree_dir.display()));
+    } else if worktree_dir.exists() {
+        problems += 1;
+        fail(
+            &format!("{} exists but is not a directory", worktree_dir.display()),
+            "move the file out of the way; graft creates worktrees there",
+        );
+    } else {
+        ok(&format!(
+            "no worktrees yet ({} is created on first open)",
+            worktree_dir.display()
+        ));
+    }
+
+    match git.worktree_dir_ignored()? {
+        None => ok("worktrees live outside the repository"),
+        Some(true) => ok(&format!("{} is ignored by git", worktree_dir.display())),
+        Some(false) => {
+            problems += 1;
+            fail(
+                &format!(
+                    "{} is not ignored; worktrees show up in git status",
+                    worktree_dir.display()
+                ),
+                "run `graft init`, or add it to .gitignore yourself",
+            );
+        }
+    }
+
+    let root = git.root();
+    let mut layouts: Vec<(String, &Path)> = ctx
+        .config
+        .policy
+        .iter()
+        .filter_map(|(pattern, rule)| {
+            let layout = rule.layout.as_deref()?;
+            Some((format!("[policy.\"{pattern}\"] layout"), layout))
+        })
+        .collect();
+    for (purpose, layout) in &ctx.config.layouts {
+        layouts.push((format!("[layouts] {purpose}"), layout));
+    }
+    if let Some(layout) = &ctx.config.layout {
+        layouts.push(("layout".to_string(), layout));
+    }
+    for (key, layout) in &layouts {
+        let path = config::layout_file(&root, &root, layout);
+        if path.is_file() {
+            ok(&format!("{key}: {}", path.display()));
+        } else {
+            problems += 1;
+            fail(
+                &format!("{key} is {}, which does not exist", layout.display()),
+                "add the layout to .graft/layouts/ or fix the setting; \
+                 sessions start with zellij's default layout meanwhile",
+            );
+        }
+    }
+    if ctx.config.layout.is_none() {
+        let path = root.join(REPO_DIR).join(LAYOUT_FILE);
+        if path.is_file() {
+            ok(&format!("layout {}", path.display()));
+        } else {
+            ok(&format!(
+                "no {} (new sessions use zellij's default layout; `graft init` writes one)",
+                path.display()
+            ));
+        }
+    }
+
+    let stale = git.stale_worktrees()?;
+    if stale.is_empty() {
+        ok("no stale worktree administrative files");
+    } else {
+        problems += 1;
+        fail(
+            &format!("stale worktree metadata: {}", stale.join(", ")),
+            "run `graft ls --prune-worktrees` (or `git worktree prune`)",
+        );
+    }
+
+    let worktrees = git.worktrees()?;
+    let mut damaged = 0;
+    for worktree in &worktrees {
+        for damage in git.worktree_damage(worktree)? {
+            damaged += 1;
+            fail(
+                &format!("{}: {damage}", worktree.path.display()),
+                "run `graft repair`",
+            );
+        }
+    }
+    for (name, record) in &ctx.state()?.worktrees {
+        if let Some(worktree) = worktrees.iter().find(|w| w.path == record.path)
+            && worktree.label() != name
+        {
+            damaged += 1;
+            fail(
+                &format!(
+                    "{} has {} checked out, but graft recorded it for {name}",
+                    record.path.display(),
+                    worktree.label()
+                ),
+                &format!(
+                    "check {name} out there again, or run `graft repair` to keep it as {}",
+                    worktree.label()
+                ),
+            );
+        }
+    }
+    if damaged == 0 {
+        ok("worktree links, HEADs and branches match graft's records");
+    }
+    problems += damaged;
+
+    if let Some(cache) = ctx.config.compile_cache {
+        let feature = match cache {
+            CompileCache::Sccache => Feature::Sccache,
+            CompileCache::Ccache => Feature::Ccache,
+        };
+        match capabilities.missing(feature) {
+            None => ok(&format!("compile cache: {feature}")),
+            Some(reason) => {
+                problems += 1;
+                fail(
+                    &format!("compile_cache is {feature}, but {reason}"),
+                    &format!(
+                        "install {feature} or unset compile_cache; builds in graft \
+                         sessions go through it"
+                    ),
+                );
+            }
+        }
+    }
+
+    finish(problems)
+}
+
+fn finish(problems: usize) -> Result<()> {
+    if problems == 0 {
+        println!("\nEverything looks good.");
+    } else {
+        println!("\n{problems} problem(s) found.");
+    }
+    Ok(())
+}
+
+fn ok(message: &str) {
+    println!("[ok]   {message}");
+}
+
+fn fail(message: &str, fix: &str) {
+    println!("[fail] {message}");
+    println!("       fix: {fix}");
+}
@@ -0,0 +1,66 @@
+use std::cmp::Reverse;
+use std::fs;
+use std::path::Path;
+use std::thread;
+
+use anyhow::Result;
+
+use super::Context;
+use crate::config::NestedRepos;
+use crate::git::WorktreeInfo;
+use crate::units::format_size;
+
+pub fn run(ctx: &Context) -> Result<()> {
+    let skip_nested = ctx.config.nested_repos == NestedRepos::Skip;
+    let mut usage = measure(ctx.git.worktrees()?, skip_nested);
+    usage.sort_by_key(|(_, size)| Reverse(*size));
+
+    let total: u64 = usage.iter().map(|(_, size)| size).sum();
+    for (worktree, size) in &usage {
+        println!(
+            "{:>8}  {:<30} {}",
+            format_size(*size),
+            worktree.label(),
+            worktree.path.display()
+        );
+    }
+    println!("{:>8}  total", format_size(total));
+    Ok(())
+}
+
+/// Disk usage of each existing worktree, walking them in parallel. With
+/// `skip_nested`, repositories nested inside don't count.
+pub fn measure(worktrees: Vec<WorktreeInfo>, skip_nested: bool) -> Vec<(WorktreeInfo, u64)> {
+    thread::scope(|s| {
+        let handles: Vec<_> = worktrees
+            .into_iter()
+            .filter(|worktree| worktree.path.exists())
+            .map(|worktree| {
+                s.spawn(move || {
+                    let size = dir_size(&worktree.path, skip_nested);
+                    (worktree, size)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("disk usage walk panicked"))
+            .collect()
+    })
+}
+
+/// Apparent size of everything below `path`, without following symlinks.
+fn dir_size(path: &Path, skip_nested: bool) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(meta) if meta.is_dir() && skip_nested && entry.path().join(".git").exists() => 0,
+            Ok(meta) if meta.is_dir() => dir_size(&entry.path(), skip_nested),
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
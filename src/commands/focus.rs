@@ -0,0 +1,39 @@
+use anyhow::{Result, bail};
+
+use super::Context;
+use super::open::attach;
+use crate::capabilities::Feature;
+use crate::cli::{AttachMode, FocusArgs};
+use crate::zellij;
+
+/// Switches the current Zellij client to the branch's session. Outside
+/// Zellij there is no client to move, so it attaches like `graft attach`.
+pub fn run(ctx: &Context, args: FocusArgs) -> Result<()> {
+    ctx.capabilities.require(Feature::Sessions)?;
+    let branch = args.branch;
+    let session = ctx.state()?.session(&branch);
+    let running = ctx
+        .zellij
+        .session_list()
+        .iter()
+        .any(|info| info.name == session && !info.exited);
+    if !running {
+        bail!("{branch} has no running session; start one with `graft {branch}`");
+    }
+    ctx.touch(&branch)?;
+    match zellij::current_session() {
+        Some(current) if current == session => println!("Already in {session}"),
+        Some(_) => ctx.zellij.switch_session(&session)?,
+        None => {
+            let path = ctx
+                .git
+                .worktrees()?
+                .into_iter()
+                .find(|worktree| worktree.label() == branch)
+                .map(|worktree| worktree.path)
+                .unwrap_or_else(|| ctx.git.worktree_path(&branch));
+            attach(ctx, &branch, &path, AttachMode::Zellij, &[], &[], &[])?;
+        }
+    }
+    Ok(())
+}
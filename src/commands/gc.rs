@@ -0,0 +1,103 @@
+use std::fs;
+
+use anyhow::Result;
+use tracing::{debug, info, warn};
+
+use super::Context;
+use super::ls::prune_stale_sessions;
+use super::rm::remove;
+use super::summary::Summary;
+use crate::cli::GcArgs;
+use crate::state;
+use crate::units::{after, parse_duration};
+
+pub fn run(ctx: &Context, args: GcArgs) -> Result<()> {
+    let _lock = ctx.lock()?;
+    for name in ctx.git.prune_worktrees()? {
+        info!("Pruned worktree {}", name);
+    }
+    let mut summary = Summary::default();
+    remove_expired(ctx, &mut summary)?;
+    let worktrees = ctx.git.worktrees()?;
+    prune_stale_sessions(ctx, &worktrees, &ctx.zellij.sessions());
+
+    if args.empty_trash {
+        let mut state = ctx.state()?;
+        for entry in state.trash.drain(..) {
+            info!("Deleting trashed {}", entry.name);
+            if !entry.trash_path.exists() {
+                continue;
+            }
+            // The path comes from the state file; never delete outside the worktrees.
+            if ctx.git.within_worktree_dir(&entry.trash_path) {
+                fs::remove_dir_all(&entry.trash_path)?;
+            } else {
+                warn!(
+                    "not deleting {}: it is outside {}",
+                    entry.trash_path.display(),
+                    ctx.git.worktree_dir().display()
+                );
+            }
+        }
+        state.save()?;
+        // Catch anything left behind without a state entry.
+        let trash_dir = ctx.git.trash_dir();
+        if trash_dir.exists() {
+            fs::remove_dir_all(trash_dir)?;
+        }
+    }
+    summary.finish(ctx.config.bulk_failure)
+}
+
+/// Removes worktrees whose `--ttl` ran out, along with their sessions;
+/// without one, a `[policy]` TTL counts from the last use. Locked and
+/// protected worktrees and ones with local changes are kept.
+fn remove_expired(ctx: &Context, summary: &mut Summary) -> Result<()> {
+    let now = state::now();
+    let mut expired = Vec::new();
+    for (name, record) in &ctx.state()?.worktrees {
+        let policy = ctx.config.policy(name);
+        let expires_at = match (record.expires_at, &policy.ttl) {
+            (Some(at), _) => Some(at),
+            (None, Some(ttl)) => {
+                let start = record.last_used.unwrap_or(record.created_at);
+                Some(after(start, parse_duration(ttl)?)?)
+            }
+            (None, None) => None,
+        };
+        if expires_at.is_none_or(|at| at > now) {
+            continue;
+        }
+        if policy.protected == Some(true) {
+            debug!("{name} expired but its policy protects it");
+            continue;
+        }
+        expired.push(name.clone());
+    }
+    for name in expired {
+        let path = ctx.git.worktree_path(&name);
+        if ctx.git.lock_reason(&name)?.is_some() {
+            summary.skipped(&name, "expired but locked");
+            continue;
+        }
+        if path.exists() {
+            let status = match ctx.git.inspect(&path) {
+                Ok(status) => status,
+                Err(err) => {
+                    summary.failed(&name, &err);
+                    continue;
+                }
+            };
+            if status.dirty_files + status.untracked_files + status.nested_unpushed > 0 {
+                summary.skipped(&name, "expired but has local changes");
+                continue;
+            }
+        }
+        info!("Removing expired {}", name);
+        match remove(ctx, &name, false, ctx.config.trash) {
+            Ok(()) => summary.done(&name, "removed, expired"),
+            Err(err) => summary.failed(&name, &err),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use tracing::info;
+
+use super::Context;
+use crate::config::{Config, LAYOUT_FILE, REPO_CONFIG_FILE, REPO_DIR};
+use crate::hooks::Hook;
+
+const CONFIG_HEADER: &str = r#"# graft settings for this repository. Everything below is a default;
+# uncomment what you want to change. The user config
+# (~/.config/graft/config.toml) applies underneath this file; GRAFT_*
+# environment variables (GRAFT_REMOTE, GRAFT_MUX, ...) and command-line
+# flags override it.
+
+# Unset unless you set them, for example:
+# fetch_remote = "upstream"
+# push_remote = "origin"
+# remote_branch = "users/{user}/{branch}"
+# worktree_root = "~/worktrees/{repo}/{branch}"
+# layout = "tools/review.kdl"
+# default_command = "nvim ."
+# max_total_size = "20G"
+# compile_cache = "sccache"
+"#;
+
+const EXAMPLE_LAYOUT: &str = r#"// Layout for new graft sessions in this repository, used unless a command
+// is given (`graft <branch> -- <cmd>` or default_command). As written it
+// matches zellij's default; see https://zellij.dev/documentation/layouts
+layout {
+    pane size=1 borderless=true {
+        plugin location="zellij:tab-bar"
+    }
+    pane
+    // An editor next to the shell:
+    // pane split_direction="vertical" {
+    //     pane command="nvim" {
+    //         args "."
+    //     }
+    //     pane
+    // }
+    pane size=2 borderless=true {
+        plugin location="zellij:status-bar"
+    }
+}
+"#;
+
+const POST_CREATE_SAMPLE: &str = r#"#!/bin/sh
+# Runs in a new worktree right after graft checks it out. Rename to
+# `post-create` to enable. GRAFT_BRANCH, GRAFT_WORKTREE and GRAFT_ROOT
+# are set. A failure is reported but keeps the worktree.
+
+# Bring over untracked setup from the main checkout, then install, e.g.:
+# cp "$GRAFT_ROOT/.env" .env 2>/dev/null
+# npm ci
+"#;
+
+const PRE_REMOVE_SAMPLE: &str = r#"#!/bin/sh
+# Runs in a worktree before graft removes it. Rename to `pre-remove` to
+# enable. Exiting non-zero stops the removal.
+
+# Refuse while there are unpushed commits:
+# if [ -n "$(git log @{upstream}.. 2>/dev/null)" ]; then
+#     echo "$GRAFT_BRANCH has unpushed commits" >&2
+#     exit 1
+# fi
+"#;
+
+pub fn run(ctx: &Context) -> Result<()> {
+    let git = &ctx.git;
+    let worktree_dir = git.worktree_dir();
+    if git.exclude_worktree_dir()? {
+        info!("Added {} to .git/info/exclude", worktree_dir.display());
+    } else if git.worktree_dir_ignored()?.is_some() {
+        info!("{} is already ignored", worktree_dir.display());
+    } else {
+        info!(
+            "Worktrees live outside the repository, in {}",
+            worktree_dir.display()
+        );
+    }
+
+    let root = git.root();
+    let defaults = toml::to_string_pretty(&Config::default())?;
+    let mut config = CONFIG_HEADER.to_string();
+    for line in defaults.lines() {
+        config.push('\n');
+        if !line.is_empty() {
+            config.push_str("# ");
+            config.push_str(line);
+        }
+    }
+    config.push('\n');
+    write_new(&root.join(REPO_CONFIG_FILE), &config, false)?;
+
+    let dir = root.join(REPO_DIR);
+    write_new(&dir.join(LAYOUT_FILE), EXAMPLE_LAYOUT, false)?;
+    for hook in Hook::ALL {
+        let sample = format!("{}.sample", hook.name());
+        let body = match hook {
+            Hook::PostCreate => POST_CREATE_SAMPLE,
+            Hook::PreRemove => PRE_REMOVE_SAMPLE,
+        };
+        write_new(&dir.join("hooks").join(sample), body, true)?;
+    }
+    Ok(())
+}
+
+/// Writes `contents` to `path` unless something is already there.
+fn write_new(path: &Path, contents: &str, executable: bool) -> Result<()> {
+    if path.exists() {
+        info!("Kept the existing {}", path.display());
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)?;
+    #[cfg(unix)]
+    if executable {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    }
+    #[cfg(not(unix))]
+    let _ = executable;
+    info!("Wrote {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,20 @@
+use anyhow::Result;
+use tracing::info;
+
+use super::Context;
+use crate::cli::{LockArgs, UnlockArgs};
+
+pub fn lock(ctx: &Context, args: LockArgs) -> Result<()> {
+    let _lock = ctx.lock()?;
+    ctx.git
+        .lock_worktree(&args.ticket, args.reason.as_deref())?;
+    info!("Locked {}", args.ticket);
+    Ok(())
+}
+
+pub fn unlock(ctx: &Context, args: UnlockArgs) -> Result<()> {
+    let _lock = ctx.lock()?;
+    ctx.git.unlock_worktree(&args.ticket)?;
+    info!("Unlocked {}", args.ticket);
+    Ok(())
+}
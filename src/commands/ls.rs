@@ -0,0 +1,364 @@
+use std::collections::{BTreeMap, HashMap};
+use std::thread;
+
+use anyhow::Result;
+use tracing::warn;
+
+use super::Context;
+use crate::capabilities::Feature;
+use crate::cli::LsArgs;
+use crate::git::WorktreeInfo;
+use crate::registry::Registry;
+use crate::state;
+use crate::units::format_age;
+use crate::zellij::{self, SessionInfo};
+
+/// Room for the tip commit's subject; longer ones are cut.
+const SUBJECT_WIDTH: usize = 40;
+
+pub fn run(ctx: &Context, args: LsArgs) -> Result<()> {
+    let git = &ctx.git;
+    // Listing stays lock-free; only pruning changes anything.
+    if args.prune_worktrees {
+        let _lock = ctx.lock()?;
+        for name in git.prune_worktrees()? {
+            println!("Pruned worktree {}", name);
+        }
+    }
+
+    let worktrees = git.worktrees()?;
+    let zellij_sessions = ctx.zellij.session_list();
+
+    if args.prune_sessions {
+        prune_stale_sessions(ctx, &worktrees, &session_names(&zellij_sessions));
+    }
+
+    print_worktrees(ctx, &worktrees, &zellij_sessions, "", args.tree)?;
+
+    if ctx.config.claims && git.has_remote() {
+        match git.foreign_claims() {
+            Ok(claims) if !claims.is_empty() => {
+                println!("\nClaimed by others:");
+                for (branch, owner) in claims {
+                    println!("  {:<30} {}", branch, owner);
+                }
+            }
+            Ok(_) => {}
+            Err(err) => warn!("could not fetch claims: {err:#}"),
+        }
+    }
+    Ok(())
+}
+
+/// `ls --all`: every registered repository, grouped under its root.
+pub fn run_all(args: LsArgs) -> Result<()> {
+    let registry = Registry::load()?;
+    if registry.repos.is_empty() {
+        println!("No repositories registered; add one with `graft repo add`.");
+        return Ok(());
+    }
+
+    let mut sessions = None;
+    for (i, root) in registry.repos.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{}", root.display());
+        let ctx = match Context::new(Some(root)) {
+            Ok(ctx) => ctx,
+            Err(err) => {
+                println!("  unavailable: {}", err.root_cause());
+                continue;
+            }
+        };
+        if args.prune_worktrees {
+            let _lock = ctx.lock()?;
+            for name in ctx.git.prune_worktrees()? {
+                println!("  Pruned worktree {}", name);
+            }
+        }
+        let worktrees = ctx.git.worktrees()?;
+        // Sessions are global to the zellij server; ask once.
+        let sessions = sessions.get_or_insert_with(|| ctx.zellij.session_list());
+        if args.prune_sessions {
+            prune_stale_sessions(&ctx, &worktrees, &session_names(sessions));
+        }
+        print_worktrees(&ctx, &worktrees, sessions, "  ", args.tree)?;
+    }
+    Ok(())
+}
+
+fn session_names(sessions: &[SessionInfo]) -> Vec<String> {
+    sessions
+        .iter()
+        .map(|session| session.name.clone())
+        .collect()
+}
+
+fn print_worktrees(
+    ctx: &Context,
+    worktrees: &[WorktreeInfo],
+    zellij_sessions: &[SessionInfo],
+    indent: &str,
+    tree: bool,
+) -> Result<()> {
+    let git = &ctx.git;
+    let state = ctx.state()?;
+    let now = state::now();
+    let sessions: HashMap<String, &SessionInfo> = worktrees
+        .iter()
+        .filter_map(|worktree| {
+            let name = state.session(worktree.label());
+            let info = zellij_sessions.iter().find(|s| s.name == name)?;
+            Some((name, info))
+        })
+        .collect();
+    let clients = client_counts(ctx, &sessions);
+    let mut rows = Vec::new();
+    for (index, worktree) in numbered(worktrees).into_iter().enumerate() {
+        let branch = worktree.label();
+        let session = state.session(branch);
+        let info = sessions.get(&session);
+        let marker = match info {
+            Some(info) if !info.exited => "*",
+            _ => " ",
+        };
+        let session_state = match info {
+            None => String::new(),
+            Some(info) if info.exited => "exited".to_string(),
+            Some(info) => {
+                let status = match clients.get(&session) {
+                    Some(0) => "detached",
+                    Some(_) => "attached",
+                    None => "running",
+                };
+                match info.age {
+                    Some(age) => format!("{status} {}", format_age(age)),
+                    None => status.to_string(),
+                }
+            }
+        };
+        let purpose = state
+            .worktrees
+            .get(branch)
+            .map(|record| record.purpose.to_string())
+            .unwrap_or_default();
+        let lock = match git.lock_reason(branch)? {
+            Some(reason) if reason.is_empty() => " [locked]".to_string(),
+            Some(reason) => format!(" [locked: {reason}]"),
+            None => String::new(),
+        };
+        let expiry = match state.worktrees.get(branch).map(|r| r.expires_at) {
+            Some(Some(at)) if at <= now => " [expired]".to_string(),
+            Some(Some(at)) => format!(" [expires in {}]", format_age(at - now)),
+            Some(None) => String::new(),
+            // Added with `git worktree add`; `graft adopt` takes it over.
+            None => " [not graft's]".to_string(),
+        };
+        let issue = match state.worktrees.get(branch).and_then(|r| r.issue.as_ref()) {
+            Some(url) => format!(" {url}"),
+            None => String::new(),
+        };
+        let (sync, commit) = match git.tip(&worktree.path) {
+            Ok(tip) => {
+                let sync = sync_label(tip.upstream).unwrap_or_default();
+                let age = format_age(now.saturating_sub(tip.time));
+                (
+                    sync,
+                    format!("{:>4} {}", age, truncate(&tip.subject, SUBJECT_WIDTH)),
+                )
+            }
+            Err(_) => (String::new(), String::new()),
+        };
+        let rest = format!(
+            "{:<8} {:<12} {:<9} {:<width$} {}{}{}{}",
+            purpose,
+            session_state,
+            sync,
+            commit,
+            worktree.path.display(),
+            lock,
+            expiry,
+            issue,
+            width = SUBJECT_WIDTH + 5
+        );
+        rows.push((
+            branch,
+            Row {
+                index: index + 1,
+                marker,
+                rest,
+            },
+        ));
+    }
+
+    if tree {
+        let mut root = Node::default();
+        for (branch, row) in &rows {
+            root.insert(branch, row);
+        }
+        root.print(indent, 0);
+    } else {
+        for (branch, row) in &rows {
+            println!(
+                "{}{:>2} {} {:<30} {}",
+                indent, row.index, row.marker, branch, row.rest
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Worktrees in the order `ls` numbers them, from 1: by name.
+pub(super) fn numbered(worktrees: &[WorktreeInfo]) -> Vec<&WorktreeInfo> {
+    let mut sorted: Vec<&WorktreeInfo> = worktrees.iter().collect();
+    sorted.sort_by(|a, b| a.label().cmp(b.label()));
+    sorted
+}
+
+/// `(ahead, behind)` versus the upstream as `=`, `+2`, `-1` or `+2 -1`;
+/// `None` without an upstream.
+pub(super) fn sync_label(upstream: Option<(usize, usize)>) -> Option<String> {
+    Some(match upstream? {
+        (0, 0) => "=".to_string(),
+        (ahead, 0) => format!("+{ahead}"),
+        (0, behind) => format!("-{behind}"),
+        (ahead, behind) => format!("+{ahead} -{behind}"),
+    })
+}
+
+/// `subject` cut to `width` characters, ending in `…` when shortened.
+fn truncate(subject: &str, width: usize) -> String {
+    if subject.chars().count() <= width {
+        return subject.to_string();
+    }
+    let mut short: String = subject.chars().take(width - 1).collect();
+    short.push('…');
+    short
+}
+
+/// Attached clients per running session, asked of every session at once;
+/// empty when this zellij can't tell.
+fn client_counts(
+    ctx: &Context,
+    sessions: &HashMap<String, &SessionInfo>,
+) -> HashMap<String, usize> {
+    if ctx.capabilities.missing(Feature::SessionClients).is_some() {
+        return HashMap::new();
+    }
+    let zellij = &ctx.zellij;
+    thread::scope(|s| {
+        let handles: Vec<_> = sessions
+            .values()
+            .filter(|info| !info.exited)
+            .map(|info| {
+                s.spawn(|| {
+                    let count = zellij.client_count(&info.name);
+                    count.map(|count| (info.name.clone(), count))
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .filter_map(|handle| handle.join().expect("client probe panicked"))
+            .collect()
+    })
+}
+
+/// An `ls` line without its name: the number `graft <n>` opens it by, the
+/// session marker and everything after the name column.
+struct Row {
+    index: usize,
+    marker: &'static str,
+    rest: String,
+}
+
+/// `ls --tree`: worktree names split on `/`.
+#[derive(Default)]
+struct Node<'a> {
+    row: Option<&'a Row>,
+    children: BTreeMap<&'a str, Node<'a>>,
+}
+
+impl<'a> Node<'a> {
+    fn insert(&mut self, name: &'a str, row: &'a Row) {
+        let mut node = self;
+        for part in name.split('/') {
+            node = node.children.entry(part).or_default();
+        }
+        node.row = Some(row);
+    }
+
+    fn count(&self) -> usize {
+        usize::from(self.row.is_some()) + self.children.values().map(Node::count).sum::<usize>()
+    }
+
+    /// Prints the children, nesting groups two spaces deeper. A prefix
+    /// holding a single entry is folded into it instead of getting a header.
+    fn print(&self, indent: &str, depth: usize) {
+        let pad = "  ".repeat(depth);
+        for (part, mut child) in &self.children {
+            let mut name = part.to_string();
+            while child.row.is_none() && child.children.len() == 1 {
+                let (part, only) = child.children.iter().next().expect("one child");
+                name = format!("{name}/{part}");
+                child = only;
+            }
+            if let Some(row) = child.row {
+                println!(
+                    "{}{:>2} {} {}{:<width$} {}",
+                    indent,
+                    row.index,
+                    row.marker,
+                    pad,
+                    name,
+                    row.rest,
+                    width = 30usize.saturating_sub(pad.len())
+                );
+            }
+            if !child.children.is_empty() {
+                let count: usize = child.children.values().map(Node::count).sum();
+                println!("{}     {}{}/ ({})", indent, pad, name, count);
+                child.print(indent, depth + 1);
+            }
+        }
+    }
+}
+
+/// Kills this repository's graft sessions whose worktree directory no
+/// longer exists. Sessions are matched to worktrees through the state
+/// records, not by their names, which nested branches (`feature/foo`) and
+/// shortened names don't map back from. A session is this repository's
+/// when its records name it or it was started in the main checkout or the
+/// worktree directory; other repositories' sessions are left alone, and so
+/// are adopted ones, which don't carry graft's prefix.
+pub fn prune_stale_sessions(ctx: &Context, worktrees: &[WorktreeInfo], sessions: &[String]) {
+    let state = match ctx.state() {
+        Ok(state) => state,
+        Err(err) => return warn!("not pruning sessions: {err:#}"),
+    };
+    let live: Vec<String> = worktrees
+        .iter()
+        .filter(|w| w.path.exists())
+        .map(|w| state.session(w.label()))
+        .collect();
+    let recorded: Vec<String> = state
+        .worktrees
+        .keys()
+        .map(|name| state.session(name))
+        .collect();
+    let dirs = [ctx.git.root(), ctx.git.worktree_dir()];
+    let ours = |session: &String| {
+        recorded.contains(session)
+            || zellij::session_cwd(session)
+                .is_some_and(|cwd| dirs.iter().any(|dir| cwd.starts_with(dir)))
+    };
+    for session in sessions {
+        if zellij::is_graft_session(session) && !live.contains(session) && ours(session) {
+            println!("Killing stale session {}", session);
+            if let Err(err) = ctx.zellij.kill_session(session) {
+                warn!("{err:#}");
+            }
+        }
+    }
+}
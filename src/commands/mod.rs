@@ -0,0 +1,422 @@
+//! One module per subcommand. Each exposes `run(ctx, args)`; adding a command
+//! means adding a variant to `cli::Command`, a module here and an arm in
+//! [`dispatch`].
+
+mod adopt;
+mod aliases;
+mod archive;
+mod attach;
+mod banner;
+mod change;
+mod checkout;
+mod clean;
+mod completions_data;
+mod config;
+mod demo;
+mod doctor;
+mod du;
+mod focus;
+mod gc;
+mod init;
+mod lock;
+mod ls;
+mod mv;
+mod note;
+mod open;
+mod prompt;
+mod protect;
+mod recent;
+mod repair;
+mod repo;
+mod report;
+mod repos;
+mod rm;
+mod serve;
+mod stats;
+mod status;
+mod summary;
+mod sync;
+mod take;
+mod target;
+mod undo;
+mod watch;
+mod zellij_pipe;
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context as _, Result, bail};
+use tracing::warn;
+
+use crate::capabilities::Capabilities;
+use crate::cli::{Cli, Command, OpenArgs};
+use crate::config::{CompileCache, Config, SessionStrategy};
+use crate::git::{Git, Network};
+use crate::process::{ProcessRunner, SystemRunner};
+use crate::state::{self, State, StateLock};
+use crate::timesheet;
+use crate::timings;
+use crate::units::parse_duration;
+use crate::zellij::{self, Zellij};
+
+/// State shared by every command that operates on a repository.
+pub struct Context {
+    pub config: Config,
+    pub git: Git,
+    pub zellij: Zellij,
+    pub capabilities: Capabilities,
+    /// Variables for sessions, shells and hooks started by this run
+    /// (`--env`); see [`Context::session_env`].
+    pub env: Vec<(String, String)>,
+    /// Layout for new sessions from `--layout`, ahead of `[policy]` rules
+    /// and the `layout` config key.
+    pub layout: Option<PathBuf>,
+    /// The directory `-C` names, else the current one; relative worktree
+    /// paths given as arguments start here.
+    pub dir: PathBuf,
+}
+
+impl Context {
+    pub fn new(repo: Option<&Path>) -> Result<Self> {
+        Self::with_runner(repo, Arc::new(SystemRunner))
+    }
+
+    /// Like [`Context::new`], with git and zellij run through `runner`.
+    pub fn with_runner(repo: Option<&Path>, runner: Arc<dyn ProcessRunner>) -> Result<Self> {
+        let dir = repo.unwrap_or(Path::new("."));
+        let git = Git::new(dir)?;
+        let config = Config::load(&git.root())?;
+        Self::assemble(dir, git, config, runner)
+    }
+
+    /// Uses `config` as is, ignoring the config files and `GRAFT_*`
+    /// variables; for tests.
+    pub fn with_config(
+        repo: &Path,
+        config: Config,
+        runner: Arc<dyn ProcessRunner>,
+    ) -> Result<Self> {
+        Self::assemble(repo, Git::new(repo)?, config, runner)
+    }
+
+    fn assemble(
+        dir: &Path,
+        mut git: Git,
+        config: Config,
+        runner: Arc<dyn ProcessRunner>,
+    ) -> Result<Self> {
+        git.set_runner(runner.clone());
+        git.set_remotes(
+            config.fetch_remote.as_deref(),
+            config.push_remote.as_deref(),
+        );
+        git.set_worktree_root(config.worktree_root.as_deref())?;
+        git.set_remote_branch(config.remote_branch.as_deref())?;
+        let timeout = |key: &str, value: &str| {
+            parse_duration(value)
+                .map(Duration::from_secs)
+                .with_context(|| format!("invalid {key}"))
+        };
+        for (pattern, rule) in &config.policy {
+            if let Some(ttl) = &rule.ttl {
+                parse_duration(ttl)
+                    .with_context(|| format!("invalid ttl in [policy.\"{pattern}\"]"))?;
+            }
+        }
+        git.set_network(Network {
+            lookup_timeout: timeout("remote_timeout", &config.remote_timeout)?,
+            fetch_timeout: timeout("fetch_timeout", &config.fetch_timeout)?,
+            retries: config.remote_retries,
+        });
+        git.set_nested_repos(config.nested_repos);
+        git.set_direnv(config.direnv);
+        git.set_submodules(config.submodules);
+        if !config.cache_links.keys().all(|path| inside(path)) {
+            bail!("[cache_links] paths must be relative paths inside the worktree");
+        }
+        git.set_cache_links(config.cache_links.clone());
+        if !config.sparse.values().flatten().all(|path| inside(path)) {
+            bail!("[sparse] paths must be relative paths inside the worktree");
+        }
+        if let Some(key) = config
+            .env
+            .keys()
+            .find(|key| key.is_empty() || key.contains(['=', '\0']))
+        {
+            bail!("[env] '{key}' is not a valid variable name");
+        }
+        if !config.secrets.files.iter().all(|file| inside(file)) {
+            bail!("[secrets] files must be relative paths inside the worktree");
+        }
+        if config.zellij.session_prefix.is_empty() {
+            bail!("[zellij] session_prefix can't be empty; every session would look like graft's");
+        }
+        let root = git.root();
+        let repo = root.file_name().unwrap_or_default().to_string_lossy();
+        zellij::set_session_prefix(&config.zellij.session_prefix.replace("{repo}", &repo));
+        let zellij = Zellij::new(&config.zellij, runner.clone());
+        let capabilities = Capabilities::new(&config.zellij.bin, runner);
+        Ok(Context {
+            config,
+            git,
+            zellij,
+            capabilities,
+            env: Vec::new(),
+            layout: None,
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    pub fn with_env(mut self, env: Vec<(String, String)>) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// `--submodules`: checks out submodules in new worktrees whatever the
+    /// `submodules` config key says.
+    pub fn with_submodules(mut self, submodules: bool) -> Self {
+        if submodules {
+            self.git.set_submodules(true);
+        }
+        self
+    }
+
+    /// `--layout`: new sessions start with this layout, whatever the config
+    /// says.
+    pub fn with_layout(mut self, layout: Option<PathBuf>) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// `--tab`: worktrees entered with Zellij open as tabs of the current
+    /// session.
+    pub fn with_tab(mut self, tab: bool) -> Self {
+        if tab {
+            self.config.session_strategy = SessionStrategy::Tabs;
+        }
+        self
+    }
+
+    /// `--sparse`: new worktrees check out only these directories. A name
+    /// from `[sparse]` stands for its directories.
+    pub fn with_sparse(mut self, sparse: &[String]) -> Result<Self> {
+        let mut dirs = Vec::new();
+        for entry in sparse {
+            match self.config.sparse.get(entry) {
+                Some(profile) => dirs.extend(profile.iter().cloned()),
+                None if inside(Path::new(entry)) => dirs.push(PathBuf::from(entry)),
+                None => bail!(
+                    "--sparse {entry} is neither a [sparse] profile nor a directory inside the worktree"
+                ),
+            }
+        }
+        dirs.sort();
+        dirs.dedup();
+        self.git.set_sparse(dirs);
+        Ok(self)
+    }
+
+    /// What sessions, shells and hooks get on top of graft's own
+    /// environment: the `compile_cache` variables, then `--env`.
+    pub fn session_env(&self) -> Vec<(String, String)> {
+        let mut env = self.cache_env();
+        env.extend(self.env.iter().cloned());
+        env
+    }
+
+    /// [`Context::session_env`] for the worktree of `branch` at `worktree`,
+    /// with the `[env]` templates expanded before `--env`.
+    pub fn worktree_env(&self, branch: &str, worktree: &Path) -> Vec<(String, String)> {
+        let mut env = self.cache_env();
+        let root = self.git.root();
+        let repo = root.file_name().unwrap_or_default().to_string_lossy();
+        let slug = branch_slug(branch);
+        for (key, template) in &self.config.env {
+            let value = template
+                .replace("{branch_slug}", &slug)
+                .replace("{branch}", branch)
+                .replace("{repo}", &repo)
+                .replace("{worktree}", &worktree.to_string_lossy());
+            env.push((key.clone(), value));
+        }
+        env.extend(self.env.iter().cloned());
+        env
+    }
+
+    /// The variables that route builds through `compile_cache`.
+    fn cache_env(&self) -> Vec<(String, String)> {
+        let mut env = Vec::new();
+        if let Some(cache) = self.config.compile_cache {
+            let root = self.git.root();
+            let dir = match &self.config.compile_cache_dir {
+                Some(dir) => root.join(dir),
+                None => self.git.state_dir().join("compile-cache"),
+            };
+            let dir = dir.to_string_lossy().into_owned();
+            let tool = match cache {
+                CompileCache::Sccache => "sccache",
+                CompileCache::Ccache => "ccache",
+            };
+            env.push(("CMAKE_C_COMPILER_LAUNCHER".to_string(), tool.to_string()));
+            env.push(("CMAKE_CXX_COMPILER_LAUNCHER".to_string(), tool.to_string()));
+            match cache {
+                CompileCache::Sccache => {
+                    env.push(("RUSTC_WRAPPER".to_string(), tool.to_string()));
+                    env.push(("SCCACHE_DIR".to_string(), dir));
+                }
+                CompileCache::Ccache => {
+                    // Paths below the base are hashed relative to the build
+                    // directory, so the same file in two worktrees hits.
+                    let base = common_ancestor(&root, &self.git.worktree_dir());
+                    env.push(("CCACHE_DIR".to_string(), dir));
+                    env.push((
+                        "CCACHE_BASEDIR".to_string(),
+                        base.to_string_lossy().into_owned(),
+                    ));
+                    env.push(("CCACHE_NOHASHDIR".to_string(), "true".to_string()));
+                }
+            }
+        }
+        env
+    }
+
+    pub fn state(&self) -> Result<State> {
+        State::load(&self.git.state_dir())
+    }
+
+    /// Serializes changes to worktrees and the state file with other graft
+    /// runs; see [`state::lock`]. Read-only commands don't take it.
+    pub fn lock(&self) -> Result<StateLock> {
+        state::lock(&self.git.state_dir())
+    }
+
+    /// Runs `f` and, with the `timings` config on, records how long it took
+    /// as a sample of `op` for `graft stats --timings`. Failures aren't timed.
+    pub fn timed<T>(&self, op: &str, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let start = Instant::now();
+        let result = f()?;
+        self.record_timing(op, start);
+        Ok(result)
+    }
+
+    /// Records the time since `start` as a sample of `op`; see [`Context::timed`].
+    pub fn record_timing(&self, op: &str, start: Instant) {
+        if self.config.timings
+            && let Err(err) = timings::record(&self.git.state_dir(), op, start.elapsed())
+        {
+            warn!("could not record timings: {err:#}");
+        }
+    }
+
+    /// Marks worktree `name` as used just now, for `graft last`.
+    pub fn touch(&self, name: &str) -> Result<()> {
+        let _lock = self.lock()?;
+        let mut state = self.state()?;
+        state.touch(name);
+        state.save()
+    }
+
+    /// Records that worktree `name` was attached from `start` until now,
+    /// for `graft report`.
+    pub fn record_attach(&self, name: &str, start: u64) {
+        if let Err(err) = timesheet::record(&self.git.state_dir(), name, start, state::now()) {
+            warn!("could not record the time attached: {err:#}");
+        }
+    }
+}
+
+/// The deepest directory containing both paths.
+fn common_ancestor(a: &Path, b: &Path) -> PathBuf {
+    a.ancestors()
+        .find(|dir| b.starts_with(dir))
+        .unwrap_or(Path::new("/"))
+        .to_path_buf()
+}
+
+/// Whether `path` names something below the directory it is relative to.
+fn inside(path: &Path) -> bool {
+    path.components()
+        .all(|part| matches!(part, Component::Normal(_) | Component::CurDir))
+        && path
+            .components()
+            .any(|part| matches!(part, Component::Normal(_)))
+}
+
+/// `branch` as `[env]`'s `{branch_slug}`: lowercase letters, digits and
+/// single underscores, so it fits database and container names.
+fn branch_slug(branch: &str) -> String {
+    let mut slug = String::new();
+    for c in branch.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('_') {
+            slug.push('_');
+        }
+    }
+    slug.trim_end_matches('_').to_string()
+}
+
+/// The context for `graft open`, with its per-run `--env`, `--submodules`
+/// and `--sparse`.
+fn open_context(repo: Option<&Path>, args: &OpenArgs) -> Result<Context> {
+    Context::new(repo)?
+        .with_env(args.env.clone())
+        .with_submodules(args.submodules)
+        .with_tab(args.tab)
+        .with_layout(args.layout.clone())
+        .with_sparse(&args.sparse)
+}
+
+pub fn dispatch(cli: Cli) -> Result<()> {
+    let repo = cli.repo.as_deref();
+    match cli.command {
+        // doctor has to work outside a repository and with a broken config,
+        // so it builds its own context.
+        Command::Demo(args) => demo::run(args),
+        Command::Doctor => doctor::run(repo),
+        Command::CompletionsData => completions_data::run(&Context::new(repo)?),
+        Command::Open(args) => open::run(&open_context(repo, &args)?, args),
+        Command::Branch(args) => {
+            let args = OpenArgs::from_shorthand(args);
+            open::run(&open_context(repo, &args)?, args)
+        }
+        Command::Attach(args) => attach::run(&Context::new(repo)?, args),
+        Command::Focus(args) => focus::run(&Context::new(repo)?, args),
+        Command::Last => recent::last(&Context::new(repo)?),
+        Command::Recent(args) => recent::recent(&Context::new(repo)?, args),
+        Command::Checkout(args) => checkout::run(&Context::new(repo)?, args),
+        Command::Change(args) => change::run(&Context::new(repo)?, args),
+        Command::Take(args) => take::run(&Context::new(repo)?, args),
+        Command::Rm(args) => rm::run(&Context::new(repo)?, args),
+        Command::Undo => undo::run(&Context::new(repo)?),
+        Command::Gc(args) => gc::run(&Context::new(repo)?, args),
+        Command::Adopt(args) => adopt::run(&Context::new(repo)?, args),
+        Command::Mv(args) => mv::run(&Context::new(repo)?, args),
+        Command::Lock(args) => lock::lock(&Context::new(repo)?, args),
+        Command::Unlock(args) => lock::unlock(&Context::new(repo)?, args),
+        Command::Archive(args) => archive::archive(&Context::new(repo)?, args),
+        Command::Unarchive(args) => archive::unarchive(&Context::new(repo)?, args),
+        Command::Protect => protect::protect(&Context::new(repo)?),
+        Command::Unprotect => protect::unprotect(&Context::new(repo)?),
+        Command::Ls(args) if args.all => ls::run_all(args),
+        Command::Ls(args) => ls::run(&Context::new(repo)?, args),
+        Command::Repo(command) => repo::run(repo, command),
+        Command::Repos(args) => repos::run(args),
+        Command::InstallGitAliases(args) => aliases::run(repo, args),
+        Command::Config(command) => config::run(&Context::new(repo)?, command),
+        Command::Init => init::run(&Context::new(repo)?),
+        Command::Repair => repair::run(&Context::new(repo)?),
+        Command::Du => du::run(&Context::new(repo)?),
+        Command::Stats(args) => stats::run(&Context::new(repo)?, args),
+        Command::Report(args) => report::run(&Context::new(repo)?, args),
+        Command::Status(args) => status::run(&Context::new(repo)?, args),
+        Command::Note(args) => note::run(&Context::new(repo)?, args),
+        Command::Prompt => prompt::run(repo),
+        Command::Clean(args) => clean::run(&Context::new(repo)?, args),
+        Command::Sync => sync::run(&Context::new(repo)?),
+        Command::Watch(args) => watch::run(&Context::new(repo)?, args),
+        Command::ZellijPipe(args) => zellij_pipe::run(&Context::new(repo)?, args),
+        Command::Serve(args) => serve::run(&Context::new(repo)?, args),
+    }
+}
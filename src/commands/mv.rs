@@ -0,0 +1,78 @@
+use std::fs;
+
+use anyhow::{Result, bail};
+use tracing::{info, warn};
+
+use super::Context;
+use crate::cli::MvArgs;
+
+/// Renames the branch and moves its worktree, then carries the state
+/// record, generated layout, session and claim over to the new name.
+pub fn run(ctx: &Context, args: MvArgs) -> Result<()> {
+    let MvArgs { old, new } = args;
+    let _lock = ctx.lock()?;
+    if old == new {
+        bail!("{old} is already called that");
+    }
+    if !ctx.git.has_branch(&old) || !ctx.git.has_worktree(&old) {
+        bail!("no worktree for branch '{old}'");
+    }
+    if ctx.git.has_branch(&new) {
+        bail!("branch {new} already exists");
+    }
+    if ctx.git.lock_reason(&old)?.is_some() {
+        bail!("{old} is locked; run `graft unlock {old}` first");
+    }
+
+    let mut state = ctx.state()?;
+    let path = ctx.git.rename_worktree(&old, &new)?;
+    // An adopted session keeps the name its owner gave it.
+    let renamed = state.move_record(&old, &new);
+    if let Some(record) = state.worktrees.get_mut(&new) {
+        record.path = path.clone();
+    }
+    state.save()?;
+
+    if let Some((old_session, new_session)) = renamed {
+        let layouts = ctx.git.state_dir().join("layouts");
+        let layout = layouts.join(format!("{old_session}.kdl"));
+        if layout.exists() {
+            fs::rename(&layout, layouts.join(format!("{new_session}.kdl")))?;
+        }
+        rename_session(ctx, &old_session, &new_session);
+    }
+    if ctx.config.claims && ctx.git.has_remote() {
+        if let Err(err) = ctx.git.release_claim(&old) {
+            warn!("could not release the claim on {old}: {err:#}");
+        }
+        match ctx.git.claim(&new) {
+            Ok(None) => {}
+            Ok(Some(owner)) => warn!("{new} is already claimed by {owner}"),
+            Err(err) => warn!("could not claim {new}: {err:#}"),
+        }
+    }
+    info!("Renamed {} to {}, now at {}", old, new, path.display());
+    Ok(())
+}
+
+/// Renames a running session; an exited one, or one zellij won't rename,
+/// is deleted instead, since it would come back in the old directory.
+pub fn rename_session(ctx: &Context, old: &str, new: &str) {
+    let Some(session) = ctx
+        .zellij
+        .session_list()
+        .into_iter()
+        .find(|session| session.name == old)
+    else {
+        return;
+    };
+    if !session.exited {
+        match ctx.zellij.rename_session(old, new) {
+            Ok(()) => return,
+            Err(err) => warn!("{err:#}; ending it instead"),
+        }
+    }
+    if let Err(err) = ctx.zellij.delete_session(old) {
+        warn!("{err:#}");
+    }
+}
@@ -0,0 +1,42 @@
+use std::env;
+
+use anyhow::{Result, bail};
+use tracing::info;
+
+use super::Context;
+use crate::cli::NoteArgs;
+
+pub fn run(ctx: &Context, args: NoteArgs) -> Result<()> {
+    let branch = match args.branch {
+        Some(branch) => branch,
+        None => match ctx.git.worktree_containing(&env::current_dir()?)? {
+            Some(worktree) => worktree.label().to_string(),
+            None => bail!("not inside a worktree; name one with --branch"),
+        },
+    };
+    let _lock = ctx.lock()?;
+    let mut state = ctx.state()?;
+    let Some(record) = state.worktrees.get_mut(&branch) else {
+        bail!("graft has no record of {branch}; open or adopt it first");
+    };
+
+    if let Some(n) = args.done {
+        if n == 0 || n > record.notes.len() {
+            bail!("{branch} has no note {n}");
+        }
+        let note = record.notes.remove(n - 1);
+        state.save()?;
+        info!("Done: {}", note);
+        return Ok(());
+    }
+    if args.text.is_empty() {
+        for (i, note) in record.notes.iter().enumerate() {
+            println!("{:>3}. {}", i + 1, note);
+        }
+        return Ok(());
+    }
+    record.notes.push(args.text.join(" "));
+    state.save()?;
+    info!("Noted on {}", branch);
+    Ok(())
+}
@@ -0,0 +1,1261 @@
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context as _, Result, bail};
+use dialoguer::Confirm;
+use tracing::{debug, info, warn};
+
+use super::Context;
+use super::banner;
+use super::gc;
+use super::ls;
+use super::protect;
+use super::rm::cleanup;
+use super::target::{self, Target};
+use super::{change, checkout};
+use crate::capabilities::Feature;
+use crate::cli::{
+    AttachMode, ChangeArgs, CheckoutArgs, CleanupStep, ExistingDir, GcArgs, OpenArgs, PostExit,
+};
+use crate::config::{self, Forge, LAYOUT_FILE, LAYOUTS_DIR, REPO_DIR, SessionStrategy};
+use crate::git;
+use crate::hooks::{self, Hook};
+use crate::issues;
+use crate::process::{Cmd, ProcessRunner};
+use crate::progress;
+use crate::secrets;
+use crate::state::{self, Purpose, SessionLayout, WorktreeRecord};
+use crate::terminal;
+use crate::units::{self, parse_duration};
+use crate::zellij;
+
+/// How often `--after` checks whether the session is still running.
+const AFTER_POLL: Duration = Duration::from_secs(2);
+
+pub fn run(ctx: &Context, args: OpenArgs) -> Result<()> {
+    if args.stdin || args.file.is_some() {
+        return open_batch(ctx, args);
+    }
+    let OpenArgs {
+        ticket,
+        issue,
+        stdin: _,
+        file: _,
+        ephemeral,
+        remote,
+        offline,
+        cleanup,
+        delete_branch,
+        existing,
+        ttl,
+        mut then,
+        after,
+        purpose,
+        yes,
+        attach: attach_mode,
+        no_attach,
+        tab: _,
+        layout: _,
+        editor,
+        env: _,
+        submodules: _,
+        sparse,
+        command,
+    } = args;
+    if (ephemeral || !cleanup.is_empty()) && !then.contains(&PostExit::Rm) {
+        then.push(PostExit::Rm);
+    }
+    let mut cleanup = if cleanup.is_empty() {
+        ctx.config.cleanup.clone()
+    } else {
+        cleanup
+    };
+    if delete_branch && !cleanup.contains(&CleanupStep::Branch) {
+        cleanup.push(CleanupStep::Branch);
+    }
+    if let Some(branch) = after {
+        return after_session(ctx, branch, &then, &cleanup);
+    }
+    let (ticket, issue) = match (ticket, issue) {
+        (_, Some(id)) => {
+            let issue = progress::spin(&format!("Looking up issue {id}"), || {
+                issues::lookup(ctx.git.runner(), &ctx.config.issues, &ctx.git.root(), &id)
+            })?;
+            info!("{}: {}", issue.key, issue.title);
+            (
+                issues::branch_name(&ctx.config.issues.branch, &issue),
+                Some(issue),
+            )
+        }
+        (Some(ticket), None) => (expand(ctx, ticket)?, None),
+        (None, None) => bail!("no branch to open"),
+    };
+    let mode = match (no_attach, attach_mode) {
+        (true, _) => AttachMode::None,
+        (false, Some(mode)) => mode,
+        (false, None) => ctx.config.attach,
+    };
+    if mode == AttachMode::Zellij
+        && let Some(reason) = ctx.capabilities.missing(Feature::Sessions)
+    {
+        bail!(
+            "{} disabled because {reason}; pass --attach shell or --no-attach \
+             to open the worktree without it",
+            Feature::Sessions
+        );
+    }
+    let (ticket, remote) = match resolve(ctx, ticket)? {
+        Target::Branch {
+            name,
+            remote: through,
+        } => (name, remote.or(through)),
+        Target::Commit(rev) => {
+            fetch_commit(ctx, &rev)?;
+            let args = CheckoutArgs { rev, keep: false };
+            return checkout::open(ctx, args, mode);
+        }
+        Target::Change(change) => {
+            let args = ChangeArgs {
+                change,
+                detach: false,
+                keep: false,
+            };
+            return change::open(ctx, args, mode);
+        }
+        Target::PullRequest { number, forge } => (fetch_pull_request(ctx, number, forge)?, remote),
+    };
+    let started = Instant::now();
+    let ttl = parse_ttl(ttl.as_deref())?;
+
+    let mut offline = offline || ctx.config.offline;
+    let remotes = match remote {
+        _ if offline => Vec::new(),
+        Some(remote) => vec![remote],
+        None => ctx.git.remotes(),
+    };
+    // Released before attaching: the session can run for days.
+    let lock = ctx.lock()?;
+    let adopted = clear_worktree_path(ctx, &ticket, existing.unwrap_or(ctx.config.existing_dir))?;
+    offer_exclude(ctx, yes)?;
+    let remote = match open_branch(ctx, &ticket, &remotes, mode == AttachMode::Zellij) {
+        Ok(remote) => remote,
+        Err(err) if git::timed_out(&err) => {
+            warn!(
+                "no remote answered within {}s; opening {ticket} as a local branch \
+                 (pass --offline to skip the lookup)",
+                ctx.git.network().lookup_timeout.as_secs()
+            );
+            offline = true;
+            None
+        }
+        Err(err) => {
+            return Err(err.context(format!(
+                "could not look up {ticket} on the remotes; pass --offline to open it \
+                 as a local branch"
+            )));
+        }
+    };
+    let had_branch = ctx.git.has_branch(&ticket);
+    let ensure_branch = || {
+        ctx.git
+            .ensure_branch(&ticket, ctx.config.track_upstream, remote.as_deref())
+    };
+    if remote.is_some() && !had_branch {
+        let message = format!("Fetching {ticket}");
+        ctx.timed("fetch", || progress::spin(&message, ensure_branch))?;
+    } else {
+        ensure_branch()?;
+    }
+    // From here on, a failure before the session starts undoes this run.
+    let mut rollback = Rollback::new(ctx, &ticket);
+    rollback.branch = !had_branch;
+    let new_worktree = !ctx.git.has_worktree(&ticket);
+    if !new_worktree && !sparse.is_empty() {
+        warn!("{ticket} already has a worktree; --sparse only applies to new ones");
+    }
+    let created = if new_worktree {
+        ctx.timed("worktree-add", || {
+            progress::spin(&format!("Checking out {ticket}"), || {
+                ctx.git.ensure_worktree(&ticket)
+            })
+        })
+    } else {
+        ctx.git.ensure_worktree(&ticket)
+    };
+    let worktree = match (created, &adopted) {
+        (Ok(worktree), _) => worktree,
+        (Err(err), Some(staging)) => {
+            // Put the directory back the way it was.
+            fs::rename(staging, ctx.git.worktree_path(&ticket))?;
+            return Err(err);
+        }
+        (Err(err), None) => return Err(err),
+    };
+    rollback.worktree = new_worktree;
+    if let Some(staging) = adopted {
+        rollback.adopted = true;
+        git::overlay_dir(&staging, worktree.path())?;
+        info!(
+            "Adopted the existing files into {}",
+            worktree.path().display()
+        );
+    }
+    if new_worktree
+        && let Err(err) = hooks::post_create(
+            ctx.git.runner(),
+            &ctx.git.root(),
+            &ctx.config.secrets,
+            &ticket,
+            worktree.path(),
+            &ctx.worktree_env(&ticket, worktree.path()),
+        )
+    {
+        warn!("{err:#}");
+    }
+    rollback.provisioned = changes(ctx, worktree.path());
+    if ctx.config.claims && !offline && ctx.git.has_remote() {
+        match ctx.git.claim(&ticket) {
+            Ok(None) => rollback.claim = !had_branch,
+            Ok(Some(owner)) => warn!("{ticket} is already claimed by {owner}"),
+            Err(err) => warn!("could not claim {ticket}: {err:#}"),
+        }
+    }
+
+    let purpose = purpose.unwrap_or_else(|| {
+        if then.contains(&PostExit::Rm) && cleanup.contains(&CleanupStep::Worktree) {
+            Purpose::Scratch
+        } else {
+            Purpose::infer(&ticket)
+        }
+    });
+    rollback.record = !ctx.state()?.worktrees.contains_key(&ticket);
+    record(ctx, &ticket, worktree.path(), purpose)?;
+    set_expiry(ctx, &ticket, purpose, ttl)?;
+    if let Some(issue) = issue {
+        let mut state = ctx.state()?;
+        if let Some(record) = state.worktrees.get_mut(&ticket) {
+            record.issue = Some(issue.url);
+        }
+        state.save()?;
+    }
+    ctx.record_timing("open", started);
+    protect::refresh(ctx);
+
+    let command = match (command.is_empty(), &ctx.config.default_command) {
+        (false, _) => command,
+        (true, Some(default)) => vec!["sh".to_string(), "-c".to_string(), default.clone()],
+        (true, None) => Vec::new(),
+    };
+    drop(lock);
+    if editor && mode != AttachMode::Editor {
+        open_editor(ctx, &ticket, worktree.path())?;
+    }
+    enter(ctx, &ticket, worktree.path(), mode, &command)?;
+    rollback.keep();
+    after_exit(ctx, &ticket, mode, &then, &cleanup)
+}
+
+/// The worktree `ticket` stands for when it is not a local branch: its
+/// number in `graft ls`, or the start of exactly one worktree's name.
+/// Anything else is left to be read as a branch, ref or URL.
+/// What an expanded `ticket` opens. A local branch wins over reading its
+/// name as a ref or remote name.
+pub(super) fn resolve(ctx: &Context, ticket: String) -> Result<Target> {
+    if ctx.git.has_branch(&ticket) {
+        return Ok(Target::Branch {
+            name: ticket,
+            remote: None,
+        });
+    }
+    target::parse(&ticket, &ctx.git.remotes())
+}
+
+pub(super) fn expand(ctx: &Context, ticket: String) -> Result<String> {
+    if ctx.git.has_branch(&ticket) {
+        return Ok(ticket);
+    }
+    let worktrees = ctx.git.worktrees()?;
+    let numbered = ls::numbered(&worktrees);
+    if numbered.iter().any(|worktree| worktree.label() == ticket) {
+        return Ok(ticket);
+    }
+    if let Ok(index) = ticket.parse::<usize>() {
+        let Some(worktree) = index.checked_sub(1).and_then(|i| numbered.get(i)) else {
+            bail!(
+                "no worktree numbered {index}; `graft ls` has {} (open a branch named \
+                 {index} as refs/heads/{index})",
+                numbered.len()
+            );
+        };
+        return Ok(worktree.label().to_string());
+    }
+    let candidates: Vec<&str> = numbered
+        .iter()
+        .map(|worktree| worktree.label())
+        .filter(|label| label.starts_with(ticket.as_str()))
+        .collect();
+    match candidates[..] {
+        [] => Ok(ticket),
+        [label] => Ok(label.to_string()),
+        _ => bail!(
+            "'{ticket}' could be any of: {}; type more of the name",
+            candidates.join(", ")
+        ),
+    }
+}
+
+/// Parses `--ttl`, refusing one that would expire past what a timestamp can
+/// hold before any worktree is created for it.
+fn parse_ttl(ttl: Option<&str>) -> Result<Option<u64>> {
+    let Some(ttl) = ttl else { return Ok(None) };
+    let seconds = parse_duration(ttl)?;
+    units::after(state::now(), seconds)?;
+    Ok(Some(seconds))
+}
+
+/// Sets when `graft gc` may remove the worktree: after `ttl`, else after
+/// the `[policy]` or per-purpose default, unless a policy protects it.
+fn set_expiry(ctx: &Context, name: &str, purpose: Purpose, ttl: Option<u64>) -> Result<()> {
+    let policy = ctx.config.policy(name);
+    let default_ttl = match policy.protected {
+        Some(true) => None,
+        _ => policy.ttl.or_else(|| ctx.config.ttl.get(&purpose).cloned()),
+    };
+    let ttl = match (ttl, default_ttl) {
+        (Some(ttl), _) => Some(ttl),
+        (None, Some(default)) => Some(parse_duration(&default)?),
+        (None, None) => None,
+    };
+    if let Some(ttl) = ttl {
+        let mut state = ctx.state()?;
+        if let Some(record) = state.worktrees.get_mut(name) {
+            record.expires_at = Some(units::after(state::now(), ttl)?);
+        }
+        state.save()?;
+    }
+    Ok(())
+}
+
+/// `graft open --stdin` / `--file`: a worktree and a background session for
+/// every branch listed. Remote lookups and fetches, post-create hooks and
+/// session starts run in parallel; checkouts go one at a time, as they all
+/// write to the same repository. A branch that fails doesn't stop the rest.
+fn open_batch(ctx: &Context, args: OpenArgs) -> Result<()> {
+    let branches = read_branches(args.file.as_deref())?;
+    if branches.is_empty() {
+        bail!("no branches to open");
+    }
+    let sessions = !args.no_attach;
+    if sessions && let Some(reason) = ctx.capabilities.missing(Feature::Sessions) {
+        bail!(
+            "{} disabled because {reason}; pass --no-attach to only create the worktrees",
+            Feature::Sessions
+        );
+    }
+    let ttl = parse_ttl(args.ttl.as_deref())?;
+    let offline = args.offline || ctx.config.offline;
+    let remotes = match args.remote {
+        _ if offline => Vec::new(),
+        Some(remote) => vec![remote],
+        None => ctx.git.remotes(),
+    };
+    offer_exclude(ctx, args.yes)?;
+
+    let root = ctx.git.root();
+    let runner = ctx.git.runner();
+    let network = ctx.git.network();
+    let lookups: Vec<(String, bool)> = branches
+        .iter()
+        .map(|branch| {
+            let lookup = !remotes.is_empty() && !ctx.git.has_branch(branch);
+            (ctx.git.remote_branch_name(branch), lookup)
+        })
+        .collect();
+    let message = format!("Fetching {} branches", branches.len());
+    let fetched: Vec<Result<Option<String>>> = progress::spin(&message, || {
+        thread::scope(|s| {
+            let handles: Vec<_> = lookups
+                .iter()
+                .map(|(remote_name, lookup)| {
+                    let (root, remotes) = (&root, &remotes);
+                    s.spawn(move || {
+                        if !lookup {
+                            return Ok(None);
+                        }
+                        let remote =
+                            git::find_remote_branch(runner, root, remotes, remote_name, network)?;
+                        if let Some(remote) = &remote {
+                            git::fetch_branch_in(runner, root, remote, remote_name, network)?;
+                        }
+                        Ok(remote)
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("branch fetch panicked"))
+                .collect()
+        })
+    });
+
+    let lock = ctx.lock()?;
+    let existing = args.existing.unwrap_or(ctx.config.existing_dir);
+    let mut opened = Vec::new();
+    let mut failed = Vec::new();
+    for (branch, remote) in branches.iter().zip(fetched) {
+        let remote = match remote {
+            Ok(remote) => remote,
+            Err(err) if git::timed_out(&err) => {
+                warn!("no remote answered for {branch}; opening it as a local branch");
+                None
+            }
+            Err(err) => {
+                failed.push((branch, err));
+                continue;
+            }
+        };
+        match check_out(ctx, branch, remote.as_deref(), existing, args.purpose, ttl) {
+            Ok((path, new)) => opened.push((branch, path, new)),
+            Err(err) => {
+                failed.push((branch, err));
+                continue;
+            }
+        }
+        if ctx.config.claims && !offline && ctx.git.has_remote() {
+            match ctx.git.claim(branch) {
+                Ok(None) => {}
+                Ok(Some(owner)) => warn!("{branch} is already claimed by {owner}"),
+                Err(err) => warn!("could not claim {branch}: {err:#}"),
+            }
+        }
+    }
+    protect::refresh(ctx);
+    drop(lock);
+
+    // One at a time, ahead of the hooks: a password manager may prompt.
+    for (branch, path, new) in &mut opened {
+        let env = ctx.worktree_env(branch, path);
+        if *new
+            && let Err(err) =
+                secrets::provision(runner, &ctx.config.secrets, &root, branch, path, &env)
+        {
+            warn!("{branch}: {err:#}; skipped the post-create hook");
+            *new = false;
+        }
+    }
+    let state = ctx.state()?;
+    let started: Vec<Result<()>> = thread::scope(|s| {
+        let handles: Vec<_> = opened
+            .iter()
+            .map(|(branch, path, new)| {
+                let session = state.session(branch);
+                let env = ctx.worktree_env(branch, path);
+                let (root, zellij) = (&root, &ctx.zellij);
+                s.spawn(move || {
+                    if *new
+                        && let Err(err) =
+                            hooks::run(runner, root, Hook::PostCreate, branch, path, &env)
+                    {
+                        warn!("{branch}: {err:#}");
+                    }
+                    if sessions {
+                        zellij.start_background(&session, path, &env)?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("session start panicked"))
+            .collect()
+    });
+
+    for ((branch, path, _), started) in opened.iter().zip(started) {
+        match started {
+            Ok(()) if sessions => println!("  {:<30} {}", branch, state.session(branch)),
+            Ok(()) => println!("  {:<30} {}", branch, path.display()),
+            Err(err) => println!("  {:<30} no session: {err:#}", branch),
+        }
+    }
+    for (branch, err) in &failed {
+        println!("  {:<30} failed: {err:#}", branch);
+    }
+    if !failed.is_empty() {
+        bail!(
+            "{} of {} branches could not be opened",
+            failed.len(),
+            branches.len()
+        );
+    }
+    Ok(())
+}
+
+/// Branch names from `file`, or stdin without one: one per line, blank
+/// lines and `#` comments skipped, repeats dropped.
+fn read_branches(file: Option<&Path>) -> Result<Vec<String>> {
+    let text = match file {
+        Some(file) => fs::read_to_string(file)
+            .with_context(|| format!("failed to read {}", file.display()))?,
+        None => io::read_to_string(io::stdin()).context("failed to read stdin")?,
+    };
+    let mut branches: Vec<String> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || branches.iter().any(|b| b == line) {
+            continue;
+        }
+        branches.push(line.to_string());
+    }
+    Ok(branches)
+}
+
+/// The branch, worktree and record for one branch of a batch, taken back
+/// if any of it fails. Returns the worktree's path and whether it is new.
+fn check_out(
+    ctx: &Context,
+    branch: &str,
+    remote: Option<&str>,
+    existing: ExistingDir,
+    purpose: Option<Purpose>,
+    ttl: Option<u64>,
+) -> Result<(PathBuf, bool)> {
+    let adopted = clear_worktree_path(ctx, branch, existing)?;
+    let had_branch = ctx.git.has_branch(branch);
+    let track = ctx.config.track_upstream;
+    match remote {
+        Some(remote) if !had_branch => ctx.git.create_branch_from_fetched(branch, remote, track)?,
+        _ => ctx.git.ensure_branch(branch, track, None)?,
+    }
+    let mut rollback = Rollback::new(ctx, branch);
+    rollback.branch = !had_branch;
+    let new = !ctx.git.has_worktree(branch);
+    let worktree = match (ctx.git.ensure_worktree(branch), &adopted) {
+        (Ok(worktree), _) => worktree,
+        (Err(err), Some(staging)) => {
+            fs::rename(staging, ctx.git.worktree_path(branch))?;
+            return Err(err);
+        }
+        (Err(err), None) => return Err(err),
+    };
+    let path = worktree.path().to_path_buf();
+    rollback.worktree = new;
+    if let Some(staging) = adopted {
+        rollback.adopted = true;
+        git::overlay_dir(&staging, &path)?;
+    }
+    rollback.provisioned = changes(ctx, &path);
+    let purpose = purpose.unwrap_or_else(|| Purpose::infer(branch));
+    rollback.record = !ctx.state()?.worktrees.contains_key(branch);
+    record(ctx, branch, &path, purpose)?;
+    set_expiry(ctx, branch, purpose, ttl)?;
+    rollback.keep();
+    Ok((path, new))
+}
+
+/// What `graft open` created for a branch. Dropped without [`Rollback::keep`]
+/// (a step failed before the session started), it removes all of it again.
+/// A worktree with files of its own, adopted or changed since graft set it
+/// up (secrets, the post-create hook), is kept instead, with how to carry on.
+struct Rollback<'a> {
+    ctx: &'a Context,
+    name: &'a str,
+    branch: bool,
+    worktree: bool,
+    /// The worktree holds files from `--existing adopt`.
+    adopted: bool,
+    /// Changed files in the worktree once graft had set it up.
+    provisioned: usize,
+    claim: bool,
+    record: bool,
+}
+
+impl<'a> Rollback<'a> {
+    fn new(ctx: &'a Context, name: &'a str) -> Self {
+        Rollback {
+            ctx,
+            name,
+            branch: false,
+            worktree: false,
+            adopted: false,
+            provisioned: 0,
+            claim: false,
+            record: false,
+        }
+    }
+
+    fn keep(mut self) {
+        self.branch = false;
+        self.worktree = false;
+        self.claim = false;
+        self.record = false;
+    }
+}
+
+impl Drop for Rollback<'_> {
+    fn drop(&mut self) {
+        if !(self.branch || self.worktree || self.claim || self.record) {
+            return;
+        }
+        let (ctx, name) = (self.ctx, self.name);
+        let resume = |why: String| warn!("{why}; resume with `graft {name}`");
+        let _lock = match ctx.lock() {
+            Ok(lock) => lock,
+            Err(err) => return resume(format!("could not roll back {name}: {err:#}")),
+        };
+        if self.worktree {
+            let path = ctx.git.worktree_path(name);
+            let changed = self.adopted || changes(ctx, &path) > self.provisioned;
+            if changed {
+                return resume(format!("kept {} for the files in it", path.display()));
+            }
+            if let Err(err) = ctx.git.remove_worktree(name, true) {
+                return resume(format!(
+                    "could not remove the new worktree of {name}: {err:#}"
+                ));
+            }
+        }
+        if self.record
+            && let Err(err) = ctx.state().and_then(|mut state| {
+                state.worktrees.remove(name);
+                state.save()
+            })
+        {
+            warn!("could not drop {name} from the state file: {err:#}");
+        }
+        if self.claim
+            && let Err(err) = ctx.git.release_claim(name)
+        {
+            warn!("could not release the claim on {name}: {err:#}");
+        }
+        if self.branch
+            && let Err(err) = ctx.git.delete_branch(name)
+        {
+            warn!("could not delete the new branch {name}: {err:#}");
+        }
+        protect::refresh(ctx);
+        info!("Rolled back what opening {name} created");
+    }
+}
+
+/// How many files in the worktree at `path` are changed or untracked; none
+/// when it cannot be read.
+fn changes(ctx: &Context, path: &Path) -> usize {
+    ctx.git
+        .inspect(path)
+        .map_or(0, |status| status.dirty_files + status.untracked_files)
+}
+
+/// Deals with something other than a worktree at the branch's worktree
+/// path. When adopting, returns where the contents were parked; they go
+/// back on top of the fresh checkout.
+fn clear_worktree_path(
+    ctx: &Context,
+    branch: &str,
+    existing: ExistingDir,
+) -> Result<Option<PathBuf>> {
+    ctx.git.recover_interrupted_add(branch)?;
+    let path = ctx.git.worktree_path(branch);
+    if ctx.git.has_worktree(branch) || fs::symlink_metadata(&path).is_err() {
+        return Ok(None);
+    }
+    let parked = |suffix: &str| {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{suffix}-{}", state::now()));
+        path.with_file_name(name)
+    };
+    match existing {
+        ExistingDir::Fail => bail!(
+            "{} exists but is not a worktree; pass --existing adopt to keep its files \
+             in a new worktree, or --existing move to move it aside \
+             (or set existing_dir in the config)",
+            path.display()
+        ),
+        ExistingDir::Adopt => {
+            if !path.is_dir() {
+                bail!(
+                    "{} is not a directory; only --existing move can clear it",
+                    path.display()
+                );
+            }
+            if path.join(".git").exists() {
+                bail!(
+                    "{} has its own .git; run `graft repair` if it is a worktree \
+                     whose links broke, or --existing move to keep it aside",
+                    path.display()
+                );
+            }
+            let staging = parked("graft-adopt");
+            fs::rename(&path, &staging)?;
+            Ok(Some(staging))
+        }
+        ExistingDir::Move => {
+            let aside = parked("bak");
+            fs::rename(&path, &aside)?;
+            info!("Moved {} to {}", path.display(), aside.display());
+            Ok(None)
+        }
+    }
+}
+
+/// The first worktree creates the worktree directory; keeps it out of
+/// `git status` for everyone, asking first unless `yes`.
+fn offer_exclude(ctx: &Context, yes: bool) -> Result<()> {
+    let git = &ctx.git;
+    let dir = git.worktree_dir();
+    if dir.exists() || git.worktree_dir_ignored()? != Some(false) {
+        return Ok(());
+    }
+    let add = yes
+        || (io::stdin().is_terminal()
+            && Confirm::new()
+                .with_prompt(format!(
+                    "Add {} to .git/info/exclude so it stays out of git status?",
+                    dir.display()
+                ))
+                .default(true)
+                .interact()?);
+    if add {
+        git.exclude_worktree_dir()?;
+        info!("Added {} to .git/info/exclude", dir.display());
+    } else {
+        warn!(
+            "{} will show up in git status; `graft init` excludes it",
+            dir.display()
+        );
+    }
+    Ok(())
+}
+
+/// Writes a generated layout under the state directory for zellij to load.
+fn write_layout(ctx: &Context, name: &str, layout: &str) -> Result<PathBuf> {
+    let dir = ctx.git.state_dir().join("layouts");
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.kdl", ctx.state()?.session(name)));
+    fs::write(&path, layout)?;
+    Ok(path)
+}
+
+/// The layout to create the session for `name` with: one running
+/// `command`, else `--layout`, else the one its session was last created
+/// with, else the repository's. The choice is recorded, with a copy of the
+/// layout, so a session recreated after a reboot or once it was killed
+/// looks the same even when the repository's layout has changed since.
+fn session_layout(ctx: &Context, name: &str, command: &[String]) -> Result<Option<PathBuf>> {
+    let saved = ctx
+        .git
+        .state_dir()
+        .join("layouts")
+        .join(format!("{}.kdl", ctx.state()?.session(name)));
+    let recorded = match ctx.state()?.worktrees.get(name) {
+        Some(record) if command.is_empty() && ctx.layout.is_none() => record.layout,
+        _ => None,
+    };
+    let reuse = match recorded {
+        Some(SessionLayout::Plain) => return Ok(None),
+        Some(SessionLayout::Saved) if saved.is_file() => true,
+        Some(SessionLayout::Saved) => {
+            warn!("the layout {name} was created with is gone; using the current one");
+            false
+        }
+        None => false,
+    };
+    let repo_layout = (!reuse && command.is_empty())
+        .then(|| repo_layout(ctx, name))
+        .transpose()?;
+    let wants_layout =
+        reuse || !command.is_empty() || repo_layout.as_ref().is_some_and(|path| path.is_file());
+    if let Some(reason) = ctx.capabilities.missing(Feature::SessionLayouts)
+        && wants_layout
+    {
+        warn!(
+            "{} disabled because {reason}; starting a plain session",
+            Feature::SessionLayouts
+        );
+        // Not what the session should have, so nothing is recorded.
+        return Ok(None);
+    }
+    if reuse {
+        return Ok(Some(saved));
+    }
+
+    let (layout, kind) = match repo_layout {
+        None => (
+            Some(write_layout(ctx, name, &zellij::command_layout(command))?),
+            SessionLayout::Saved,
+        ),
+        Some(path) if path.is_file() => {
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("could not read {}", path.display()))?;
+            (
+                Some(write_layout(ctx, name, &contents)?),
+                SessionLayout::Saved,
+            )
+        }
+        Some(_) => (None, SessionLayout::Plain),
+    };
+    let _lock = ctx.lock()?;
+    let mut state = ctx.state()?;
+    if let Some(record) = state.worktrees.get_mut(name) {
+        record.layout = Some(kind);
+        state.save()?;
+    }
+    Ok(layout)
+}
+
+/// Fetches `rev` from the fetch remote when it isn't here yet, as for a
+/// commit URL of something nobody has pulled.
+fn fetch_commit(ctx: &Context, rev: &str) -> Result<()> {
+    if ctx.git.resolve_commit(rev).is_ok() {
+        return Ok(());
+    }
+    let remote = ctx.git.fetch_remote();
+    info!("Fetching {rev}");
+    progress::spin("Fetching", || ctx.git.run_remote(&["fetch", remote, rev]))
+        .with_context(|| format!("{rev} is not here and could not be fetched from {remote}"))?;
+    Ok(())
+}
+
+/// Fetches pull request `number` into a local `pr/<number>` branch, or a
+/// GitLab merge request into `mr/<number>`, and returns the branch name.
+/// `forge` is the one named by the URL; without one the `forge` config
+/// decides, then the fetch remote's URL, then GitHub's refs are tried. A
+/// branch already there is kept as it is, with whatever was committed on
+/// it since.
+fn fetch_pull_request(ctx: &Context, number: u64, forge: Option<Forge>) -> Result<String> {
+    let remote = ctx.git.fetch_remote();
+    let forge = forge
+        .or(ctx.config.forge)
+        .or_else(|| target::detect_forge(&ctx.git.remote_url(remote)?))
+        .unwrap_or(Forge::Github);
+    let (name, refname, label) = match forge {
+        Forge::Gitlab => (
+            format!("mr/{number}"),
+            format!("merge-requests/{number}/head"),
+            format!("merge request !{number}"),
+        ),
+        Forge::Github | Forge::Gitea => (
+            format!("pr/{number}"),
+            format!("pull/{number}/head"),
+            format!("pull request #{number}"),
+        ),
+    };
+    if ctx.git.has_branch(&name) {
+        return Ok(name);
+    }
+    // FETCH_HEAD is shared, so hold the lock from the fetch on.
+    let _lock = ctx.lock()?;
+    info!("Fetching {label}");
+    progress::spin("Fetching", || {
+        ctx.git.run_remote(&["fetch", remote, &refname])
+    })
+    .with_context(|| format!("failed to fetch {refname} from {remote}"))?;
+    let oid = ctx.git.resolve_commit("FETCH_HEAD")?;
+    ctx.git.create_branch_at(&name, oid)?;
+    Ok(name)
+}
+
+/// Runs the provisioning steps that don't depend on each other at the same
+/// time: the remote lookup (network), the worktree parent directory (disk)
+/// and the session probe (zellij, unless `probe_session` is off). Returns
+/// the first of `remotes` that has the branch; only asked when there is no
+/// local branch.
+fn open_branch(
+    ctx: &Context,
+    branch: &str,
+    remotes: &[String],
+    probe_session: bool,
+) -> Result<Option<String>> {
+    let probe_remote = !ctx.git.has_branch(branch) && !remotes.is_empty();
+    let root = ctx.git.root();
+    let worktree_path = ctx.git.worktree_path(branch);
+    let session = ctx.state()?.session(branch);
+    let zellij = &ctx.zellij;
+    let runner = ctx.git.runner();
+    let remote_name = ctx.git.remote_branch_name(branch);
+    let network = ctx.git.network();
+
+    thread::scope(|s| {
+        let remote = s.spawn(|| {
+            if probe_remote {
+                git::find_remote_branch(runner, &root, remotes, &remote_name, network)
+            } else {
+                Ok(None)
+            }
+        });
+        let session_exists = s.spawn(|| probe_session && zellij.sessions().contains(&session));
+        if let Some(parent) = worktree_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        if session_exists.join().expect("session probe panicked") {
+            info!("Reattaching to existing session {}", session);
+        }
+        remote.join().expect("remote lookup panicked")
+    })
+}
+
+/// Stores the worktree in the state file unless it is already known, and
+/// marks it as used just now.
+pub fn record(ctx: &Context, name: &str, path: &Path, purpose: Purpose) -> Result<()> {
+    let mut state = ctx.state()?;
+    let session = state.new_session(name);
+    state
+        .worktrees
+        .entry(name.to_string())
+        .or_insert_with(|| WorktreeRecord {
+            path: path.to_path_buf(),
+            purpose,
+            created_at: state::now(),
+            last_used: None,
+            session: Some(session),
+            expires_at: None,
+            layout: None,
+            notes: Vec::new(),
+            issue: None,
+        });
+    state.touch(name);
+    state.save()
+}
+
+/// Enters the worktree the way `mode` says, running `command` in it if
+/// given, then runs the post-exit actions; `rm` performs the `cleanup` steps.
+pub fn attach(
+    ctx: &Context,
+    name: &str,
+    path: &Path,
+    mode: AttachMode,
+    command: &[String],
+    then: &[PostExit],
+    cleanup_steps: &[CleanupStep],
+) -> Result<()> {
+    enter(ctx, name, path, mode, command)?;
+    after_exit(ctx, name, mode, then, cleanup_steps)
+}
+
+/// Starts the session or shell in the worktree and waits for it to end;
+/// with [`AttachMode::None`], prints the path. Errors mean it never started.
+fn enter(
+    ctx: &Context,
+    name: &str,
+    path: &Path,
+    mode: AttachMode,
+    command: &[String],
+) -> Result<()> {
+    let banner = match mode {
+        AttachMode::None | AttachMode::Editor | AttachMode::Wezterm | AttachMode::Kitty => None,
+        AttachMode::Zellij if opens_tab(ctx) => None,
+        _ if !ctx.config.banner => None,
+        _ => banner::text(ctx, name, path)
+            .inspect_err(|err| debug!("no banner for {name}: {err:#}"))
+            .ok(),
+    };
+    let attached = state::now();
+    match mode {
+        AttachMode::Zellij if opens_tab(ctx) => {
+            if !ctx.env.is_empty() || !command.is_empty() {
+                warn!("--env and commands only apply to new sessions, not tabs");
+            }
+            return ctx.zellij.open_tab(name, path);
+        }
+        AttachMode::Zellij => {
+            let session = ctx.state()?.session(name);
+            check_session_dir(ctx, &session, path)?;
+            let exists = ctx.zellij.sessions().contains(&session);
+            if !ctx.env.is_empty() && exists {
+                warn!("session {session} is already running; --env only applies to new sessions");
+            }
+            if ctx.layout.is_some() && exists {
+                warn!(
+                    "session {session} is already running; --layout only applies to new sessions"
+                );
+            }
+            // zellij brings back an existing session as it was.
+            let layout = if exists {
+                None
+            } else {
+                session_layout(ctx, name, command)?
+            };
+            if let Some(text) = banner {
+                // Once the session is up; detached, since attaching blocks.
+                let (zellij, session) = (ctx.zellij.clone(), session.clone());
+                thread::spawn(move || {
+                    if let Err(err) = zellij.show_floating(&session, "graft", &text) {
+                        debug!("no banner: {err:#}");
+                    }
+                });
+            }
+            ctx.zellij.start_session(
+                &session,
+                path,
+                layout.as_deref(),
+                &ctx.worktree_env(name, path),
+            )?;
+        }
+        AttachMode::Shell => {
+            if let Some(text) = banner {
+                eprintln!("{text}\n");
+            }
+            run_shell(
+                ctx.git.runner(),
+                path,
+                command,
+                &ctx.worktree_env(name, path),
+            )?
+        }
+        AttachMode::None => {
+            println!("{}", path.display());
+            return Ok(());
+        }
+        AttachMode::Editor => return open_editor(ctx, name, path),
+        AttachMode::Wezterm => {
+            let env = ctx.worktree_env(name, path);
+            terminal::wezterm_tab(ctx.git.runner(), name, path, command, &env)?;
+        }
+        AttachMode::Kitty => {
+            let env = ctx.worktree_env(name, path);
+            terminal::kitty_tab(ctx.git.runner(), name, path, command, &env)?;
+        }
+    }
+    ctx.record_attach(name, attached);
+    Ok(())
+}
+
+/// Whether Zellij mode opens a tab rather than a session: with
+/// `session_strategy = "tabs"`, inside Zellij.
+fn opens_tab(ctx: &Context) -> bool {
+    ctx.config.session_strategy == SessionStrategy::Tabs && zellij::current_session().is_some()
+}
+
+/// Opens `path` with the `editor` config command, else `$VISUAL` or
+/// `$EDITOR`, through `sh` so the command can carry options.
+fn open_editor(ctx: &Context, name: &str, path: &Path) -> Result<()> {
+    let Some(editor) = ctx
+        .config
+        .editor
+        .clone()
+        .or_else(|| env::var("VISUAL").ok())
+        .or_else(|| env::var("EDITOR").ok())
+        .filter(|editor| !editor.trim().is_empty())
+    else {
+        bail!("no editor to open {name} in; set the `editor` config key or $EDITOR");
+    };
+    let script = if editor.contains("{path}") {
+        editor.replace("{path}", "\"$1\"")
+    } else {
+        format!("{editor} \"$1\"")
+    };
+    let mut process = Cmd::new("sh");
+    process
+        .args(["-c", &script, "graft"])
+        .arg(path)
+        .current_dir(path);
+    for (key, value) in ctx.worktree_env(name, path) {
+        process.env(key, value);
+    }
+    debug!("running {}", process);
+    let status = ctx
+        .git
+        .runner()
+        .status(&process)
+        .with_context(|| format!("failed to run the editor {editor}"))?;
+    if !status.success() {
+        bail!("the editor {editor} failed ({status})");
+    }
+    Ok(())
+}
+
+/// Runs the `then` actions once the session has ended.
+fn after_exit(
+    ctx: &Context,
+    name: &str,
+    mode: AttachMode,
+    then: &[PostExit],
+    cleanup_steps: &[CleanupStep],
+) -> Result<()> {
+    // An editor may hand the window off and return at once, as does
+    // opening a tab.
+    if matches!(
+        mode,
+        AttachMode::None | AttachMode::Editor | AttachMode::Wezterm | AttachMode::Kitty
+    ) || (mode == AttachMode::Zellij && opens_tab(ctx))
+    {
+        if !then.is_empty() {
+            warn!("nothing to wait for without attaching; skipping the post-exit actions");
+        }
+        return Ok(());
+    }
+    run_actions(ctx, name, then, cleanup_steps)
+}
+
+/// `--after`: waits for the session of the worktree `ticket` stands for to
+/// end, then runs the `then` actions on it.
+fn after_session(
+    ctx: &Context,
+    ticket: String,
+    then: &[PostExit],
+    cleanup_steps: &[CleanupStep],
+) -> Result<()> {
+    if then.is_empty() {
+        bail!("nothing to run after {ticket}; pass --then");
+    }
+    let name = expand(ctx, ticket)?;
+    let session = ctx.state()?.session(&name);
+    let running = || {
+        ctx.zellij
+            .session_list()
+            .iter()
+            .any(|info| info.name == session && !info.exited)
+    };
+    if !running() {
+        bail!("{name} has no running session to wait for");
+    }
+    info!("Waiting for session {session} to end");
+    while running() {
+        thread::sleep(AFTER_POLL);
+    }
+    run_actions(ctx, &name, then, cleanup_steps)
+}
+
+fn run_actions(
+    ctx: &Context,
+    name: &str,
+    then: &[PostExit],
+    cleanup_steps: &[CleanupStep],
+) -> Result<()> {
+    for action in then {
+        match action {
+            PostExit::Push => {
+                info!("Pushing {}", name);
+                ctx.git.push_branch(name)?;
+            }
+            PostExit::Merge => {
+                info!("Merging {} into the base branch", name);
+                ctx.git.merge_into_base(name)?;
+            }
+            PostExit::Rm => {
+                info!("Cleaning up {}", name);
+                cleanup(ctx, name, cleanup_steps)?;
+            }
+            PostExit::Gc => {
+                info!("Collecting expired worktrees");
+                gc::run(ctx, GcArgs { empty_trash: false })?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Zellij keeps a session's directories when the worktree under it is
+/// moved or removed, so attaching to it would land in a dead directory.
+/// Such a session is deleted, to be created again in `path`; one running
+/// in another repository under the same name is left alone.
+fn check_session_dir(ctx: &Context, session: &str, path: &Path) -> Result<()> {
+    if !ctx.zellij.sessions().iter().any(|s| s == session) {
+        return Ok(());
+    }
+    let Some(cwd) = zellij::session_cwd(session) else {
+        return Ok(());
+    };
+    let canonical = |dir: &Path| dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    if canonical(&cwd).starts_with(canonical(path)) {
+        return Ok(());
+    }
+    let ours = cwd.starts_with(ctx.git.root()) || cwd.starts_with(ctx.git.worktree_dir());
+    if cwd.exists() && !ours {
+        bail!(
+            "session {session} runs in {}, outside this repository; give each repository its own \
+             sessions with `[zellij] session_prefix = \"{{repo}}-\"`, or delete it with \
+             `zellij delete-session {session}`",
+            cwd.display()
+        );
+    }
+    let why = if cwd.exists() {
+        "not this worktree"
+    } else {
+        "gone"
+    };
+    warn!(
+        "session {} was started in {}, which is {}; recreating it in {}",
+        session,
+        cwd.display(),
+        why,
+        path.display()
+    );
+    ctx.zellij.delete_session(session)
+}
+
+/// `--layout`, else the `[policy]` rules' for `name`, else the `[layouts]`
+/// one for its purpose, else the `layout` setting, else
+/// `.graft/layout.kdl`. Names are looked up in `.graft/layouts/` before
+/// being read as paths: `--layout`'s relative to the current directory,
+/// the config's to the repository root. A configured layout that doesn't
+/// exist is reported rather than silently replaced; `--layout`'s is an
+/// error.
+fn repo_layout(ctx: &Context, name: &str) -> Result<PathBuf> {
+    let root = ctx.git.root();
+    let purpose = ctx
+        .state()?
+        .worktrees
+        .get(name)
+        .map(|record| record.purpose);
+    let Some(layout) = ctx
+        .layout
+        .clone()
+        .or_else(|| ctx.config.policy(name).layout)
+        .or_else(|| ctx.config.layouts.get(&purpose?).cloned())
+        .or_else(|| ctx.config.layout.clone())
+    else {
+        return Ok(root.join(REPO_DIR).join(LAYOUT_FILE));
+    };
+    // Paths on the command line are the user's, from where graft runs.
+    let base = match ctx.layout {
+        Some(_) => env::current_dir()?,
+        None => root.clone(),
+    };
+    let path = config::layout_file(&root, &base, &layout);
+    if !path.is_file() {
+        let missing = format!(
+            "layout {} is neither in {REPO_DIR}/{LAYOUTS_DIR}/ nor a file",
+            layout.display()
+        );
+        if ctx.layout.is_some() {
+            bail!(missing);
+        }
+        warn!("{missing}; starting a plain session");
+    }
+    Ok(path)
+}
+
+/// Runs `command`, or `$SHELL` when there is none, in `dir` and waits for
+/// it. The exit status is the user's business, like a session's.
+fn run_shell(
+    runner: &dyn ProcessRunner,
+    dir: &Path,
+    command: &[String],
+    env: &[(String, String)],
+) -> Result<()> {
+    let mut process = match command.split_first() {
+        Some((program, args)) => {
+            let mut process = Cmd::new(program);
+            process.args(args);
+            process
+        }
+        None => {
+            info!("Starting a shell in {}; exit it when done", dir.display());
+            Cmd::new(env::var_os("SHELL").unwrap_or_else(|| "sh".into()))
+        }
+    };
+    process.current_dir(dir);
+    for (key, value) in env {
+        process.env(key, value);
+    }
+    debug!("running {}", process);
+    runner
+        .status(&process)
+        .with_context(|| format!("failed to run {:?}", process.program()))?;
+    Ok(())
+}
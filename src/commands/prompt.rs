@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::Context;
+use crate::git::{self, Checkout};
+use crate::state;
+
+/// Remembered dirty flags, inside the state directory.
+const CACHE_FILE: &str = "prompt.json";
+/// How long a dirty flag is trusted while HEAD and the index stay the same.
+/// Edits to files that aren't staged change neither, so the working tree
+/// is scanned again this often.
+const CACHE_TTL: u64 = 10;
+
+/// A dirty flag, keyed in the cache by checkout path.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    head: Option<String>,
+    index_changed: Option<u64>,
+    checked_at: u64,
+    dirty: bool,
+}
+
+/// Prints e.g. `feature-x* ●`. Runs no git or zellij process: HEAD comes
+/// from the repository files, the session from `$ZELLIJ_SESSION_NAME` and
+/// the dirty marker from a cache. Prints nothing outside a repository.
+pub fn run(repo: Option<&Path>) -> Result<()> {
+    let dir = match repo {
+        Some(repo) => repo.to_path_buf(),
+        None => env::current_dir()?,
+    };
+    let Some(checkout) = git::checkout_at(&dir)? else {
+        return Ok(());
+    };
+    let ctx = Context::new(Some(&checkout.path))?;
+    let label = match (&checkout.branch, &checkout.head) {
+        (Some(branch), _) => branch.clone(),
+        (None, Some(head)) => head[..7].to_string(),
+        (None, None) => return Ok(()),
+    };
+
+    let mut segment = label.clone();
+    if dirty(&ctx, &checkout) {
+        segment.push('*');
+    }
+    if let Ok(current) = env::var("ZELLIJ_SESSION_NAME") {
+        let own = ctx.state()?.session(&label);
+        segment.push_str(if current == own { " ●" } else { " ○" });
+    }
+    println!("{segment}");
+    Ok(())
+}
+
+/// Whether `checkout` has uncommitted changes or untracked files, from the
+/// cache when it is fresh; otherwise scans and updates the cache. Cache
+/// failures only cost the scan.
+fn dirty(ctx: &Context, checkout: &Checkout) -> bool {
+    let state_dir = ctx.git.state_dir();
+    let path = state_dir.join(CACHE_FILE);
+    let mut cache: BTreeMap<PathBuf, Entry> = fs::read_to_string(&path)
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default();
+    let now = state::now();
+    if let Some(entry) = cache.get(&checkout.path)
+        && entry.head == checkout.head
+        && entry.index_changed == checkout.index_changed
+        && now.saturating_sub(entry.checked_at) < CACHE_TTL
+    {
+        return entry.dirty;
+    }
+
+    let dirty = ctx
+        .git
+        .inspect(&checkout.path)
+        .is_ok_and(|status| status.dirty_files + status.untracked_files > 0);
+    // Drop checkouts that are gone so the file doesn't grow forever.
+    cache.retain(|path, _| path.exists());
+    cache.insert(
+        checkout.path.clone(),
+        Entry {
+            head: checkout.head.clone(),
+            index_changed: checkout.index_changed,
+            checked_at: now,
+            dirty,
+        },
+    );
+    // Write-then-rename: prompts in several shells may race.
+    let tmp = path.with_extension(format!("json.{}", std::process::id()));
+    if let Ok(contents) = serde_json::to_string(&cache)
+        && fs::create_dir_all(&state_dir).is_ok()
+        && fs::write(&tmp, contents).is_ok()
+    {
+        let _ = fs::rename(&tmp, &path);
+    }
+    dirty
+}
@@ -0,0 +1,50 @@
+use anyhow::Result;
+use tracing::{info, warn};
+
+use super::Context;
+
+pub fn protect(ctx: &Context) -> Result<()> {
+    set(ctx, true)
+}
+
+pub fn unprotect(ctx: &Context) -> Result<()> {
+    set(ctx, false)
+}
+
+/// With `protect_main`, protects the main checkout while worktrees for
+/// branches exist and makes it writable again once the last one is gone.
+/// Problems are only warned about; they shouldn't stop the command.
+pub fn refresh(ctx: &Context) {
+    if !ctx.config.protect_main {
+        return;
+    }
+    let result = ctx.git.worktrees().and_then(|worktrees| {
+        let active = worktrees.iter().any(|worktree| worktree.branch.is_some());
+        if active != ctx.state()?.main_protected {
+            set(ctx, active)?;
+        }
+        Ok(())
+    });
+    if let Err(err) = result {
+        warn!("could not update the main checkout's protection: {err:#}");
+    }
+}
+
+fn set(ctx: &Context, read_only: bool) -> Result<()> {
+    let changed = ctx.git.set_main_read_only(read_only)?;
+    let mut state = ctx.state()?;
+    state.main_protected = read_only;
+    state.save()?;
+    if read_only {
+        info!(
+            "Made {} tracked file(s) in the main checkout read-only; `graft unprotect` undoes it",
+            changed
+        );
+    } else {
+        info!(
+            "Made {} file(s) in the main checkout writable again",
+            changed
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,126 @@
+//! `graft last` and `graft recent`: back and forth between worktrees the
+//! way `cd -` goes between directories, by the order graft last opened or
+//! attached to them.
+
+use std::env;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+use dialoguer::Select;
+
+use super::Context;
+use super::open::{attach, record};
+use crate::cli::{AttachMode, RecentArgs};
+use crate::state::{self, Purpose, State};
+use crate::units::format_age;
+use crate::zellij;
+
+pub fn last(ctx: &Context) -> Result<()> {
+    let state = ctx.state()?;
+    let current = current(ctx, &state)?;
+    let Some((name, path)) = used(ctx, &state)?
+        .into_iter()
+        .find(|(name, _)| Some(name) != current.as_ref())
+    else {
+        bail!("no other worktree used yet");
+    };
+    switch(ctx, &name, path)
+}
+
+pub fn recent(ctx: &Context, args: RecentArgs) -> Result<()> {
+    let state = ctx.state()?;
+    let current = current(ctx, &state)?;
+    let worktrees = used(ctx, &state)?;
+    if worktrees.is_empty() {
+        println!("No worktrees used yet.");
+        return Ok(());
+    }
+    let now = state::now();
+    let items: Vec<String> = worktrees
+        .iter()
+        .map(|(name, _)| {
+            let age = state
+                .worktrees
+                .get(name)
+                .and_then(|record| record.last_used)
+                .map(|used| format_age(now.saturating_sub(used)))
+                .unwrap_or_else(|| "?".to_string());
+            let marker = if Some(name) == current.as_ref() {
+                "*"
+            } else {
+                " "
+            };
+            format!("{marker} {name:<30} {age:>5}")
+        })
+        .collect();
+    if args.list || !io::stdin().is_terminal() {
+        for item in &items {
+            println!("{item}");
+        }
+        return Ok(());
+    }
+    let Some(choice) = Select::new()
+        .with_prompt("Switch to (Esc to stay)")
+        .items(&items)
+        .default(if current.is_some() && items.len() > 1 {
+            1
+        } else {
+            0
+        })
+        .interact_opt()?
+    else {
+        return Ok(());
+    };
+    let (name, path) = &worktrees[choice];
+    switch(ctx, name, path.clone())
+}
+
+/// Existing worktrees in the order they were last used, most recent first.
+fn used(ctx: &Context, state: &State) -> Result<Vec<(String, PathBuf)>> {
+    let worktrees = ctx.git.worktrees()?;
+    Ok(state
+        .recent
+        .iter()
+        .filter_map(|name| {
+            let worktree = worktrees.iter().find(|w| w.label() == name)?;
+            Some((name.clone(), worktree.path.clone()))
+        })
+        .collect())
+}
+
+/// The worktree graft runs in: the one whose session this is, else the
+/// one containing the working directory.
+fn current(ctx: &Context, state: &State) -> Result<Option<String>> {
+    if let Some(name) = zellij::current_session()
+        .as_deref()
+        .and_then(|session| state.name_for_session(session))
+    {
+        return Ok(Some(name.to_string()));
+    }
+    Ok(ctx
+        .git
+        .worktree_containing(&env::current_dir()?)?
+        .map(|worktree| worktree.label().to_string()))
+}
+
+/// Enters `name` the configured way. From inside Zellij, a running session
+/// is switched to rather than attached inside the current one.
+fn switch(ctx: &Context, name: &str, path: PathBuf) -> Result<()> {
+    let lock = ctx.lock()?;
+    record(ctx, name, &path, Purpose::infer(name))?;
+    drop(lock);
+    let mode = ctx.config.attach;
+    if mode == AttachMode::Zellij && zellij::current_session().is_some() {
+        let session = ctx.state()?.session(name);
+        let running = ctx
+            .zellij
+            .session_list()
+            .iter()
+            .any(|info| info.name == session && !info.exited);
+        if running {
+            return ctx.zellij.switch_session(&session);
+        }
+    }
+    attach(ctx, name, &path, mode, &[], &[], &[])
+}
@@ -0,0 +1,84 @@
+use anyhow::Result;
+
+use super::Context;
+use super::ls::prune_stale_sessions;
+use super::mv::rename_session;
+use crate::capabilities::Feature;
+
+pub fn run(ctx: &Context) -> Result<()> {
+    ctx.capabilities.require(Feature::WorktreeRepair)?;
+    let _lock = ctx.lock()?;
+    let git = &ctx.git;
+    let out = git.repair_worktrees()?;
+    // git prints one line per link it rewrote; nothing means all is well.
+    for line in out.lines().filter(|line| !line.trim().is_empty()) {
+        println!("{}", line.trim());
+    }
+
+    let mut state = ctx.state()?;
+    // A HEAD mangled beyond resolving goes back to the branch graft
+    // recorded the worktree for.
+    for worktree in git.worktrees()? {
+        let Some((name, _)) = state
+            .worktrees
+            .iter()
+            .find(|(_, record)| record.path == worktree.path)
+        else {
+            continue;
+        };
+        if git.repair_head(&worktree.name, name)? {
+            println!("Reset HEAD of {} to {}", worktree.path.display(), name);
+        }
+    }
+
+    let worktrees = git.worktrees()?;
+    let mut changed = false;
+    // A worktree switched to another branch behind graft's back is kept
+    // under that branch, and its session renamed to match.
+    let switched: Vec<(String, String)> = state
+        .worktrees
+        .iter()
+        .filter(|(name, _)| !worktrees.iter().any(|w| w.label() == name.as_str()))
+        .filter_map(|(name, record)| {
+            let worktree = worktrees.iter().find(|w| w.path == record.path)?;
+            let label = worktree.label();
+            (!state.worktrees.contains_key(label)).then(|| (name.clone(), label.to_string()))
+        })
+        .collect();
+    for (old, new) in switched {
+        if let Some((old_session, new_session)) = state.move_record(&old, &new) {
+            rename_session(ctx, &old_session, &new_session);
+        }
+        println!("Moved {old} to {new}: its worktree has {new} checked out");
+        changed = true;
+    }
+    state.worktrees.retain(|name, record| {
+        let current = worktrees.iter().find(|w| w.label() == name.as_str());
+        match current {
+            Some(worktree) if worktree.path != record.path => {
+                println!(
+                    "Updated {}: {} -> {}",
+                    name,
+                    record.path.display(),
+                    worktree.path.display()
+                );
+                record.path = worktree.path.clone();
+                changed = true;
+                true
+            }
+            Some(_) => true,
+            None => {
+                println!("Forgot {}: no matching worktree", name);
+                changed = true;
+                false
+            }
+        }
+    });
+    if changed {
+        state.save()?;
+    }
+
+    prune_stale_sessions(ctx, &worktrees, &ctx.zellij.sessions());
+    println!("Repair complete.");
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use tracing::info;
+
+use crate::cli::RepoCommand;
+use crate::git::Git;
+use crate::registry::Registry;
+
+pub fn run(repo: Option<&Path>, command: RepoCommand) -> Result<()> {
+    let mut registry = Registry::load()?;
+    match command {
+        RepoCommand::Add { path } => {
+            let path = path.as_deref().or(repo).unwrap_or(Path::new("."));
+            let root = Git::new(path)?.root();
+            if registry.add(root.clone()) {
+                registry.save()?;
+                info!("Registered {}", root.display());
+            } else {
+                info!("{} is already registered", root.display());
+            }
+        }
+        RepoCommand::Remove { path } => {
+            let path = path.as_deref().or(repo).unwrap_or(Path::new("."));
+            // The repository may be gone already; fall back to the path as typed.
+            let root = Git::new(path)
+                .map(|git| git.root())
+                .or_else(|_| std::path::absolute(path))?;
+            if !registry.remove(&root) {
+                bail!("{} is not registered", root.display());
+            }
+            registry.save()?;
+            info!("Unregistered {}", root.display());
+        }
+        RepoCommand::List => {
+            for root in &registry.repos {
+                let missing = if Git::new(root).is_ok() {
+                    ""
+                } else {
+                    " (missing)"
+                };
+                println!("{}{}", root.display(), missing);
+            }
+        }
+    }
+    Ok(())
+}
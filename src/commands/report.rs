@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use super::Context;
+use crate::cli::ReportArgs;
+use crate::state;
+use crate::timesheet;
+use crate::units::{format_hours, parse_duration};
+
+/// Time attached to one worktree over the reported period.
+#[derive(Serialize)]
+struct Row {
+    branch: String,
+    attaches: usize,
+    seconds: u64,
+}
+
+pub fn run(ctx: &Context, args: ReportArgs) -> Result<()> {
+    let now = state::now();
+    let since = match &args.since {
+        Some(since) => now.saturating_sub(parse_duration(since)?),
+        None => 0,
+    };
+    // Spans running across the start of the period count from there.
+    let mut totals: BTreeMap<String, Row> = BTreeMap::new();
+    for span in timesheet::load(&ctx.git.state_dir())? {
+        if span.end < since {
+            continue;
+        }
+        let row = totals.entry(span.name.clone()).or_insert_with(|| Row {
+            branch: span.name.clone(),
+            attaches: 0,
+            seconds: 0,
+        });
+        row.attaches += 1;
+        row.seconds += span.end.saturating_sub(span.start.max(since));
+    }
+    let mut rows: Vec<Row> = totals.into_values().collect();
+    rows.sort_by(|a, b| b.seconds.cmp(&a.seconds).then(a.branch.cmp(&b.branch)));
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+    if args.csv {
+        println!("branch,attaches,seconds");
+        for row in &rows {
+            println!(
+                "{},{},{}",
+                csv_field(&row.branch),
+                row.attaches,
+                row.seconds
+            );
+        }
+        return Ok(());
+    }
+    if rows.is_empty() {
+        println!("Nothing attached to in that time.");
+        return Ok(());
+    }
+    println!("{:<30} {:>8} {:>8}", "branch", "attaches", "time");
+    for row in &rows {
+        println!(
+            "{:<30} {:>8} {:>8}",
+            row.branch,
+            row.attaches,
+            format_hours(row.seconds)
+        );
+    }
+    let total: u64 = rows.iter().map(|row| row.seconds).sum();
+    println!("{:<30} {:>8} {:>8}", "total", "", format_hours(total));
+    Ok(())
+}
+
+/// `field` quoted when it holds a comma or quote, as branch names may.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
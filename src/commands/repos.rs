@@ -0,0 +1,79 @@
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+
+use anyhow::Result;
+use dialoguer::Select;
+
+use super::Context;
+use super::open::attach;
+use crate::cli::{AttachMode, ReposArgs};
+use crate::registry::Registry;
+use crate::units::format_age;
+
+/// Every registered repository with the graft sessions running in it, then
+/// a picker to attach to one of them; `switch` across repositories.
+pub fn run(args: ReposArgs) -> Result<()> {
+    let registry = Registry::load()?;
+    if registry.repos.is_empty() {
+        println!("No repositories registered; add one with `graft repo add`.");
+        return Ok(());
+    }
+
+    let mut sessions = None;
+    // (repository, worktree) for each running session, in listing order.
+    let mut targets: Vec<(PathBuf, String)> = Vec::new();
+    for (i, root) in registry.repos.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{}", root.display());
+        // Each context sets the session prefix, which may name the repository.
+        let ctx = match Context::new(Some(root)) {
+            Ok(ctx) => ctx,
+            Err(err) => {
+                println!("  unavailable: {}", err.root_cause());
+                continue;
+            }
+        };
+        // Sessions are global to the zellij server; ask once.
+        let sessions = sessions.get_or_insert_with(|| ctx.zellij.session_list());
+        let state = ctx.state()?;
+        let before = targets.len();
+        for worktree in ctx.git.worktrees()? {
+            let name = state.session(worktree.label());
+            let Some(info) = sessions.iter().find(|s| s.name == name && !s.exited) else {
+                continue;
+            };
+            let age = info.age.map(format_age).unwrap_or_default();
+            println!("  {:<30} {:<30} {}", worktree.label(), name, age);
+            targets.push((root.clone(), worktree.label().to_string()));
+        }
+        if targets.len() == before {
+            println!("  no running sessions");
+        }
+    }
+
+    if args.list || targets.is_empty() || !io::stdin().is_terminal() {
+        return Ok(());
+    }
+    let items: Vec<String> = targets
+        .iter()
+        .map(|(root, label)| {
+            let repo = root.file_name().unwrap_or_default().to_string_lossy();
+            format!("{repo}: {label}")
+        })
+        .collect();
+    println!();
+    let Some(choice) = Select::new()
+        .with_prompt("Switch to (Esc to stay)")
+        .items(&items)
+        .default(0)
+        .interact_opt()?
+    else {
+        return Ok(());
+    };
+    let (root, label) = &targets[choice];
+    let ctx = Context::new(Some(root))?;
+    let path = ctx.git.worktree_path(label);
+    attach(&ctx, label, &path, AttachMode::Zellij, &[], &[], &[])
+}
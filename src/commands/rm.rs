@@ -0,0 +1,402 @@
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+
+use anyhow::{Context as _, Result, bail};
+use dialoguer::{Confirm, MultiSelect};
+use tracing::{info, warn};
+
+use super::Context;
+use super::ls::sync_label;
+use super::protect;
+use super::summary::Summary;
+use crate::cli::{CleanupStep, RmArgs};
+use crate::config::{NestedRepos, glob_match};
+use crate::git::WorktreeInfo;
+use crate::hooks::{self, Hook};
+use crate::secrets;
+use crate::state::{self, TrashEntry};
+use crate::units::format_age;
+
+pub fn run(ctx: &Context, args: RmArgs) -> Result<()> {
+    let trash = args.trash || ctx.config.trash;
+    if args.interactive {
+        let targets = pick(ctx)?;
+        if targets.is_empty() {
+            return Ok(());
+        }
+        let question = format!("Remove {}?", targets.join(", "));
+        if !args.yes && !confirm(&question)? {
+            bail!("nothing removed");
+        }
+        return remove_all(ctx, &targets, args.delete_branch, trash, Summary::default());
+    }
+    if args.all {
+        return remove_every(ctx, &args, trash);
+    }
+    let targets = resolve(ctx, &args.tickets, args.by_session)?;
+
+    // A single branch named outright is removed without asking, as before.
+    let plain = args.tickets.len() == 1 && !is_pattern(&args.tickets[0]);
+    if !plain && !args.yes {
+        println!("Will remove:");
+        for target in &targets {
+            println!("  {:<30} {}", target, at_stake(ctx, target));
+        }
+        if !confirm(&format!("Remove {} worktree(s)?", targets.len()))? {
+            bail!("nothing removed");
+        }
+    }
+    if plain {
+        info!("Removing {} ({})", targets[0], at_stake(ctx, &targets[0]));
+        return remove(ctx, &targets[0], args.delete_branch, trash);
+    }
+    remove_all(ctx, &targets, args.delete_branch, trash, Summary::default())
+}
+
+/// Removes each of `targets`, carrying on past failures, and sums up along
+/// with what `summary` already holds.
+fn remove_all(
+    ctx: &Context,
+    targets: &[String],
+    delete_branch: bool,
+    trash: bool,
+    mut summary: Summary,
+) -> Result<()> {
+    for target in targets {
+        match remove(ctx, target, delete_branch, trash) {
+            Ok(()) if trash => summary.done(target, "moved to the trash"),
+            Ok(()) => summary.done(target, "removed"),
+            Err(err) => summary.failed(target, &err),
+        }
+    }
+    summary.finish(ctx.config.bulk_failure)
+}
+
+/// `--all`: every worktree and its session. Locked worktrees are skipped,
+/// and so are ones with uncommitted or untracked files unless `--force`,
+/// which still asks about them by name even with `--yes`.
+fn remove_every(ctx: &Context, args: &RmArgs, trash: bool) -> Result<()> {
+    let mut summary = Summary::default();
+    let mut clean = Vec::new();
+    let mut dirty = Vec::new();
+    let mut worktrees = ctx.git.worktrees()?;
+    worktrees.sort_by(|a, b| a.label().cmp(b.label()));
+    for worktree in &worktrees {
+        let label = worktree.label().to_string();
+        if ctx.git.lock_reason(&label)?.is_some() {
+            summary.skipped(&label, "locked");
+            continue;
+        }
+        // A checkout that is gone has nothing left to lose.
+        match ctx.git.inspect(&worktree.path) {
+            Ok(status) if status.dirty_files + status.untracked_files > 0 => {
+                let changes = format!(
+                    "{} changed, {} untracked",
+                    status.dirty_files, status.untracked_files
+                );
+                dirty.push((label, changes));
+            }
+            _ => clean.push(label),
+        }
+    }
+    if !args.force {
+        for (label, changes) in dirty.drain(..) {
+            summary.skipped(&label, format!("{changes}; --force to include"));
+        }
+    }
+    if clean.is_empty() && dirty.is_empty() {
+        println!("No worktrees to remove.");
+        return summary.finish(ctx.config.bulk_failure);
+    }
+
+    println!("Will remove:");
+    let listed = clean.iter().chain(dirty.iter().map(|(label, _)| label));
+    for label in listed {
+        println!("  {:<30} {}", label, at_stake(ctx, label));
+    }
+    let count = clean.len() + dirty.len();
+    if !args.yes && !confirm(&format!("Remove {count} worktree(s)?"))? {
+        bail!("nothing removed");
+    }
+    if !dirty.is_empty() {
+        if !io::stdin().is_terminal() {
+            bail!("--force asks before discarding changes, and there is no terminal to ask on");
+        }
+        let names: Vec<&str> = dirty.iter().map(|(label, _)| label.as_str()).collect();
+        let question = format!("Discard the changes in {}?", names.join(", "));
+        if confirm(&question)? {
+            clean.extend(dirty.into_iter().map(|(label, _)| label));
+        } else {
+            for (label, changes) in dirty {
+                summary.skipped(&label, format!("{changes}; kept"));
+            }
+        }
+    }
+    remove_all(ctx, &clean, args.delete_branch, trash, summary)
+}
+
+/// One line on what removing `ticket` would take with it: uncommitted and
+/// untracked files, stashes made on its branch, commits no remote has, and
+/// how its tip compares to the upstream.
+fn at_stake(ctx: &Context, ticket: &str) -> String {
+    let path = ctx.git.worktree_path(ticket);
+    let Ok(status) = ctx.git.inspect(&path) else {
+        return "missing".to_string();
+    };
+    let mut parts = Vec::new();
+    if status.dirty_files > 0 {
+        parts.push(format!("{} changed", status.dirty_files));
+    }
+    if status.untracked_files > 0 {
+        parts.push(format!("{} untracked", status.untracked_files));
+    }
+    let stashes = ctx.git.stash_count(ticket);
+    if stashes > 0 {
+        parts.push(format!("{stashes} stashed"));
+    }
+    let unpushed = ctx.git.unpushed(&path).unwrap_or(0);
+    if unpushed > 0 {
+        parts.push(format!("{unpushed} unpushed"));
+    }
+    parts.push(match sync_label(status.upstream) {
+        Some(sync) => format!("upstream {sync}"),
+        None => "no upstream".to_string(),
+    });
+    parts.join(", ")
+}
+
+/// Checklist of managed worktrees with their age and local changes.
+fn pick(ctx: &Context) -> Result<Vec<String>> {
+    if !io::stdin().is_terminal() {
+        bail!("--interactive needs a terminal");
+    }
+    let state = ctx.state()?;
+    let now = state::now();
+    let mut worktrees = ctx.git.worktrees()?;
+    worktrees.sort_by(|a, b| a.label().cmp(b.label()));
+    let mut items = Vec::new();
+    for worktree in &worktrees {
+        let label = worktree.label();
+        let age = state
+            .worktrees
+            .get(label)
+            .map(|record| format_age(now.saturating_sub(record.created_at)))
+            .unwrap_or_else(|| "?".to_string());
+        items.push(format!(
+            "{:<30} {:>5}  {}",
+            label,
+            age,
+            at_stake(ctx, label)
+        ));
+    }
+    if items.is_empty() {
+        println!("No worktrees to remove.");
+        return Ok(Vec::new());
+    }
+    let chosen = MultiSelect::new()
+        .with_prompt("Worktrees to remove (space to select, enter to confirm)")
+        .items(&items)
+        .interact()?;
+    Ok(chosen
+        .into_iter()
+        .map(|i| worktrees[i].label().to_string())
+        .collect())
+}
+
+/// Expands glob patterns against the managed worktrees and turns worktree
+/// paths, or session names with `by_session`, into the worktrees' names;
+/// branch names pass through unchanged. Every pattern must match something.
+fn resolve(ctx: &Context, tickets: &[String], by_session: bool) -> Result<Vec<String>> {
+    let worktrees = ctx.git.worktrees()?;
+    let state = ctx.state()?;
+    let mut targets: Vec<String> = Vec::new();
+    for ticket in tickets {
+        if by_session || !is_pattern(ticket) {
+            let target = if by_session {
+                worktrees
+                    .iter()
+                    .map(|worktree| worktree.label())
+                    .find(|label| state.session(label) == *ticket)
+                    .with_context(|| format!("no worktree has session '{ticket}'"))?
+                    .to_string()
+            } else {
+                by_path(&ctx.dir, &worktrees, ticket).unwrap_or_else(|| ticket.clone())
+            };
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+            continue;
+        }
+        let mut matches: Vec<&str> = worktrees
+            .iter()
+            .map(|worktree| worktree.label())
+            .filter(|label| glob_match(ticket, label))
+            .collect();
+        if matches.is_empty() {
+            bail!("no worktree matches '{ticket}'");
+        }
+        matches.sort();
+        for label in matches {
+            if !targets.iter().any(|t| t == label) {
+                targets.push(label.to_string());
+            }
+        }
+    }
+    Ok(targets)
+}
+
+/// The worktree checked out at `ticket` when it is a path (from `dir`)
+/// rather than a branch.
+fn by_path(dir: &Path, worktrees: &[WorktreeInfo], ticket: &str) -> Option<String> {
+    let path = dir.join(ticket).canonicalize().ok()?;
+    worktrees
+        .iter()
+        .find(|worktree| worktree.path.canonicalize().is_ok_and(|p| p == path))
+        .map(|worktree| worktree.label().to_string())
+}
+
+fn is_pattern(ticket: &str) -> bool {
+    ticket.contains(['*', '?'])
+}
+
+/// Asks a yes/no question on the terminal; refuses when there is none.
+fn confirm(question: &str) -> Result<bool> {
+    if !io::stdin().is_terminal() {
+        bail!("not a terminal; pass --yes to remove without asking");
+    }
+    Ok(Confirm::new()
+        .with_prompt(question)
+        .default(false)
+        .interact()?)
+}
+
+/// Kills the session and removes the worktree, or with `trash` moves it to
+/// `.worktrees/.trash` where `graft undo` can bring it back.
+pub fn remove(ctx: &Context, ticket: &str, delete_branch: bool, trash: bool) -> Result<()> {
+    let _lock = ctx.lock()?;
+    if let Some(reason) = ctx.git.lock_reason(ticket)? {
+        let reason = if reason.is_empty() {
+            String::new()
+        } else {
+            format!(" ({reason})")
+        };
+        bail!("{ticket} is locked{reason}; run `graft unlock {ticket}` to remove it");
+    }
+    // Before the hook runs, the session ends or the secrets are shredded, so
+    // a removal that is then refused leaves all of them alone.
+    let managed = ctx.state()?.worktrees.contains_key(ticket);
+    ctx.git.check_removable(ticket, managed)?;
+    let path = ctx.git.worktree_path(ticket);
+    if path.exists() {
+        hooks::run(
+            ctx.git.runner(),
+            &ctx.git.root(),
+            Hook::PreRemove,
+            ticket,
+            &path,
+            &ctx.worktree_env(ticket, &path),
+        )
+        .with_context(|| format!("not removing {ticket}"))?;
+        if !trash && ctx.config.nested_repos != NestedRepos::Skip {
+            warn_nested(ctx, ticket, &path);
+        }
+        // Trashed worktrees lose them too: the trash is not locked away.
+        let shredded = secrets::shred(&ctx.config.secrets.files, &path)
+            .with_context(|| format!("not removing {ticket}"))?;
+        if shredded > 0 {
+            info!("Shredded {shredded} secret file(s)");
+        }
+    }
+    end_session(ctx, ticket)?;
+    let mut state = ctx.state()?;
+    let record = state.worktrees.remove(ticket);
+    if trash && ctx.git.worktree_path(ticket).exists() {
+        let now = state::now();
+        let trash_path = ctx
+            .git
+            .trash_dir()
+            .join(format!("{}-{}", now, ticket.replace('/', "-")));
+        let (head, detached) = ctx.git.trash_worktree(ticket, &trash_path)?;
+        info!("Moved {} to {}", ticket, trash_path.display());
+        state.trash.push(TrashEntry {
+            name: ticket.to_string(),
+            path: ctx.git.worktree_path(ticket),
+            trash_path,
+            head,
+            detached,
+            trashed_at: now,
+            record,
+        });
+    }
+    // Clears leftovers too: the admin entry and empty parents.
+    ctx.git.remove_worktree(ticket, managed)?;
+    if delete_branch {
+        ctx.git.delete_branch(ticket)?;
+    }
+    if ctx.config.claims
+        && ctx.git.has_remote()
+        && let Err(err) = ctx.git.release_claim(ticket)
+    {
+        warn!("could not release the claim on {ticket}: {err:#}");
+    }
+    state.save()?;
+    protect::refresh(ctx);
+    Ok(())
+}
+
+/// Names the repositories inside the worktree that go with it while holding
+/// changes or commits nothing else has.
+fn warn_nested(ctx: &Context, ticket: &str, path: &Path) {
+    let nested = match ctx.git.nested_repos(path) {
+        Ok(nested) => nested,
+        Err(err) => return warn!("could not look for repositories inside {ticket}: {err:#}"),
+    };
+    for nested in nested.iter().filter(|nested| nested.has_work()) {
+        warn!(
+            "{} inside {ticket} is deleted with it: {} changed file(s), {} unpushed commit(s)",
+            nested.path.display(),
+            nested.changed_files,
+            nested.unpushed
+        );
+    }
+}
+
+/// Runs the post-exit cleanup `steps` for `name`.
+pub fn cleanup(ctx: &Context, name: &str, steps: &[CleanupStep]) -> Result<()> {
+    if steps.contains(&CleanupStep::Worktree) {
+        return remove(
+            ctx,
+            name,
+            steps.contains(&CleanupStep::Branch),
+            ctx.config.trash,
+        );
+    }
+    if steps.contains(&CleanupStep::Artifacts) {
+        ctx.git.clean_ignored(&ctx.git.worktree_path(name))?;
+    }
+    if steps.contains(&CleanupStep::Session) {
+        end_session(ctx, name)?;
+    }
+    if steps.contains(&CleanupStep::Branch) {
+        warn!("kept branch {name}; it is still checked out in its worktree");
+    }
+    Ok(())
+}
+
+/// Kills the session (adopted ones too) and drops its generated layout.
+fn end_session(ctx: &Context, name: &str) -> Result<()> {
+    let session = ctx.state()?.session(name);
+    if ctx.zellij.sessions().contains(&session) {
+        ctx.zellij.kill_session(&session)?;
+    }
+    let layout = ctx
+        .git
+        .state_dir()
+        .join("layouts")
+        .join(format!("{session}.kdl"));
+    if layout.exists() {
+        fs::remove_file(layout)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,216 @@
+//! `graft serve`: JSON-RPC 2.0 on a Unix socket, one request and one
+//! response per line, for editor extensions and scripts that would rather
+//! not scrape `ls`. Methods:
+//!
+//! - `list`: every worktree's status, as `graft status --json` has it
+//! - `status {branch}`: one worktree's
+//! - `open {branch}`: creates the worktree if needed (no session) and
+//!   returns its status; numbers and name prefixes work as with `graft
+//!   open`, commits, changes and pull requests don't
+//! - `remove {branch, force?, delete_branch?}`: refuses worktrees with
+//!   uncommitted or untracked files unless `force`
+//!
+//! Each connection gets a thread and context of its own; changes take the
+//! same lock as the command line, so they run one at a time.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::thread;
+
+use anyhow::{Context as _, Result, bail};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tracing::{info, warn};
+
+use super::target::Target;
+use super::{Context, open, rm, status};
+use crate::cli::{OpenArgs, ServeArgs};
+use crate::git::WorktreeInfo;
+
+const SOCKET_FILE: &str = "graft.sock";
+
+/// JSON-RPC error codes.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// The method ran and failed; the message says why.
+const FAILED: i64 = -32000;
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct BranchParams {
+    branch: String,
+    #[serde(default)]
+    force: bool,
+    #[serde(default)]
+    delete_branch: bool,
+}
+
+/// What to answer a failed request with: its JSON-RPC code and message.
+struct Failure(i64, String);
+
+impl From<anyhow::Error> for Failure {
+    fn from(err: anyhow::Error) -> Self {
+        Failure(FAILED, format!("{err:#}"))
+    }
+}
+
+/// Listens on `--socket`, by default `.git/graft/graft.sock`, until
+/// interrupted. A socket file nobody answers on is left from an earlier
+/// run and replaced.
+#[cfg(unix)]
+pub fn run(ctx: &Context, args: ServeArgs) -> Result<()> {
+    let socket = args
+        .socket
+        .unwrap_or_else(|| ctx.git.state_dir().join(SOCKET_FILE));
+    if socket.exists() {
+        if UnixStream::connect(&socket).is_ok() {
+            bail!("another graft serve is listening on {}", socket.display());
+        }
+        fs::remove_file(&socket)?;
+    }
+    if let Some(dir) = socket.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let listener = UnixListener::bind(&socket)
+        .with_context(|| format!("failed to listen on {}", socket.display()))?;
+    info!("Listening on {} (Ctrl-C to stop)", socket.display());
+    let root = ctx.git.root();
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!("connection failed: {err}");
+                continue;
+            }
+        };
+        let root = root.clone();
+        thread::spawn(move || {
+            if let Err(err) = serve(&root, stream) {
+                warn!("connection closed: {err:#}");
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn run(_ctx: &Context, _args: ServeArgs) -> Result<()> {
+    bail!("graft serve needs Unix sockets");
+}
+
+/// Answers requests on `stream` until the client hangs up.
+#[cfg(unix)]
+fn serve(root: &Path, stream: UnixStream) -> Result<()> {
+    let ctx = Context::new(Some(root))?;
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        writeln!(writer, "{}", respond(&ctx, &line))?;
+    }
+    Ok(())
+}
+
+fn respond(ctx: &Context, line: &str) -> Value {
+    let request: Request = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(err) => return error(Value::Null, Failure(PARSE_ERROR, err.to_string())),
+    };
+    if request.jsonrpc != "2.0" {
+        let failure = Failure(INVALID_REQUEST, "expected JSON-RPC 2.0".to_string());
+        return error(request.id, failure);
+    }
+    match call(ctx, &request.method, request.params) {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": request.id, "result": result }),
+        Err(failure) => error(request.id, failure),
+    }
+}
+
+fn error(id: Value, Failure(code, message): Failure) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn call(ctx: &Context, method: &str, params: Value) -> Result<Value, Failure> {
+    if method == "list" {
+        // In `ls` order.
+        let mut worktrees = ctx.git.worktrees()?;
+        worktrees.sort_by(|a, b| a.label().cmp(b.label()));
+        let mut statuses = Vec::new();
+        for worktree in worktrees {
+            statuses.push(status::status(ctx, worktree)?);
+        }
+        return Ok(json!(statuses));
+    }
+    if !matches!(method, "status" | "open" | "remove") {
+        return Err(Failure(METHOD_NOT_FOUND, format!("no method {method}")));
+    }
+    let params: BranchParams =
+        serde_json::from_value(params).map_err(|err| Failure(INVALID_PARAMS, err.to_string()))?;
+    let branch = params.branch;
+    match method {
+        "status" => Ok(json!(status::status(ctx, find(ctx, &branch)?)?)),
+        "open" => {
+            // Parsed like the command line, where a leading `-` is a flag.
+            if branch.is_empty() || branch.starts_with('-') {
+                let failure = Failure(INVALID_PARAMS, format!("'{branch}' is not a branch"));
+                return Err(failure);
+            }
+            // Numbers and prefixes name worktrees; the status is looked up
+            // under the branch they expand to.
+            let ticket = open::expand(ctx, branch.clone())?;
+            let Target::Branch { name, .. } = open::resolve(ctx, ticket.clone())? else {
+                let failure = Failure(
+                    INVALID_PARAMS,
+                    format!("'{branch}' is not a branch; open commits and changes with graft open"),
+                );
+                return Err(failure);
+            };
+            let args = OpenArgs::from_shorthand(vec![
+                ticket,
+                "--no-attach".to_string(),
+                "--yes".to_string(),
+            ]);
+            open::run(ctx, args)?;
+            Ok(json!(status::status(ctx, find(ctx, &name)?)?))
+        }
+        _ => {
+            let worktree = find(ctx, &branch)?;
+            if let Ok(changes) = ctx.git.inspect(&worktree.path)
+                && changes.dirty_files + changes.untracked_files > 0
+                && !params.force
+            {
+                let message = format!(
+                    "{branch} has {} changed and {} untracked file(s); pass force to remove it",
+                    changes.dirty_files, changes.untracked_files
+                );
+                return Err(Failure(FAILED, message));
+            }
+            rm::remove(ctx, &branch, params.delete_branch, ctx.config.trash)?;
+            Ok(json!({ "branch": branch, "removed": true }))
+        }
+    }
+}
+
+fn find(ctx: &Context, branch: &str) -> Result<WorktreeInfo, Failure> {
+    ctx.git
+        .worktrees()?
+        .into_iter()
+        .find(|worktree| worktree.label() == branch)
+        .ok_or_else(|| Failure(FAILED, format!("no worktree for {branch}")))
+}
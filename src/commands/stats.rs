@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+
+use super::Context;
+use crate::cli::StatsArgs;
+use crate::state;
+use crate::timings::{self, Sample};
+use crate::units::{format_age, format_millis};
+
+/// The operations `Context::timed` records, in the order they happen.
+const OPERATIONS: [&str; 3] = ["open", "fetch", "worktree-add"];
+
+pub fn run(ctx: &Context, args: StatsArgs) -> Result<()> {
+    if args.timings {
+        return print_timings(ctx);
+    }
+    let state = ctx.state()?;
+    let worktrees = ctx.git.worktrees()?;
+    let mut purposes: BTreeMap<String, usize> = BTreeMap::new();
+    for worktree in &worktrees {
+        let purpose = state
+            .worktrees
+            .get(worktree.label())
+            .map_or("untracked".to_string(), |record| record.purpose.to_string());
+        *purposes.entry(purpose).or_default() += 1;
+    }
+    let now = state::now();
+    let expired = state
+        .worktrees
+        .values()
+        .filter(|record| record.expires_at.is_some_and(|at| at <= now))
+        .count();
+
+    println!("{} worktree(s)", worktrees.len());
+    for (purpose, count) in &purposes {
+        println!("  {:<10} {}", purpose, count);
+    }
+    println!("{} expired, {} in the trash", expired, state.trash.len());
+    Ok(())
+}
+
+/// Per operation: sample count, latest, median, 90th percentile and how
+/// the newer half of the samples compares to the older half.
+fn print_timings(ctx: &Context) -> Result<()> {
+    let samples = timings::load(&ctx.git.state_dir())?;
+    if samples.is_empty() {
+        if ctx.config.timings {
+            println!("No timings recorded yet.");
+        } else {
+            println!("No timings recorded; set `timings = true` in the config to collect them.");
+        }
+        return Ok(());
+    }
+
+    let mut by_op: BTreeMap<&str, Vec<&Sample>> = BTreeMap::new();
+    for sample in &samples {
+        by_op.entry(&sample.op).or_default().push(sample);
+    }
+    let mut ops: Vec<&str> = OPERATIONS
+        .into_iter()
+        .filter(|op| by_op.contains_key(op))
+        .collect();
+    ops.extend(by_op.keys().filter(|op| !OPERATIONS.contains(op)));
+
+    let since = format_age(state::now().saturating_sub(samples[0].at));
+    println!("{} sample(s) over the last {}", samples.len(), since);
+    println!(
+        "{:<14} {:>7} {:>8} {:>8} {:>8}  trend",
+        "operation", "samples", "last", "median", "p90"
+    );
+    for op in ops {
+        let op_samples = &by_op[op];
+        let ms: Vec<u64> = op_samples.iter().map(|sample| sample.ms).collect();
+        let (older, newer) = ms.split_at(ms.len() / 2);
+        let trend = if older.len() < 2 {
+            "-".to_string()
+        } else {
+            let (before, after) = (median(older) as f64, median(newer) as f64);
+            format!("{:+.0}%", (after - before) / before.max(1.0) * 100.0)
+        };
+        println!(
+            "{:<14} {:>7} {:>8} {:>8} {:>8}  {}",
+            op,
+            ms.len(),
+            format_millis(ms[ms.len() - 1]),
+            format_millis(median(&ms)),
+            format_millis(percentile(&ms, 90)),
+            trend
+        );
+    }
+    Ok(())
+}
+
+fn median(values: &[u64]) -> u64 {
+    percentile(values, 50)
+}
+
+/// Nearest-rank percentile.
+fn percentile(values: &[u64], pct: usize) -> u64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
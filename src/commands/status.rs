@@ -0,0 +1,115 @@
+use std::env;
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+use serde::Serialize;
+
+use super::Context;
+use crate::cli::StatusArgs;
+use crate::git::WorktreeInfo;
+use crate::state::{self, Purpose};
+use crate::units::format_age;
+
+/// `graft status --json`, and `status` over `graft serve`. Times are Unix
+/// seconds.
+#[derive(Serialize)]
+pub(super) struct Status {
+    branch: String,
+    worktree: PathBuf,
+    /// Whether graft has a record of the worktree (it opened or adopted it).
+    managed: bool,
+    session: String,
+    base: String,
+    created_at: Option<u64>,
+    purpose: Option<Purpose>,
+    /// Scratch worktrees, removed when their session ends.
+    ephemeral: bool,
+    expires_at: Option<u64>,
+    dirty_files: usize,
+    untracked_files: usize,
+    /// Commits in nested repositories that no remote has.
+    nested_unpushed: usize,
+    /// Left with `graft note`, oldest first.
+    notes: Vec<String>,
+}
+
+pub fn run(ctx: &Context, args: StatusArgs) -> Result<()> {
+    let Some(worktree) = ctx.git.worktree_containing(&env::current_dir()?)? else {
+        bail!(
+            "not inside a worktree (the main checkout is {})",
+            ctx.git.root().display()
+        );
+    };
+    let status = status(ctx, worktree)?;
+
+    if args.json {
+        println!("{}", serde_json::to_string(&status)?);
+        return Ok(());
+    }
+    let now = state::now();
+    println!("branch    {}", status.branch);
+    println!("worktree  {}", status.worktree.display());
+    println!("session   {}", status.session);
+    println!("base      {}", status.base);
+    if !status.managed {
+        println!("          (not opened by graft)");
+        return print_changes(&status);
+    }
+    if let (Some(created_at), Some(purpose)) = (status.created_at, status.purpose) {
+        println!(
+            "created   {} ago, as {}",
+            format_age(now.saturating_sub(created_at)),
+            purpose
+        );
+    }
+    match status.expires_at {
+        Some(at) if at <= now => println!("expires   expired; `graft gc` removes it"),
+        Some(at) => println!("expires   in {}", format_age(at - now)),
+        None if status.ephemeral => println!("expires   when its session ends"),
+        None => println!("expires   never"),
+    }
+    for (i, note) in status.notes.iter().enumerate() {
+        let label = if i == 0 { "notes" } else { "" };
+        println!("{:<9} {}. {}", label, i + 1, note);
+    }
+    print_changes(&status)
+}
+
+fn print_changes(status: &Status) -> Result<()> {
+    println!(
+        "changes   {} modified, {} untracked",
+        status.dirty_files, status.untracked_files
+    );
+    if status.nested_unpushed > 0 {
+        println!(
+            "          {} unpushed commit(s) in nested repositories",
+            status.nested_unpushed
+        );
+    }
+    Ok(())
+}
+
+/// Where `worktree` stands.
+pub(super) fn status(ctx: &Context, worktree: WorktreeInfo) -> Result<Status> {
+    let state = ctx.state()?;
+    let branch = worktree.label().to_string();
+    let record = state.worktrees.get(&branch);
+    let changes = ctx.git.inspect(&worktree.path)?;
+    Ok(Status {
+        session: state.session(&branch),
+        managed: record.is_some(),
+        base: ctx.git.base_branch().to_string(),
+        created_at: record.map(|record| record.created_at),
+        purpose: record.map(|record| record.purpose),
+        ephemeral: record.is_some_and(|record| record.purpose == Purpose::Scratch),
+        expires_at: record.and_then(|record| record.expires_at),
+        dirty_files: changes.dirty_files,
+        untracked_files: changes.untracked_files,
+        nested_unpushed: changes.nested_unpushed,
+        notes: record
+            .map(|record| record.notes.clone())
+            .unwrap_or_default(),
+        branch,
+        worktree: worktree.path,
+    })
+}
@@ -0,0 +1,81 @@
+//! The closing table of commands that act on many worktrees at once (`gc`,
+//! `sync`, `rm` with several targets): what was done, skipped and failed,
+//! so the outcome doesn't have to be pieced together from the log above.
+
+use anyhow::{Error, Result, bail};
+
+use crate::config::BulkFailure;
+
+enum Outcome {
+    Done,
+    Skipped,
+    Failed,
+}
+
+#[derive(Default)]
+pub struct Summary {
+    rows: Vec<(String, Outcome, String)>,
+}
+
+impl Summary {
+    pub fn done(&mut self, name: &str, detail: impl Into<String>) {
+        self.rows
+            .push((name.to_string(), Outcome::Done, detail.into()));
+    }
+
+    pub fn skipped(&mut self, name: &str, reason: impl Into<String>) {
+        self.rows
+            .push((name.to_string(), Outcome::Skipped, reason.into()));
+    }
+
+    pub fn failed(&mut self, name: &str, err: &Error) {
+        self.rows
+            .push((name.to_string(), Outcome::Failed, format!("{err:#}")));
+    }
+
+    fn count(&self, outcome: fn(&Outcome) -> bool) -> usize {
+        self.rows.iter().filter(|(_, o, _)| outcome(o)).count()
+    }
+
+    /// Prints the table, then fails as `policy` says.
+    pub fn finish(self, policy: BulkFailure) -> Result<()> {
+        self.print();
+        self.verdict(policy)
+    }
+
+    /// The table; nothing when there was nothing to do.
+    pub fn print(&self) {
+        if self.rows.is_empty() {
+            return;
+        }
+        let done = self.count(|o| matches!(o, Outcome::Done));
+        let skipped = self.count(|o| matches!(o, Outcome::Skipped));
+        let failed = self.count(|o| matches!(o, Outcome::Failed));
+        println!("\n{done} succeeded, {skipped} skipped, {failed} failed");
+        for (name, outcome, detail) in &self.rows {
+            let label = match outcome {
+                Outcome::Done => "ok",
+                Outcome::Skipped => "skipped",
+                Outcome::Failed => "FAILED",
+            };
+            println!("  {:<30} {:<8} {}", name, label, detail);
+        }
+    }
+
+    /// Whether the run failed under `policy`, as an error saying how.
+    pub fn verdict(&self, policy: BulkFailure) -> Result<()> {
+        let skipped = self.count(|o| matches!(o, Outcome::Skipped));
+        let failed = self.count(|o| matches!(o, Outcome::Failed));
+        let total = self.rows.len();
+        match policy {
+            BulkFailure::AnySkipped if failed + skipped > 0 => {
+                bail!("{} of {total} failed or were skipped", failed + skipped)
+            }
+            BulkFailure::AnyFailed | BulkFailure::AnySkipped if failed > 0 => {
+                bail!("{failed} of {total} failed")
+            }
+            BulkFailure::AllFailed if total > 0 && failed == total => bail!("all {total} failed"),
+            _ => Ok(()),
+        }
+    }
+}
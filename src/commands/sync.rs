@@ -0,0 +1,126 @@
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use dialoguer::Select;
+use tracing::info;
+
+use super::Context;
+use super::open::attach;
+use super::summary::Summary;
+use crate::git::{Blockers, Git};
+use crate::progress;
+
+pub fn run(ctx: &Context) -> Result<()> {
+    let git = &ctx.git;
+    info!("Fetching...");
+    ctx.timed("fetch", || progress::spin("Fetching", || git.fetch_prune()))?;
+
+    let mut summary = Summary::default();
+    let mut blocked = Vec::new();
+    for worktree in git.worktrees()? {
+        let Some(branch) = worktree.branch else {
+            continue;
+        };
+        if !worktree.path.exists() {
+            continue;
+        }
+
+        match update(git, &worktree.path) {
+            Ok((outcome, true)) => summary.done(&branch, outcome),
+            Ok((outcome, false)) => {
+                summary.skipped(&branch, outcome);
+                blocked.push((branch.clone(), worktree.path.clone()));
+            }
+            Err(err) => summary.failed(&branch, &err),
+        }
+    }
+    summary.print();
+
+    if !blocked.is_empty() {
+        println!("\nCould not update:");
+        for (branch, path) in &blocked {
+            report(git, branch, path);
+        }
+        offer_session(ctx, &blocked)?;
+    }
+    summary.verdict(ctx.config.bulk_failure)
+}
+
+/// What stands between one worktree and its upstream, file by file.
+fn report(git: &Git, branch: &str, path: &Path) {
+    let blockers = match git.blockers(path) {
+        Ok(Some(blockers)) => blockers,
+        Ok(None) => return println!("  {branch}"),
+        Err(err) => return println!("  {branch}: could not inspect: {err:#}"),
+    };
+    let Blockers {
+        ahead,
+        behind,
+        conflicts,
+        overlapping,
+    } = blockers;
+    if ahead > 0 {
+        println!("  {branch}: diverged, {ahead} local and {behind} upstream commit(s)");
+        if conflicts.is_empty() {
+            println!("    merges cleanly: run `git merge` or `git rebase` in the worktree");
+        } else {
+            println!("    merging would conflict in: {}", conflicts.join(", "));
+        }
+    } else {
+        println!("  {branch}: {behind} upstream commit(s), held back by local changes");
+    }
+    if !overlapping.is_empty() {
+        println!(
+            "    local changes the upstream also touches: {}",
+            overlapping.join(", ")
+        );
+    } else if ahead == 0 {
+        println!("    the upstream doesn't touch the changed files: commit or stash, then sync");
+    }
+}
+
+/// Lets the user jump into one of the blocked worktrees to sort it out.
+fn offer_session(ctx: &Context, blocked: &[(String, PathBuf)]) -> Result<()> {
+    if !io::stdin().is_terminal() {
+        println!("\nOpen one with `graft <branch>` to resolve it.");
+        return Ok(());
+    }
+    let mut items: Vec<&str> = blocked.iter().map(|(branch, _)| branch.as_str()).collect();
+    items.push("none");
+    let choice = Select::new()
+        .with_prompt("Open a worktree to resolve?")
+        .items(&items)
+        .default(items.len() - 1)
+        .interact()?;
+    match blocked.get(choice) {
+        Some((branch, path)) => attach(ctx, branch, path, ctx.config.attach, &[], &[], &[]),
+        None => Ok(()),
+    }
+}
+
+/// Fast-forwards a clean worktree to its (already fetched) upstream.
+/// Returns a description of what happened and `false` if the worktree
+/// needed updating but couldn't be.
+pub fn update(git: &Git, path: &Path) -> Result<(String, bool)> {
+    let status = git.inspect(path)?;
+    Ok(match status.upstream {
+        None => ("no upstream".to_string(), true),
+        Some((_, 0)) => ("up to date".to_string(), true),
+        Some((ahead, behind)) if ahead > 0 => (
+            format!("diverged (ahead {ahead}, behind {behind}), not updated"),
+            false,
+        ),
+        Some((_, behind)) if status.dirty_files > 0 => (
+            format!(
+                "behind {behind} but has {} local change(s), not updated",
+                status.dirty_files
+            ),
+            false,
+        ),
+        Some((_, behind)) => match git.fast_forward(path) {
+            Ok(()) => (format!("fast-forwarded {behind} commit(s)"), true),
+            Err(err) => (format!("fast-forward failed: {err}"), false),
+        },
+    })
+}
@@ -0,0 +1,119 @@
+//! `graft take`: work started in the wrong checkout (on `main`, usually)
+//! moves to a new branch and worktree of its own. The changes go through
+//! the stash, untracked files included, so the checkout they came from is
+//! left clean.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as _, Result, bail};
+use tracing::{info, warn};
+
+use super::Context;
+use super::open::{attach, record};
+use crate::cli::TakeArgs;
+use crate::git;
+use crate::hooks;
+use crate::progress;
+use crate::state::Purpose;
+
+pub fn run(ctx: &Context, args: TakeArgs) -> Result<()> {
+    let branch = args.branch;
+    let source = match ctx.git.worktree_containing(&env::current_dir()?)? {
+        Some(worktree) => worktree.path,
+        None => ctx.git.root(),
+    };
+    if ctx.git.has_branch(&branch) {
+        bail!("{branch} already exists; graft take starts a new branch");
+    }
+    let runner = ctx.git.runner();
+    let changes = git::run_ok_in(runner, &source, &["status", "--porcelain"])?;
+    if changes.trim().is_empty() {
+        bail!("no uncommitted changes in {} to take", source.display());
+    }
+
+    let lock = ctx.lock()?;
+    // The new branch starts where the changes were made, so they apply.
+    let head = git::run_ok_in(runner, &source, &["rev-parse", "HEAD"])?;
+    let message = format!("graft take {branch}");
+    git::run_ok_in(
+        runner,
+        &source,
+        &[
+            "stash",
+            "push",
+            "--include-untracked",
+            "--message",
+            &message,
+        ],
+    )?;
+    let stash = git::run_ok_in(runner, &source, &["rev-parse", "refs/stash"])?
+        .trim()
+        .to_string();
+    let path = match check_out(ctx, &branch, head.trim()) {
+        Ok(path) => path,
+        Err(err) => {
+            if let Err(restore) = unstash(ctx, &source, &stash) {
+                warn!("could not put the changes back: {restore:#}; they are in stash {stash}");
+            }
+            return Err(err);
+        }
+    };
+    unstash(ctx, &path, &stash).with_context(|| {
+        format!(
+            "could not apply the changes in {}; they are in stash {stash}, and \
+             `git stash pop` in {} puts them back",
+            path.display(),
+            source.display()
+        )
+    })?;
+    info!(
+        "Moved the uncommitted changes from {} to {branch}",
+        source.display()
+    );
+
+    if let Err(err) = hooks::post_create(
+        runner,
+        &ctx.git.root(),
+        &ctx.config.secrets,
+        &branch,
+        &path,
+        &ctx.worktree_env(&branch, &path),
+    ) {
+        warn!("{err:#}");
+    }
+    record(ctx, &branch, &path, Purpose::infer(&branch))?;
+    drop(lock);
+    attach(ctx, &branch, &path, ctx.config.attach, &[], &[], &[])
+}
+
+/// Creates `branch` at `head` and its worktree, deleting the branch again
+/// if the worktree can't be made.
+fn check_out(ctx: &Context, branch: &str, head: &str) -> Result<PathBuf> {
+    ctx.git
+        .create_branch_at(branch, ctx.git.resolve_commit(head)?)?;
+    let created = progress::spin(&format!("Checking out {branch}"), || {
+        ctx.git.ensure_worktree(branch)
+    })
+    .map(|worktree| worktree.path().to_path_buf());
+    if created.is_err()
+        && let Err(err) = ctx.git.delete_branch(branch)
+    {
+        warn!("could not delete {branch} again: {err:#}");
+    }
+    created
+}
+
+/// Applies `stash` in `dir`, staged changes staged again, then drops it
+/// if it is still the newest entry.
+fn unstash(ctx: &Context, dir: &Path, stash: &str) -> Result<()> {
+    let runner = ctx.git.runner();
+    git::run_ok_in(runner, dir, &["stash", "apply", "--index", stash])?;
+    let newest = git::run_ok_in(runner, dir, &["rev-parse", "refs/stash"])?;
+    if newest.trim() == stash {
+        git::run_ok_in(runner, dir, &["stash", "drop", "--quiet"])?;
+    } else {
+        warn!("left stash {stash} in place: newer entries were pushed meanwhile");
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+//! What `graft open` was pointed at, when it is more than a branch name:
+//! a full ref, a remote-tracking name, a pull request number, or a URL
+//! copied from a browser.
+
+use anyhow::{Result, bail};
+
+use crate::config::Forge;
+
+/// An open target, normalized.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Target {
+    /// A branch, with the remote it was named through (`origin/foo`).
+    Branch {
+        name: String,
+        remote: Option<String>,
+    },
+    /// A tag or commit, for a detached worktree.
+    Commit(String),
+    /// A pull or merge request, with the forge its URL names; `None` for
+    /// `pr:<n>`, which the config or the remote's URL decides.
+    PullRequest { number: u64, forge: Option<Forge> },
+    /// A Gerrit change URL, for `graft change`.
+    Change(String),
+}
+
+/// Normalizes `input`. `remotes` are the configured remote names, so
+/// `origin/foo` is told apart from a branch that merely contains a slash.
+/// `pr:<n>` and `mr:<n>` (GitLab's) can't be branches: `:` isn't allowed in
+/// their names.
+pub fn parse(input: &str, remotes: &[String]) -> Result<Target> {
+    let input = input.trim();
+    if let Some(rest) = input
+        .strip_prefix("https://")
+        .or_else(|| input.strip_prefix("http://"))
+    {
+        return parse_url(input, rest);
+    }
+    if let Some((kind @ ("pr" | "mr"), number)) = input.split_once(':') {
+        let Ok(number) = number.parse() else {
+            bail!("{input}: expected a number after {kind}:");
+        };
+        let forge = (kind == "mr").then_some(Forge::Gitlab);
+        return Ok(Target::PullRequest { number, forge });
+    }
+    if let Some(name) = input.strip_prefix("refs/heads/") {
+        return Ok(branch(name, None));
+    }
+    if input.starts_with("refs/tags/") {
+        return Ok(Target::Commit(input.to_string()));
+    }
+    let tracking = input.strip_prefix("refs/remotes/").unwrap_or(input);
+    for remote in remotes {
+        if let Some(name) = tracking
+            .strip_prefix(remote.as_str())
+            .and_then(|rest| rest.strip_prefix('/'))
+            .filter(|name| !name.is_empty())
+        {
+            return Ok(branch(name, Some(remote)));
+        }
+    }
+    Ok(branch(input, None))
+}
+
+fn branch(name: &str, remote: Option<&String>) -> Target {
+    Target::Branch {
+        name: name.to_string(),
+        remote: remote.cloned(),
+    }
+}
+
+/// GitHub and GitLab commit and branch URLs, GitHub and Gitea pull
+/// requests, GitLab merge requests and Gerrit changes.
+fn parse_url(input: &str, rest: &str) -> Result<Target> {
+    let path = rest.split(['?', '#']).next().unwrap_or(rest);
+    if path.contains("/+/") {
+        return Ok(Target::Change(input.to_string()));
+    }
+    // Host, owner, repository; GitLab puts a `-` before its own pages.
+    let segments: Vec<&str> = path
+        .split('/')
+        .skip(3)
+        .skip_while(|segment| *segment == "-")
+        .collect();
+    Ok(match segments.as_slice() {
+        ["pull", number, ..] if let Ok(number) = number.parse() => Target::PullRequest {
+            number,
+            forge: Some(Forge::Github),
+        },
+        ["pulls", number, ..] if let Ok(number) = number.parse() => Target::PullRequest {
+            number,
+            forge: Some(Forge::Gitea),
+        },
+        ["merge_requests", number, ..] if let Ok(number) = number.parse() => Target::PullRequest {
+            number,
+            forge: Some(Forge::Gitlab),
+        },
+        ["commit" | "commits", sha, ..] if is_sha(sha) => Target::Commit(sha.to_string()),
+        ["tree", name @ ..] if !name.is_empty() => branch(&name.join("/"), None),
+        _ => bail!("{input} is not a pull request, commit or branch URL"),
+    })
+}
+
+/// The forge a remote URL points at, from well-known host names: GitLab
+/// and Gitea instances usually carry theirs in the host name.
+pub fn detect_forge(url: &str) -> Option<Forge> {
+    let url = url.to_ascii_lowercase();
+    let host = match url.split_once("://") {
+        Some((_, rest)) => rest.split('/').next().unwrap_or(rest),
+        // scp-like `git@host:owner/repo`.
+        None => url.split(':').next().unwrap_or(&url),
+    };
+    if host.contains("gitlab") {
+        Some(Forge::Gitlab)
+    } else if ["gitea", "forgejo", "codeberg"]
+        .iter()
+        .any(|name| host.contains(name))
+    {
+        Some(Forge::Gitea)
+    } else if host.contains("github") {
+        Some(Forge::Github)
+    } else {
+        None
+    }
+}
+
+fn is_sha(text: &str) -> bool {
+    (7..=40).contains(&text.len()) && text.chars().all(|c| c.is_ascii_hexdigit())
+}
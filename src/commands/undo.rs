@@ -0,0 +1,44 @@
+use anyhow::{Result, bail};
+use tracing::{info, warn};
+
+use super::Context;
+use super::protect;
+use crate::secrets;
+
+pub fn run(ctx: &Context) -> Result<()> {
+    let _lock = ctx.lock()?;
+    let mut state = ctx.state()?;
+    let Some(entry) = state.trash.pop() else {
+        bail!("the trash is empty; nothing to undo");
+    };
+    if ctx.git.worktree_path(&entry.name).exists() {
+        bail!(
+            "{} already exists again; its trashed copy is still at {}",
+            entry.path.display(),
+            entry.trash_path.display()
+        );
+    }
+
+    let path =
+        ctx.git
+            .restore_worktree(&entry.name, &entry.head, entry.detached, &entry.trash_path)?;
+    if let Some(mut record) = entry.record {
+        record.path = path.clone();
+        state.worktrees.insert(entry.name.clone(), record);
+    }
+    state.save()?;
+    info!("Restored {} at {}", entry.name, path.display());
+    // Removal shredded them.
+    if let Err(err) = secrets::provision(
+        ctx.git.runner(),
+        &ctx.config.secrets,
+        &ctx.git.root(),
+        &entry.name,
+        &path,
+        &ctx.worktree_env(&entry.name, &path),
+    ) {
+        warn!("{err:#}");
+    }
+    protect::refresh(ctx);
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use tracing::{info, warn};
+
+use super::Context;
+use super::open::record;
+use super::sync::update;
+use crate::cli::WatchArgs;
+use crate::state::Purpose;
+
+/// Follows a remote branch: fetches every `--interval` seconds and
+/// fast-forwards the worktree while it is clean. Runs until interrupted.
+pub fn run(ctx: &Context, args: WatchArgs) -> Result<()> {
+    let git = &ctx.git;
+    let branch = &args.branch;
+    let lock = ctx.lock()?;
+    if !git.has_branch(branch) {
+        git.ensure_branch(branch, true, Some(git.fetch_remote()))?;
+    }
+    let worktree = git.ensure_worktree(branch)?;
+    let path = worktree.path();
+    if git.inspect(path)?.upstream.is_none() {
+        bail!("{branch} has no upstream to follow; set one with `git branch -u`");
+    }
+    record(ctx, branch, path, Purpose::infer(branch))?;
+    drop(lock);
+
+    info!(
+        "Watching {} in {} every {}s (Ctrl-C to stop)",
+        branch,
+        path.display(),
+        args.interval
+    );
+    let mut last = String::new();
+    loop {
+        let outcome =
+            match git.fetch_branch(&git.upstream_remote(branch), &git.upstream_branch(branch)) {
+                Ok(()) => update(git, path),
+                Err(err) => Err(err),
+            };
+        // Only report changes, so a quiet branch keeps a quiet terminal.
+        match outcome {
+            Ok((message, updated)) if message != last => {
+                if updated {
+                    info!("{branch}: {message}");
+                } else {
+                    // Ring the terminal bell: this one needs a human.
+                    warn!("\x07{branch}: {message}");
+                }
+                last = message;
+            }
+            Ok(_) => {}
+            Err(err) => warn!("{branch}: update failed: {err:#}"),
+        }
+        thread::sleep(Duration::from_secs(args.interval));
+    }
+}
@@ -0,0 +1,103 @@
+//! `graft zellij-pipe`: graft from a Zellij keybinding. The binding runs it
+//! in a floating pane, where it asks for a branch; a branch can also be
+//! piped in or passed. It creates the worktree and moves the client to the
+//! branch's session, started in the background if needed, or with `--tab`
+//! (or `session_strategy = "tabs"`) to a tab of the current session.
+
+use std::io::{self, BufRead, IsTerminal};
+
+use anyhow::{Result, bail};
+use dialoguer::Input;
+
+use super::Context;
+use super::open::{self, expand};
+use crate::cli::{OpenArgs, ZellijPipeArgs};
+use crate::config::SessionStrategy;
+use crate::zellij;
+
+/// For the user's `config.kdl`; printed by `--keybinding`.
+const KEYBINDING: &str = r#"// graft: Alt g asks for a branch and switches to its worktree's session.
+keybinds {
+    shared {
+        bind "Alt g" {
+            Run "graft" "zellij-pipe" {
+                floating true
+                close_on_exit true
+                name "graft"
+            }
+        }
+    }
+}"#;
+
+pub fn run(ctx: &Context, args: ZellijPipeArgs) -> Result<()> {
+    if args.keybinding {
+        println!("{KEYBINDING}");
+        return Ok(());
+    }
+    let interactive = args.branch.is_none() && io::stdin().is_terminal();
+    let result = switch(ctx, args);
+    // The pane closes when graft exits, taking the error with it.
+    if interactive && let Err(err) = &result {
+        eprintln!("error: {err:#}\n(Enter to close)");
+        let _ = io::stdin().lock().read_line(&mut String::new());
+    }
+    result
+}
+
+fn switch(ctx: &Context, args: ZellijPipeArgs) -> Result<()> {
+    if zellij::current_session().is_none() {
+        bail!("zellij-pipe runs inside Zellij; outside it, use `graft <branch>`");
+    }
+    let branch = match args.branch {
+        Some(branch) => branch,
+        None if io::stdin().is_terminal() => Input::<String>::new()
+            .with_prompt("Branch")
+            .interact_text()?,
+        None => {
+            let mut lines = io::stdin().lock().lines();
+            lines.next().transpose()?.unwrap_or_default()
+        }
+    };
+    let branch = branch.trim();
+    if branch.is_empty() {
+        return Ok(());
+    }
+    // Parsed like the command line, where a leading `-` is a flag.
+    if branch.starts_with('-') {
+        bail!("'{branch}' is not a branch");
+    }
+    let name = expand(ctx, branch.to_string())?;
+    open::run(
+        ctx,
+        OpenArgs::from_shorthand(vec![
+            name.clone(),
+            "--no-attach".to_string(),
+            "--yes".to_string(),
+        ]),
+    )?;
+    let Some(worktree) = ctx
+        .git
+        .worktrees()?
+        .into_iter()
+        .find(|worktree| worktree.label() == name)
+    else {
+        bail!("opened {branch}, but found no worktree named {name}");
+    };
+    if args.tab || ctx.config.session_strategy == SessionStrategy::Tabs {
+        return ctx.zellij.open_tab(&name, &worktree.path);
+    }
+    let session = ctx.state()?.session(&name);
+    let running = ctx
+        .zellij
+        .session_list()
+        .iter()
+        .any(|info| info.name == session && !info.exited);
+    if !running {
+        ctx.zellij.start_background(
+            &session,
+            &worktree.path,
+            &ctx.worktree_env(&name, &worktree.path),
+        )?;
+    }
+    ctx.zellij.switch_session(&session)
+}
@@ -0,0 +1,653 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use toml::Table;
+
+use crate::cli::{AttachMode, CleanupStep, ExistingDir};
+use crate::state::Purpose;
+use crate::zellij;
+
+pub const REPO_CONFIG_FILE: &str = ".graft.toml";
+/// Shared per-repository files: `layout.kdl`, `layouts/` and `hooks/`.
+pub const REPO_DIR: &str = ".graft";
+/// Layout for new sessions, inside [`REPO_DIR`].
+pub const LAYOUT_FILE: &str = "layout.kdl";
+/// Named layouts, `<name>.kdl`, inside [`REPO_DIR`].
+pub const LAYOUTS_DIR: &str = "layouts";
+
+/// Effective configuration. Later sources win: the user config, the
+/// repository's `.graft.toml`, then `GRAFT_*` environment variables (see
+/// [`ENV_OVERRIDES`]); command-line flags override all of them. Missing keys
+/// fall back to the defaults below.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Set `branch.<name>.remote`/`merge` on branches graft creates.
+    pub track_upstream: bool,
+
+    /// Remote to fetch branches and tags from (default `origin`); set to
+    /// `upstream` in fork workflows.
+    pub fetch_remote: Option<String>,
+
+    /// Remote to push branches to; defaults to git's `remote.pushDefault`,
+    /// then to `fetch_remote`.
+    pub push_remote: Option<String>,
+
+    /// Never ask remotes whether a branch exists: `graft open` uses or
+    /// creates local branches only and skips claims, as `--offline` does.
+    pub offline: bool,
+
+    /// How long a remote gets to say whether it has a branch before
+    /// `graft open` carries on offline.
+    pub remote_timeout: String,
+
+    /// How long a fetch may take before it is stopped.
+    pub fetch_timeout: String,
+
+    /// How often a fetch or lookup that timed out or lost the network is
+    /// tried again, waiting 1s, 2s, 4s, ... in between. Other failures
+    /// (a missing ref, refused credentials) are not retried.
+    pub remote_retries: u32,
+
+    /// Name of a branch on remotes when it differs from the local one, e.g.
+    /// `users/{user}/{branch}` where pushing to the plain name is forbidden.
+    /// `{user}` is the local part of `user.email`.
+    pub remote_branch: Option<String>,
+
+    /// How `graft open` enters worktrees: `zellij`, `shell` (no multiplexer)
+    /// or `none` (print the path).
+    pub attach: AttachMode,
+
+    /// `sessions` gives every worktree a Zellij session of its own; `tabs`
+    /// opens worktrees as tabs of the session graft runs in, when it runs
+    /// in one.
+    pub session_strategy: SessionStrategy,
+
+    /// Where worktrees go instead of `.worktrees/<branch>`, e.g.
+    /// `~/worktrees/{repo}/{branch}`. Relative paths start at the repository
+    /// root; include `{repo}` when several repositories share the directory.
+    pub worktree_root: Option<String>,
+
+    /// Layout for new sessions instead of `.graft/layout.kdl`: the name of
+    /// one in `.graft/layouts/`, else a path relative to the repository
+    /// root.
+    pub layout: Option<PathBuf>,
+
+    /// Shell command run in the first pane of new sessions, e.g. `nvim .`.
+    pub default_command: Option<String>,
+
+    /// Command opening a worktree for `--editor` and `attach = "editor"`,
+    /// e.g. `code --new-window`. The path is appended, or goes where
+    /// `{path}` is; `$VISUAL`, then `$EDITOR` when unset.
+    pub editor: Option<String>,
+
+    /// Budget for all worktrees together (`20G`), enforced by `graft clean`.
+    pub max_total_size: Option<String>,
+
+    /// Announce opened branches to the team through `refs/graft/claims/*`
+    /// on the remote, and show other people's claims in `graft ls`.
+    pub claims: bool,
+
+    /// What `graft open` does when `.worktrees/<branch>` exists but isn't a
+    /// worktree: `fail`, `adopt` or `move`.
+    pub existing_dir: ExistingDir,
+
+    /// On entering a worktree, show where it stands: commits versus the
+    /// base and upstream, local changes and `graft note`s. Shells print it;
+    /// zellij sessions get it in a floating pane, closed with Enter.
+    pub banner: bool,
+
+    /// Default `--ttl` per purpose, e.g. `[ttl]` with `review = "7d"`.
+    pub ttl: BTreeMap<Purpose, String>,
+
+    /// Layout for new sessions per purpose, e.g. `[layouts]` with
+    /// `review = "review"`; between `[policy]` rules and `layout`.
+    pub layouts: BTreeMap<Purpose, PathBuf>,
+
+    /// What `--ephemeral` and `--then rm` clean up when no `--cleanup` is given.
+    pub cleanup: Vec<CleanupStep>,
+
+    /// When `gc`, `sync` and `rm` with several targets exit non-zero:
+    /// `any-failed`, `any-skipped` (a skip counts as a failure),
+    /// `all-failed` or `never`. Their summary is printed either way.
+    pub bulk_failure: BulkFailure,
+
+    /// Also log at debug level to `.git/graft/graft.log` (rotated at 1 MiB).
+    pub log_file: bool,
+
+    /// Record how long open, fetch and worktree creation take in
+    /// `.git/graft/timings.jsonl`, for `graft stats --timings`.
+    pub timings: bool,
+
+    /// Make the main checkout's tracked files read-only while worktrees for
+    /// branches exist, so edits meant for a worktree can't land there.
+    pub protect_main: bool,
+
+    /// Move removed worktrees to `.worktrees/.trash` so `graft undo` works.
+    pub trash: bool,
+
+    /// How repositories inside a worktree (submodules, or clones in
+    /// untracked or ignored directories) are treated: `recurse` counts
+    /// their changes and unpushed commits as the worktree's, so `gc` and
+    /// `clean` keep it; `warn` leaves them out of the counts but warns
+    /// before deleting any with work in them; `skip` ignores them, in disk
+    /// usage too.
+    pub nested_repos: NestedRepos,
+
+    /// Build and dependency directories new worktrees share with the main
+    /// checkout instead of starting without, e.g. `[cache_links]` with
+    /// `node_modules = "symlink"` and `target = "hardlink"`. `symlink`
+    /// points at the main checkout's copy; `hardlink` clones it without
+    /// using space, for tools that replace files rather than edit them in
+    /// place; `copy` gives the worktree its own copy, a copy-on-write clone
+    /// that is near-instant on btrfs, XFS and APFS; `separate` keeps the
+    /// worktree's own.
+    pub cache_links: BTreeMap<PathBuf, CacheLink>,
+
+    /// Named sets of directories for `--sparse`, e.g. `[sparse]` with
+    /// `web = ["apps/web", "libs/ui"]`; `graft <branch> --sparse web`
+    /// checks out only those.
+    pub sparse: BTreeMap<String, Vec<PathBuf>>,
+
+    /// Run `direnv allow` in new worktrees, first linking the main
+    /// checkout's `.envrc` into them when it is untracked. A checked-out
+    /// `.envrc` that differs from the main checkout's is left for you to
+    /// allow.
+    pub direnv: bool,
+
+    /// Check out submodules (`git submodule update --init --recursive`) in
+    /// new worktrees; `git worktree add` leaves them empty.
+    pub submodules: bool,
+
+    /// Where `pr:<n>` pull requests come from: `github`, `gitlab` (merge
+    /// requests) or `gitea`. Guessed from the fetch remote's URL when unset;
+    /// URLs of pull requests say for themselves.
+    pub forge: Option<Forge>,
+
+    /// Compiler cache shared by all worktrees: `sccache` or `ccache`.
+    /// Sessions, shells and hooks get the variables that route cargo and
+    /// CMake builds through it.
+    pub compile_cache: Option<CompileCache>,
+
+    /// Where `compile_cache` keeps its cache, relative to the repository
+    /// root; defaults to `.git/graft/compile-cache`.
+    pub compile_cache_dir: Option<PathBuf>,
+
+    /// Variables for each worktree's sessions, shells and hooks, e.g.
+    /// `[env]` with `DATABASE_URL = "postgres://localhost/app_{branch_slug}"`.
+    /// `{branch}`, `{branch_slug}` (lowercase letters, digits and `_`),
+    /// `{repo}` and `{worktree}` (its path) are expanded; `--env` wins.
+    pub env: BTreeMap<String, String>,
+
+    /// Rules for branches matching a pattern (`*` and `?`, as in `graft rm`),
+    /// e.g. `[policy."scratch/*"]` with `ttl = "24h"`. Where several match,
+    /// longer patterns override shorter ones key by key.
+    pub policy: BTreeMap<String, Policy>,
+
+    pub secrets: SecretsConfig,
+
+    pub issues: IssuesConfig,
+
+    pub zellij: ZellijConfig,
+}
+
+/// `[policy."<pattern>"]`: how graft treats worktrees of matching branches.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    /// Default `--ttl`, ahead of the purpose's. `graft gc` also applies it
+    /// to worktrees opened without one, counting from their last use.
+    pub ttl: Option<String>,
+    /// Never removed by `graft gc` or `graft clean`.
+    pub protected: Option<bool>,
+    /// Layout for new sessions, ahead of the `layout` key: a name from
+    /// `.graft/layouts/` or a path, e.g. a read-only one for `review/*`.
+    pub layout: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            track_upstream: true,
+            fetch_remote: None,
+            push_remote: None,
+            offline: false,
+            remote_timeout: "5s".to_string(),
+            fetch_timeout: "10m".to_string(),
+            remote_retries: 2,
+            remote_branch: None,
+            attach: AttachMode::Zellij,
+            session_strategy: SessionStrategy::Sessions,
+            worktree_root: None,
+            layout: None,
+            default_command: None,
+            editor: None,
+            max_total_size: None,
+            claims: false,
+            existing_dir: ExistingDir::Fail,
+            banner: false,
+            ttl: BTreeMap::new(),
+            layouts: BTreeMap::new(),
+            cleanup: vec![CleanupStep::Session, CleanupStep::Worktree],
+            bulk_failure: BulkFailure::AnyFailed,
+            log_file: false,
+            timings: false,
+            protect_main: false,
+            trash: false,
+            nested_repos: NestedRepos::Recurse,
+            cache_links: BTreeMap::new(),
+            sparse: BTreeMap::new(),
+            direnv: false,
+            submodules: false,
+            forge: None,
+            compile_cache: None,
+            compile_cache_dir: None,
+            env: BTreeMap::new(),
+            policy: BTreeMap::new(),
+            secrets: SecretsConfig::default(),
+            issues: IssuesConfig::default(),
+            zellij: ZellijConfig::default(),
+        }
+    }
+}
+
+/// A compiler cache for `compile_cache`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompileCache {
+    /// Wraps rustc as well as C and C++ compilers.
+    Sccache,
+    /// C and C++ only.
+    Ccache,
+}
+
+/// Where worktrees entered with Zellij go.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStrategy {
+    /// A session per worktree.
+    #[default]
+    Sessions,
+    /// A tab per worktree in the current session; a session per worktree
+    /// outside Zellij.
+    Tabs,
+}
+
+/// A code host, for the refs its pull requests are fetched from.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Forge {
+    /// `refs/pull/<n>/head`.
+    Github,
+    /// `refs/merge-requests/<n>/head`.
+    Gitlab,
+    /// `refs/pull/<n>/head`, as on GitHub; Forgejo and Codeberg too.
+    Gitea,
+}
+
+/// How `[cache_links]` gives a new worktree one of its directories.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheLink {
+    Symlink,
+    Hardlink,
+    Copy,
+    Separate,
+}
+
+/// Values of `bulk_failure`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BulkFailure {
+    #[default]
+    AnyFailed,
+    AnySkipped,
+    AllFailed,
+    Never,
+}
+
+/// Values of `nested_repos`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NestedRepos {
+    #[default]
+    Recurse,
+    Warn,
+    Skip,
+}
+
+/// `[secrets]`: decrypted files a new worktree needs, e.g. `.env`, written
+/// by a command instead of copied around by hand.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecretsConfig {
+    /// Shell command run in each new worktree before the post-create hook,
+    /// e.g. `op inject -i .env.tpl -o .env` or `sops -d secrets.enc > .env`.
+    pub command: Option<String>,
+    /// What the command writes, relative to the worktree. They are made
+    /// readable by the owner only, left out of `graft archive`, and
+    /// overwritten before the worktree is removed.
+    pub files: Vec<PathBuf>,
+}
+
+/// `[issues]`: where `graft new --issue` looks issues up, and the branch
+/// names it makes of them.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IssuesConfig {
+    /// `github`, `jira` or `linear`; by default numbers are GitHub issues
+    /// and keys like `PROJ-12` Jira's with `jira_url` set, else Linear's.
+    pub tracker: Option<IssueTracker>,
+    /// Branch name for an issue: `{key}` is its number or key, `{slug}` its
+    /// title in lowercase words joined by `-`.
+    pub branch: String,
+    /// The Jira site, e.g. `https://acme.atlassian.net`.
+    pub jira_url: Option<String>,
+    /// Shell command printing the API token when `JIRA_API_TOKEN` or
+    /// `LINEAR_API_KEY` is unset, e.g. a keychain lookup.
+    pub token_command: Option<String>,
+}
+
+impl Default for IssuesConfig {
+    fn default() -> Self {
+        IssuesConfig {
+            tracker: None,
+            branch: "{key}-{slug}".to_string(),
+            jira_url: None,
+            token_command: None,
+        }
+    }
+}
+
+/// Values of `issues.tracker`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IssueTracker {
+    Github,
+    Jira,
+    Linear,
+}
+
+/// `[zellij]`: how to invoke the zellij executable and name its sessions.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ZellijConfig {
+    /// Executable name or path, e.g. a nix-profile binary or wrapper script.
+    pub bin: String,
+    /// Global options placed before every subcommand (`--config-dir …`).
+    pub args: Vec<String>,
+    /// Extra options for `zellij attach --create`.
+    pub attach_args: Vec<String>,
+    /// Start of every session name graft creates; sessions without it are
+    /// never pruned. `{repo}` is the repository's directory name, so
+    /// `{repo}-` groups sessions per repository (`api-feature-x`).
+    pub session_prefix: String,
+}
+
+impl Default for ZellijConfig {
+    fn default() -> Self {
+        ZellijConfig {
+            bin: "zellij".to_string(),
+            args: Vec::new(),
+            attach_args: Vec::new(),
+            session_prefix: zellij::DEFAULT_SESSION_PREFIX.to_string(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(repo_root: &Path) -> Result<Config> {
+        let mut table = Table::new();
+        if let Some(dir) = user_config_dir() {
+            merge(&mut table, read_table(&dir.join("config.toml"))?);
+        }
+        merge(&mut table, read_table(&repo_root.join(REPO_CONFIG_FILE))?);
+        merge(&mut table, env_table()?);
+        toml::Value::Table(table)
+            .try_into()
+            .context("invalid graft configuration")
+    }
+
+    /// The `[policy]` rules for `branch`, merged.
+    pub fn policy(&self, branch: &str) -> Policy {
+        let mut rules: Vec<(&String, &Policy)> = self
+            .policy
+            .iter()
+            .filter(|(pattern, _)| glob_match(pattern, branch))
+            .collect();
+        rules.sort_by_key(|(pattern, _)| pattern.len());
+        let mut policy = Policy::default();
+        for (_, rule) in rules {
+            if rule.ttl.is_some() {
+                policy.ttl = rule.ttl.clone();
+            }
+            if rule.protected.is_some() {
+                policy.protected = rule.protected;
+            }
+            if rule.layout.is_some() {
+                policy.layout = rule.layout.clone();
+            }
+        }
+        policy
+    }
+}
+
+/// The file a layout setting names: `.graft/layouts/<name>.kdl` when there
+/// is one, else `layout` as a path relative to `base`.
+pub fn layout_file(repo_root: &Path, base: &Path, layout: &Path) -> PathBuf {
+    let named = repo_root
+        .join(REPO_DIR)
+        .join(LAYOUTS_DIR)
+        .join(format!("{}.kdl", layout.display()));
+    if named.is_file() {
+        named
+    } else {
+        base.join(layout)
+    }
+}
+
+/// `*` matches any run of characters (including `/`), `?` exactly one.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// How an environment variable's text becomes a config value.
+#[derive(Clone, Copy)]
+enum EnvKind {
+    Str,
+    Bool,
+    Int,
+    /// Comma-separated, e.g. `GRAFT_CLEANUP=session,worktree`.
+    List,
+    /// Whitespace-separated, e.g. `GRAFT_ZELLIJ_ARGS="--config-dir /x"`.
+    Words,
+}
+
+/// Environment variables that override config keys (dotted for tables),
+/// one per key except the tables keyed by name (`[env]`, `[policy]`, ...);
+/// `GRAFT_TTL_<PURPOSE>` sets `ttl.<purpose>`.
+const ENV_OVERRIDES: &[(&str, &str, EnvKind)] = &[
+    ("GRAFT_REMOTE", "fetch_remote", EnvKind::Str),
+    ("GRAFT_PUSH_REMOTE", "push_remote", EnvKind::Str),
+    ("GRAFT_OFFLINE", "offline", EnvKind::Bool),
+    ("GRAFT_REMOTE_TIMEOUT", "remote_timeout", EnvKind::Str),
+    ("GRAFT_FETCH_TIMEOUT", "fetch_timeout", EnvKind::Str),
+    ("GRAFT_REMOTE_RETRIES", "remote_retries", EnvKind::Int),
+    ("GRAFT_REMOTE_BRANCH", "remote_branch", EnvKind::Str),
+    ("GRAFT_MUX", "attach", EnvKind::Str),
+    ("GRAFT_SESSION_STRATEGY", "session_strategy", EnvKind::Str),
+    ("GRAFT_WORKTREE_DIR", "worktree_root", EnvKind::Str),
+    ("GRAFT_LAYOUT", "layout", EnvKind::Str),
+    ("GRAFT_DEFAULT_COMMAND", "default_command", EnvKind::Str),
+    ("GRAFT_EDITOR", "editor", EnvKind::Str),
+    ("GRAFT_MAX_TOTAL_SIZE", "max_total_size", EnvKind::Str),
+    ("GRAFT_TRACK_UPSTREAM", "track_upstream", EnvKind::Bool),
+    ("GRAFT_CLAIMS", "claims", EnvKind::Bool),
+    ("GRAFT_EXISTING_DIR", "existing_dir", EnvKind::Str),
+    ("GRAFT_BANNER", "banner", EnvKind::Bool),
+    ("GRAFT_CLEANUP", "cleanup", EnvKind::List),
+    ("GRAFT_BULK_FAILURE", "bulk_failure", EnvKind::Str),
+    ("GRAFT_LOG_FILE", "log_file", EnvKind::Bool),
+    ("GRAFT_TIMINGS", "timings", EnvKind::Bool),
+    ("GRAFT_PROTECT_MAIN", "protect_main", EnvKind::Bool),
+    ("GRAFT_TRASH", "trash", EnvKind::Bool),
+    ("GRAFT_NESTED_REPOS", "nested_repos", EnvKind::Str),
+    ("GRAFT_DIRENV", "direnv", EnvKind::Bool),
+    ("GRAFT_SUBMODULES", "submodules", EnvKind::Bool),
+    ("GRAFT_FORGE", "forge", EnvKind::Str),
+    ("GRAFT_COMPILE_CACHE", "compile_cache", EnvKind::Str),
+    ("GRAFT_COMPILE_CACHE_DIR", "compile_cache_dir", EnvKind::Str),
+    ("GRAFT_SECRETS_COMMAND", "secrets.command", EnvKind::Str),
+    ("GRAFT_SECRETS_FILES", "secrets.files", EnvKind::List),
+    ("GRAFT_ISSUE_TRACKER", "issues.tracker", EnvKind::Str),
+    ("GRAFT_ISSUE_BRANCH", "issues.branch", EnvKind::Str),
+    ("GRAFT_JIRA_URL", "issues.jira_url", EnvKind::Str),
+    (
+        "GRAFT_ISSUE_TOKEN_COMMAND",
+        "issues.token_command",
+        EnvKind::Str,
+    ),
+    ("GRAFT_ZELLIJ_BIN", "zellij.bin", EnvKind::Str),
+    ("GRAFT_ZELLIJ_ARGS", "zellij.args", EnvKind::Words),
+    (
+        "GRAFT_ZELLIJ_ATTACH_ARGS",
+        "zellij.attach_args",
+        EnvKind::Words,
+    ),
+    (
+        "GRAFT_SESSION_PREFIX",
+        "zellij.session_prefix",
+        EnvKind::Str,
+    ),
+];
+
+const ENV_TTL_PREFIX: &str = "GRAFT_TTL_";
+
+/// The `GRAFT_*` variable that overrides the config `key` (dotted for
+/// tables), if one does.
+pub fn env_var(key: &str) -> Option<&'static str> {
+    ENV_OVERRIDES
+        .iter()
+        .find(|&&(_, name, _)| name == key)
+        .map(|&(var, _, _)| var)
+}
+
+/// The `GRAFT_*` variables that are set, as a table to merge over the files.
+/// Empty variables count as unset.
+fn env_table() -> Result<Table> {
+    let mut table = Table::new();
+    for &(var, key, kind) in ENV_OVERRIDES {
+        let Some(text) = env::var(var).ok().filter(|text| !text.is_empty()) else {
+            continue;
+        };
+        let value = match kind {
+            EnvKind::Str => toml::Value::String(text),
+            EnvKind::Bool => match text.to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => toml::Value::Boolean(true),
+                "0" | "false" | "no" | "off" => toml::Value::Boolean(false),
+                _ => bail!("{var}={text} is not a boolean; use true or false"),
+            },
+            EnvKind::Int => match text.trim().parse() {
+                Ok(number) => toml::Value::Integer(number),
+                Err(_) => bail!("{var}={text} is not a whole number"),
+            },
+            EnvKind::List => toml::Value::Array(
+                text.split(',')
+                    .map(|item| toml::Value::String(item.trim().to_string()))
+                    .collect(),
+            ),
+            EnvKind::Words => toml::Value::Array(
+                text.split_whitespace()
+                    .map(|word| toml::Value::String(word.to_string()))
+                    .collect(),
+            ),
+        };
+        set_dotted(&mut table, key, value);
+    }
+    for (var, text) in env::vars() {
+        if let Some(purpose) = var.strip_prefix(ENV_TTL_PREFIX)
+            && !text.is_empty()
+        {
+            let key = format!("ttl.{}", purpose.to_ascii_lowercase());
+            set_dotted(&mut table, &key, toml::Value::String(text));
+        }
+    }
+    Ok(table)
+}
+
+/// Sets `a.b.c` in `table`, creating the tables on the way.
+fn set_dotted(table: &mut Table, key: &str, value: toml::Value) {
+    match key.split_once('.') {
+        Some((head, rest)) => {
+            let entry = table
+                .entry(head)
+                .or_insert_with(|| toml::Value::Table(Table::new()));
+            if let toml::Value::Table(inner) = entry {
+                set_dotted(inner, rest, value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/graft`, falling back to `~/.config/graft`.
+pub fn user_config_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("graft"))
+}
+
+fn read_table(path: &Path) -> Result<Table> {
+    match fs::read_to_string(path) {
+        Ok(contents) => contents
+            .parse::<Table>()
+            .with_context(|| format!("failed to parse {}", path.display())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Table::new()),
+        Err(err) => Err(err).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+/// Deep-merges `overlay` into `base`; nested tables merge key by key.
+fn merge(base: &mut Table, overlay: Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(incoming)) => {
+                merge(existing, incoming)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
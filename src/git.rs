@@ -1,78 +1,2189 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-use git2::{BranchType, Repository, Worktree, WorktreeAddOptions};
+use anyhow::{Context, Result, bail};
+use git2::{
+    BranchType, ErrorCode, Repository, StatusOptions, SubmoduleIgnore, SubmoduleStatus, Worktree,
+    WorktreeAddOptions, WorktreeLockStatus, WorktreePruneOptions,
+};
+use tracing::{debug, info, warn};
+
+use crate::config::{CacheLink, NestedRepos};
+use crate::process::{Cmd, ProcessRunner, SystemRunner};
+use crate::registry::Registry;
 
 pub struct Git {
     repo: Repository,
+    /// Where branches and tags come from (`upstream` in fork workflows).
+    fetch_remote: String,
+    /// Where graft pushes branches and claims (your fork).
+    push_remote: String,
+    /// From the `worktree_root` config; `<root>/.worktrees` when unset.
+    worktree_dir: Option<PathBuf>,
+    /// From the `remote_branch` config, `{user}` already expanded: where a
+    /// local branch lives on remotes, e.g. `users/anze/{branch}`.
+    remote_branch: Option<String>,
+    /// Runs the git CLI; the system unless tests swap it.
+    runner: Arc<dyn ProcessRunner>,
+    network: Network,
+    /// From the `nested_repos` config: whether [`Git::inspect`] counts
+    /// changes inside repositories nested in a worktree.
+    nested: NestedRepos,
+    /// From the `direnv` config: whether new worktrees get their `.envrc`
+    /// allowed.
+    direnv: bool,
+    /// From `[cache_links]`: directories new worktrees share with the main
+    /// checkout.
+    cache_links: BTreeMap<PathBuf, CacheLink>,
+    /// From the `submodules` config or `--submodules`: whether new
+    /// worktrees get their submodules checked out.
+    submodules: bool,
+    /// From `--sparse`: the directories new worktrees check out, in
+    /// cone-mode sparse checkouts; everything when empty.
+    sparse: Vec<PathBuf>,
+}
+
+/// Limits for git commands that talk to a remote, from the
+/// `remote_timeout`, `fetch_timeout` and `remote_retries` config.
+#[derive(Clone, Copy, Debug)]
+pub struct Network {
+    /// How long a remote gets to say whether it has a branch (`ls-remote`)
+    /// before graft assumes there's no network; git would hang for ~30s.
+    pub lookup_timeout: Duration,
+    pub fetch_timeout: Duration,
+    /// Attempts after the first when one fails; each waits twice as long
+    /// as the one before, starting at a second. Timeouts aren't retried.
+    pub retries: u32,
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Network {
+            lookup_timeout: Duration::from_secs(5),
+            fetch_timeout: Duration::from_secs(10 * 60),
+            retries: 2,
+        }
+    }
+}
+
+pub struct WorktreeInfo {
+    pub name: String,
+    pub path: PathBuf,
+    pub branch: Option<String>,
+}
+
+impl WorktreeInfo {
+    /// The name graft knows the worktree by: its branch, or for detached
+    /// worktrees the administrative name (the short SHA for `checkout`).
+    pub fn label(&self) -> &str {
+        self.branch.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// Local state of a single checkout relative to its upstream.
+pub struct WorktreeStatus {
+    /// Tracked files with uncommitted changes.
+    pub dirty_files: usize,
+    /// Files git doesn't track yet (ignored files excluded).
+    pub untracked_files: usize,
+    /// `(ahead, behind)` versus the upstream, if one is configured.
+    pub upstream: Option<(usize, usize)>,
+    /// Commits in nested repositories that none of their remotes have;
+    /// only counted with `nested_repos = "recurse"`.
+    pub nested_unpushed: usize,
+}
+
+/// A repository inside a worktree: a submodule's checkout, or a clone the
+/// worktree's repository only sees as an untracked or ignored directory.
+pub struct NestedRepo {
+    /// Relative to the worktree.
+    pub path: PathBuf,
+    pub submodule: bool,
+    /// Uncommitted and untracked files in it.
+    pub changed_files: usize,
+    /// Commits on its HEAD that none of its remote-tracking branches have.
+    pub unpushed: usize,
+}
+
+impl NestedRepo {
+    /// Whether deleting it would lose anything.
+    pub fn has_work(&self) -> bool {
+        self.changed_files + self.unpushed > 0
+    }
+}
+
+/// The commit a worktree is on, for `graft ls`.
+pub struct Tip {
+    /// First line of the commit message.
+    pub subject: String,
+    /// Commit time (Unix seconds).
+    pub time: u64,
+    /// `(ahead, behind)` versus the upstream, if one is configured.
+    pub upstream: Option<(usize, usize)>,
+}
+
+/// The checkout a directory is in, read without scanning the working tree;
+/// for `graft prompt`.
+pub struct Checkout {
+    /// Top-level directory.
+    pub path: PathBuf,
+    /// `None` when detached.
+    pub branch: Option<String>,
+    /// HEAD commit; `None` on an unborn branch.
+    pub head: Option<String>,
+    /// When the index last changed (Unix milliseconds). Staging, commits
+    /// and checkouts all rewrite it.
+    pub index_changed: Option<u64>,
+}
+
+/// What [`Git::bundle_unpushed`] wrote.
+pub struct Bundled {
+    /// The commit the bundle builds on; `None` when it holds the whole
+    /// history.
+    pub basis: Option<String>,
+    /// Commits in the bundle; without any, no file is written.
+    pub commits: usize,
+}
+
+/// Why a worktree can't be fast-forwarded to its upstream, in detail.
+pub struct Blockers {
+    /// Local commits the upstream doesn't have.
+    pub ahead: usize,
+    /// Upstream commits not yet in the worktree.
+    pub behind: usize,
+    /// Files merging the upstream would conflict in (worked out in memory,
+    /// only when diverged).
+    pub conflicts: Vec<String>,
+    /// Locally changed or untracked files that the upstream changes too.
+    pub overlapping: Vec<String>,
 }
 
 const BASE_BRANCH: &str = "main";
-const WORKTREE_DIR: &str = "./.worktrees";
+/// Fetch and push remote unless configured otherwise.
+const REMOTE: &str = "origin";
+const WORKTREE_DIR: &str = ".worktrees";
+/// Namespace for branch claims shared through the remote.
+const CLAIMS_REF: &str = "refs/graft/claims/";
+/// Inside the worktree directory; holds removed checkouts in trash mode.
+const TRASH_DIR: &str = ".trash";
 
 impl Git {
-    pub fn new(path: &str) -> Self {
-        let repo = Repository::open(path).unwrap();
-        Git { repo }
+    pub fn new(path: &Path) -> Result<Self> {
+        let mut repo = match Repository::discover(path) {
+            Ok(repo) => repo,
+            Err(err) if err.code() == ErrorCode::NotFound => return Err(not_a_repository(path)),
+            Err(err) => return Err(err.into()),
+        };
+        if repo.is_worktree() {
+            repo = Repository::open(repo.commondir())?;
+        }
+        Ok(Git {
+            repo,
+            fetch_remote: REMOTE.to_string(),
+            push_remote: REMOTE.to_string(),
+            worktree_dir: None,
+            remote_branch: None,
+            runner: Arc::new(SystemRunner),
+            network: Network::default(),
+            nested: NestedRepos::default(),
+            direnv: false,
+            cache_links: BTreeMap::new(),
+            submodules: false,
+            sparse: Vec::new(),
+        })
+    }
+
+    pub fn set_runner(&mut self, runner: Arc<dyn ProcessRunner>) {
+        self.runner = runner;
+    }
+
+    pub fn runner(&self) -> &dyn ProcessRunner {
+        self.runner.as_ref()
+    }
+
+    /// Applies the `fetch_remote`/`push_remote` settings. The push remote
+    /// falls back to git's `remote.pushDefault`, then to the fetch remote.
+    pub fn set_remotes(&mut self, fetch: Option<&str>, push: Option<&str>) {
+        if let Some(fetch) = fetch {
+            self.fetch_remote = fetch.to_string();
+        }
+        self.push_remote = match push {
+            Some(push) => push.to_string(),
+            None => self
+                .repo
+                .config()
+                .and_then(|config| config.get_string("remote.pushDefault"))
+                .unwrap_or_else(|_| self.fetch_remote.clone()),
+        };
+    }
+
+    pub fn set_network(&mut self, network: Network) {
+        self.network = network;
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn set_nested_repos(&mut self, nested: NestedRepos) {
+        self.nested = nested;
+    }
+
+    pub fn set_direnv(&mut self, direnv: bool) {
+        self.direnv = direnv;
+    }
+
+    pub fn set_cache_links(&mut self, links: BTreeMap<PathBuf, CacheLink>) {
+        self.cache_links = links;
+    }
+
+    pub fn set_submodules(&mut self, submodules: bool) {
+        self.submodules = submodules;
+    }
+
+    pub fn set_sparse(&mut self, sparse: Vec<PathBuf>) {
+        self.sparse = sparse;
+    }
+
+    /// Applies the `worktree_root` setting: a directory, absolute or
+    /// relative to the repository root, optionally ending in `/{branch}`.
+    /// `~` and `{repo}` (the repository's directory name) are expanded.
+    pub fn set_worktree_root(&mut self, template: Option<&str>) -> Result<()> {
+        let Some(template) = template else {
+            return Ok(());
+        };
+        let base = template
+            .strip_suffix("{branch}")
+            .map_or(template, |base| base.trim_end_matches('/'));
+        if base.contains("{branch}") {
+            bail!("worktree_root '{template}': {{branch}} must be the last path component");
+        }
+        let root = self.root();
+        let repo = root.file_name().unwrap_or_default().to_string_lossy();
+        let base = base.replace("{repo}", &repo);
+        let base = match base.strip_prefix('~') {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+                let home = std::env::var_os("HOME")
+                    .context("worktree_root starts with ~ but $HOME is not set")?;
+                PathBuf::from(home).join(rest.trim_start_matches('/'))
+            }
+            _ => PathBuf::from(base),
+        };
+        self.worktree_dir = Some(root.join(base));
+        Ok(())
+    }
+
+    /// Applies the `remote_branch` setting, a template with `{branch}` and
+    /// optionally `{user}`: the local part of `user.email`, else `$USER`.
+    pub fn set_remote_branch(&mut self, template: Option<&str>) -> Result<()> {
+        let Some(template) = template else {
+            return Ok(());
+        };
+        if !template.contains("{branch}") {
+            bail!("remote_branch '{template}' must contain {{branch}}");
+        }
+        let mut template = template.to_string();
+        if template.contains("{user}") {
+            let user = self
+                .repo
+                .config()
+                .and_then(|config| config.get_string("user.email"))
+                .ok()
+                .and_then(|email| email.split('@').next().map(str::to_string))
+                .filter(|user| !user.is_empty())
+                .or_else(|| std::env::var("USER").ok())
+                .context("remote_branch uses {user}, but neither user.email nor $USER is set")?;
+            template = template.replace("{user}", &user);
+        }
+        self.remote_branch = Some(template);
+        Ok(())
+    }
+
+    /// What `branch_name` is called on remotes under the `remote_branch`
+    /// setting; the same name without it.
+    pub fn remote_branch_name(&self, branch_name: &str) -> String {
+        match &self.remote_branch {
+            Some(template) => template.replace("{branch}", branch_name),
+            None => branch_name.to_string(),
+        }
+    }
+
+    /// The remote branch `branch_name` tracks (`branch.<name>.merge`), else
+    /// the name [`Git::remote_branch_name`] gives it.
+    pub fn upstream_branch(&self, branch_name: &str) -> String {
+        self.repo
+            .config()
+            .and_then(|config| config.get_string(&format!("branch.{branch_name}.merge")))
+            .ok()
+            .and_then(|merge| merge.strip_prefix("refs/heads/").map(str::to_string))
+            .unwrap_or_else(|| self.remote_branch_name(branch_name))
+    }
+
+    /// The branch new branches start from and `--then merge` merges into.
+    pub fn base_branch(&self) -> &str {
+        BASE_BRANCH
+    }
+
+    /// The linked worktree containing `dir`, if any; `None` in the main
+    /// checkout and outside the repository.
+    pub fn worktree_containing(&self, dir: &Path) -> Result<Option<WorktreeInfo>> {
+        let dir = dir.canonicalize()?;
+        Ok(self.worktrees()?.into_iter().find(|worktree| {
+            worktree
+                .path
+                .canonicalize()
+                .is_ok_and(|path| dir.starts_with(path))
+        }))
+    }
+
+    pub fn fetch_remote(&self) -> &str {
+        &self.fetch_remote
+    }
+
+    /// Remotes to look for a branch on, in order: the fetch remote, the
+    /// push remote, then any other configured remote.
+    pub fn remotes(&self) -> Vec<String> {
+        let mut remotes = vec![self.fetch_remote.clone(), self.push_remote.clone()];
+        if let Ok(names) = self.repo.remotes() {
+            remotes.extend(names.iter().flatten().map(str::to_string));
+        }
+        let mut seen = Vec::new();
+        remotes.retain(|remote| {
+            let keep = !seen.contains(remote) && self.repo.find_remote(remote).is_ok();
+            seen.push(remote.clone());
+            keep
+        });
+        remotes
+    }
+
+    /// The remote `branch.<name>.remote` points at, else the fetch remote.
+    pub fn upstream_remote(&self, branch_name: &str) -> String {
+        self.repo
+            .config()
+            .and_then(|config| config.get_string(&format!("branch.{branch_name}.remote")))
+            .unwrap_or_else(|_| self.fetch_remote.clone())
+    }
+
+    /// Root of the main checkout, even when invoked from inside a worktree.
+    pub fn root(&self) -> PathBuf {
+        let common = self.repo.commondir();
+        match common.file_name() {
+            Some(name) if name == ".git" => common.parent().unwrap().to_path_buf(),
+            _ => common.to_path_buf(),
+        }
+    }
+
+    /// Where graft keeps its own metadata (`.git/graft`).
+    pub fn state_dir(&self) -> PathBuf {
+        self.repo.commondir().join("graft")
+    }
+
+    pub fn worktree_dir(&self) -> PathBuf {
+        match &self.worktree_dir {
+            Some(dir) => dir.clone(),
+            None => self.root().join(WORKTREE_DIR),
+        }
+    }
+
+    pub fn trash_dir(&self) -> PathBuf {
+        self.worktree_dir().join(TRASH_DIR)
+    }
+
+    /// Where `branch`'s worktree is, or will be. An existing worktree keeps
+    /// its place when `worktree_root` changes later.
+    pub fn worktree_path(&self, branch: &str) -> PathBuf {
+        match self.lookup_worktree(branch) {
+            Some(worktree) => worktree.path().to_path_buf(),
+            None => self.worktree_dir().join(branch),
+        }
+    }
+
+    /// The worktree graft knows by `label`: the one it named after the
+    /// branch, else one added elsewhere (`git worktree add ../x branch`)
+    /// that has the branch or label checked out.
+    fn lookup_worktree(&self, label: &str) -> Option<Worktree> {
+        if let Ok(worktree) = self.repo.find_worktree(&worktree_name(label)) {
+            return Some(worktree);
+        }
+        let info = self
+            .worktrees()
+            .ok()?
+            .into_iter()
+            .find(|worktree| worktree.label() == label)?;
+        self.repo.find_worktree(&info.name).ok()
+    }
+
+    pub fn worktrees(&self) -> Result<Vec<WorktreeInfo>> {
+        let names = self.repo.worktrees()?;
+        let mut infos = Vec::new();
+        for name in names.iter().flatten() {
+            let worktree = self.repo.find_worktree(name)?;
+            let branch = Repository::open_from_worktree(&worktree)
+                .ok()
+                .and_then(|repo| head_branch(&repo));
+            infos.push(WorktreeInfo {
+                name: name.to_string(),
+                path: worktree.path().to_path_buf(),
+                branch,
+            });
+        }
+        Ok(infos)
+    }
+
+    pub fn branches(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for branch in self.repo.branches(Some(BranchType::Local))? {
+            let (branch, _branch_type) = branch?;
+            if let Some(name) = branch.name()? {
+                names.push(name.to_string());
+            }
+        }
+        Ok(names)
     }
 
-    pub fn worktrees(&self) -> Vec<String> {
-        let worktrees = self.repo.worktrees().unwrap();
-        worktrees.iter().map(|w| w.unwrap().to_string()).collect()
+    /// Runs the git CLI in the repository root and returns its stdout.
+    ///
+    /// Network operations go through here rather than libgit2 so that
+    /// `GIT_SSH_COMMAND`, `core.sshCommand`, `url.<base>.insteadOf`, proxy
+    /// settings and `~/.ssh/config` apply exactly as they do for hand-run git.
+    pub fn run_ok(&self, args: &[&str]) -> Result<String> {
+        run_ok_in(self.runner(), &self.root(), args)
     }
 
-    pub fn branches(&self) -> Vec<String> {
-        let branches = self.repo.branches(Some(BranchType::Local)).unwrap();
-        return branches
-            .map(|b| {
-                let (branch, _branch_type) = b.unwrap();
-                branch.name().unwrap().unwrap().to_string()
+    /// Like [`Git::run_ok`] for commands that talk to a remote (`fetch`,
+    /// `ls-remote`): timed out and retried as [`Git::network`] says.
+    pub fn run_remote(&self, args: &[&str]) -> Result<String> {
+        run_remote_in(self.runner(), &self.root(), args, self.network)
+    }
+
+    /// Fetches the remote and drops remote-tracking refs deleted upstream.
+    pub fn fetch_prune(&self) -> Result<()> {
+        self.run_remote(&["fetch", "--prune", &self.fetch_remote])?;
+        Ok(())
+    }
+
+    /// Counts the worktree's local changes. Repositories nested in it count
+    /// as a single changed path at most (a submodule on another commit);
+    /// with `nested_repos = "recurse"` their own changes are added.
+    pub fn inspect(&self, worktree_path: &Path) -> Result<WorktreeStatus> {
+        let repo = Repository::open(worktree_path)?;
+        let (mut dirty_files, untracked_files) = count_changes(&repo, worktree_path)?;
+        let mut nested_unpushed = 0;
+        if self.nested == NestedRepos::Recurse {
+            for nested in self.nested_repos(worktree_path)? {
+                dirty_files += nested.changed_files;
+                nested_unpushed += nested.unpushed;
+            }
+        }
+
+        let upstream = match repo.head() {
+            Ok(head) if head.is_branch() => {
+                let branch = git2::Branch::wrap(head);
+                match branch.upstream() {
+                    Ok(upstream) => {
+                        let local = branch.get().peel_to_commit()?.id();
+                        let remote = upstream.get().peel_to_commit()?.id();
+                        Some(repo.graph_ahead_behind(local, remote)?)
+                    }
+                    Err(_) => None,
+                }
+            }
+            _ => None,
+        };
+        Ok(WorktreeStatus {
+            dirty_files,
+            untracked_files,
+            upstream,
+            nested_unpushed,
+        })
+    }
+
+    /// The repositories nested in the checkout at `worktree_path`, and
+    /// theirs in turn. Other worktrees of this repository, which can live
+    /// inside the main checkout, aren't among them.
+    pub fn nested_repos(&self, worktree_path: &Path) -> Result<Vec<NestedRepo>> {
+        let worktrees = self.worktrees()?;
+        let mut found = find_nested(worktree_path)?;
+        found.retain(|nested| {
+            let path = worktree_path.join(&nested.path);
+            !worktrees
+                .iter()
+                .any(|worktree| same_path(&worktree.path, &path))
+        });
+        Ok(found)
+    }
+
+    /// The worktree's HEAD commit and how it compares to the upstream.
+    /// Cheaper than [`Git::inspect`]: it doesn't scan the working tree.
+    pub fn tip(&self, worktree_path: &Path) -> Result<Tip> {
+        let repo = Repository::open(worktree_path)?;
+        let head = repo.head()?;
+        let commit = head.peel_to_commit()?;
+        let upstream = if head.is_branch() {
+            match git2::Branch::wrap(head).upstream() {
+                Ok(upstream) => {
+                    let remote = upstream.get().peel_to_commit()?.id();
+                    Some(repo.graph_ahead_behind(commit.id(), remote)?)
+                }
+                Err(_) => None,
+            }
+        } else {
+            None
+        };
+        Ok(Tip {
+            subject: commit.summary().unwrap_or_default().to_string(),
+            time: commit.time().seconds().max(0) as u64,
+            upstream,
+        })
+    }
+
+    /// Commits on the worktree's HEAD that no remote-tracking branch has.
+    pub fn unpushed(&self, worktree_path: &Path) -> Result<usize> {
+        Ok(unpushed(&Repository::open(worktree_path)?))
+    }
+
+    /// Stash entries made on `branch_name`. The stash is shared by every
+    /// worktree; its messages (`WIP on <branch>:`, `On <branch>:`) tell
+    /// whose an entry is.
+    pub fn stash_count(&self, branch_name: &str) -> usize {
+        let Ok(reflog) = self.repo.reflog("refs/stash") else {
+            return 0;
+        };
+        let prefixes = [
+            format!("WIP on {branch_name}:"),
+            format!("On {branch_name}:"),
+        ];
+        reflog
+            .iter()
+            .filter(|entry| {
+                entry
+                    .message()
+                    .is_some_and(|message| prefixes.iter().any(|p| message.starts_with(p)))
             })
+            .count()
+    }
+
+    /// `(ahead, behind)` of the worktree's HEAD versus the base branch,
+    /// preferring the remote's copy of it; `None` when there is neither.
+    pub fn versus_base(&self, worktree_path: &Path) -> Result<Option<(usize, usize)>> {
+        let repo = Repository::open(worktree_path)?;
+        let head = repo.head()?.peel_to_commit()?.id();
+        let base = [
+            format!("refs/remotes/{}/{}", self.fetch_remote, BASE_BRANCH),
+            format!("refs/heads/{BASE_BRANCH}"),
+        ]
+        .iter()
+        .find_map(|refname| repo.refname_to_id(refname).ok());
+        match base {
+            Some(base) => Ok(Some(repo.graph_ahead_behind(head, base)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Details what keeps the worktree from following its upstream: the
+    /// commits on each side, the files a merge would conflict in, and the
+    /// local changes the upstream would overwrite. `None` without upstream.
+    pub fn blockers(&self, worktree_path: &Path) -> Result<Option<Blockers>> {
+        let repo = Repository::open(worktree_path)?;
+        let head = repo.head()?;
+        if !head.is_branch() {
+            return Ok(None);
+        }
+        let branch = git2::Branch::wrap(head);
+        let Ok(upstream) = branch.upstream() else {
+            return Ok(None);
+        };
+        let local = branch.get().peel_to_commit()?;
+        let remote = upstream.get().peel_to_commit()?;
+        let (ahead, behind) = repo.graph_ahead_behind(local.id(), remote.id())?;
+
+        let mut conflicts = Vec::new();
+        if ahead > 0 && behind > 0 {
+            let index = repo.merge_commits(&local, &remote, None)?;
+            for conflict in index.conflicts()? {
+                let conflict = conflict?;
+                let entry = conflict.our.or(conflict.their).or(conflict.ancestor);
+                if let Some(entry) = entry {
+                    conflicts.push(String::from_utf8_lossy(&entry.path).into_owned());
+                }
+            }
+        }
+
+        let base = repo.merge_base(local.id(), remote.id())?;
+        let base_tree = repo.find_commit(base)?.tree()?;
+        let diff = repo.diff_tree_to_tree(Some(&base_tree), Some(&remote.tree()?), None)?;
+        let mut changed = Vec::new();
+        for delta in diff.deltas() {
+            for file in [delta.old_file(), delta.new_file()] {
+                if let Some(path) = file.path() {
+                    changed.push(path.to_path_buf());
+                }
+            }
+        }
+        let mut options = StatusOptions::new();
+        options.include_untracked(true).include_ignored(false);
+        let mut overlapping: Vec<String> = repo
+            .statuses(Some(&mut options))?
+            .iter()
+            .filter_map(|entry| entry.path().map(str::to_string))
+            .filter(|path| changed.iter().any(|c| c == Path::new(path)))
             .collect();
+        overlapping.sort();
+        conflicts.sort();
+        conflicts.dedup();
+        Ok(Some(Blockers {
+            ahead,
+            behind,
+            conflicts,
+            overlapping,
+        }))
+    }
+
+    pub fn push_branch(&self, branch_name: &str) -> Result<()> {
+        let refspec = format!("{branch_name}:{}", self.remote_branch_name(branch_name));
+        self.run_ok(&["push", "--set-upstream", &self.push_remote, &refspec])?;
+        Ok(())
+    }
+
+    /// Refreshes the local copy of every claim on the remote.
+    fn fetch_claims(&self) -> Result<()> {
+        let refspec = format!("+{CLAIMS_REF}*:{CLAIMS_REF}*");
+        self.run_remote(&["fetch", "--prune", &self.push_remote, &refspec])?;
+        Ok(())
+    }
+
+    /// Who holds the claim on `branch_name`, as `Name <email>`, and whether
+    /// that is the current user. Reads the local copy of the claim ref.
+    fn claim_owner(&self, branch_name: &str) -> Result<Option<(String, bool)>> {
+        let Ok(reference) = self
+            .repo
+            .find_reference(&format!("{CLAIMS_REF}{branch_name}"))
+        else {
+            return Ok(None);
+        };
+        let author = reference.peel_to_commit()?.author().to_owned();
+        let me = self.repo.signature()?;
+        let mine = author.email_bytes() == me.email_bytes();
+        Ok(Some((author.to_string(), mine)))
+    }
+
+    /// Claims `branch_name` by pushing a marker commit authored by the
+    /// current user to `refs/graft/claims/<branch>`. Returns the owner
+    /// instead if someone else claimed it first.
+    pub fn claim(&self, branch_name: &str) -> Result<Option<String>> {
+        self.fetch_claims()?;
+        match self.claim_owner(branch_name)? {
+            Some((_, true)) => return Ok(None),
+            Some((owner, false)) => return Ok(Some(owner)),
+            None => {}
+        }
+        let signature = self.repo.signature()?;
+        let tree = self.repo.find_tree(self.repo.treebuilder(None)?.write()?)?;
+        let message = format!("graft claim {branch_name}");
+        let marker = self
+            .repo
+            .commit(None, &signature, &signature, &message, &tree, &[])?;
+        let name = format!("{CLAIMS_REF}{branch_name}");
+        self.repo.reference(&name, marker, true, &message)?;
+        self.run_ok(&["push", &self.push_remote, &format!("{name}:{name}")])?;
+        Ok(None)
+    }
+
+    /// Drops the current user's claim on `branch_name`, if they hold one.
+    pub fn release_claim(&self, branch_name: &str) -> Result<()> {
+        if let Some((_, true)) = self.claim_owner(branch_name)? {
+            let name = format!("{CLAIMS_REF}{branch_name}");
+            self.run_ok(&["push", &self.push_remote, &format!(":{name}")])?;
+            self.repo.find_reference(&name)?.delete()?;
+        }
+        Ok(())
+    }
+
+    /// Claims held by other people, as `(branch, owner)`.
+    pub fn foreign_claims(&self) -> Result<Vec<(String, String)>> {
+        self.fetch_claims()?;
+        let mut claims = Vec::new();
+        for reference in self.repo.references_glob(&format!("{CLAIMS_REF}*"))? {
+            let Some(name) = reference?.name().map(str::to_string) else {
+                continue;
+            };
+            let branch = &name[CLAIMS_REF.len()..];
+            if let Some((owner, false)) = self.claim_owner(branch)? {
+                claims.push((branch.to_string(), owner));
+            }
+        }
+        Ok(claims)
+    }
+
+    /// Fast-forwards the base branch to `branch_name` in the main checkout.
+    pub fn merge_into_base(&self, branch_name: &str) -> Result<()> {
+        match head_branch(&self.repo) {
+            Some(head) if head == BASE_BRANCH => {
+                self.run_ok(&["merge", "--ff-only", branch_name])?;
+                Ok(())
+            }
+            _ => bail!("the main checkout is not on '{BASE_BRANCH}', refusing to merge"),
+        }
+    }
+
+    pub fn fast_forward(&self, worktree_path: &Path) -> Result<()> {
+        run_ok_in(
+            self.runner(),
+            worktree_path,
+            &["merge", "--ff-only", "@{upstream}"],
+        )?;
+        Ok(())
+    }
+
+    /// Deletes ignored files (build output) from a worktree.
+    pub fn clean_ignored(&self, worktree_path: &Path) -> Result<()> {
+        run_ok_in(self.runner(), worktree_path, &["clean", "-fdX"])?;
+        Ok(())
     }
 
-    pub fn create_branch(&self, branch_name: &str) {
+    pub fn has_branch(&self, branch_name: &str) -> bool {
+        self.repo
+            .find_branch(branch_name, BranchType::Local)
+            .is_ok()
+    }
+
+    /// The URL `remote` is fetched from.
+    pub fn remote_url(&self, remote: &str) -> Option<String> {
+        let remote = self.repo.find_remote(remote).ok()?;
+        remote.url().map(str::to_string)
+    }
+
+    pub fn has_remote(&self) -> bool {
+        self.repo.find_remote(&self.fetch_remote).is_ok()
+    }
+
+    fn has_push_remote(&self) -> bool {
+        self.repo.find_remote(&self.push_remote).is_ok()
+    }
+
+    /// Fetches the branch called `remote_name` on `remote` into its
+    /// remote-tracking ref.
+    pub fn fetch_branch(&self, remote: &str, remote_name: &str) -> Result<()> {
+        fetch_branch_in(
+            self.runner(),
+            &self.root(),
+            remote,
+            remote_name,
+            self.network,
+        )
+    }
+
+    pub fn create_branch(&self, branch_name: &str, track_upstream: bool) -> Result<()> {
         let base = self
             .repo
             .find_branch(BASE_BRANCH, BranchType::Local)
-            .unwrap();
-        let base_head = base.get().peel_to_commit().unwrap();
-        self.repo.branch(branch_name, &base_head, false).unwrap();
+            .with_context(|| format!("base branch '{BASE_BRANCH}' not found"))?;
+        let base_head = base.get().peel_to_commit()?;
+        self.repo.branch(branch_name, &base_head, false)?;
+        if track_upstream && self.has_push_remote() {
+            // The remote branch doesn't exist yet, so set the tracking config
+            // directly; the first plain `git push` creates it.
+            let mut config = self.repo.config()?;
+            config.set_str(&format!("branch.{branch_name}.remote"), &self.push_remote)?;
+            config.set_str(
+                &format!("branch.{branch_name}.merge"),
+                &format!("refs/heads/{}", self.remote_branch_name(branch_name)),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Creates `branch_name` at `oid`, tracking nothing; for refs like
+    /// review patchsets that have no branch to follow.
+    pub fn create_branch_at(&self, branch_name: &str, oid: git2::Oid) -> Result<()> {
+        let commit = self.repo.find_commit(oid)?;
+        self.repo.branch(branch_name, &commit, false)?;
+        Ok(())
     }
 
-    pub fn ensure_branch(&self, branch_name: &str) {
-        let branch_exists = self
+    /// Sets `branch_name` to track its branch on the fetch remote, when
+    /// there is a remote-tracking ref for it. Returns whether it did.
+    pub fn track_remote_branch(&self, branch_name: &str) -> Result<bool> {
+        let remote_ref = format!(
+            "{}/{}",
+            self.fetch_remote,
+            self.remote_branch_name(branch_name)
+        );
+        if self
             .repo
-            .find_branch(branch_name, BranchType::Local)
-            .is_ok();
-        if !branch_exists {
-            self.create_branch(branch_name);
+            .find_branch(&remote_ref, BranchType::Remote)
+            .is_err()
+        {
+            return Ok(false);
+        }
+        let mut branch = self.repo.find_branch(branch_name, BranchType::Local)?;
+        branch.set_upstream(Some(&remote_ref))?;
+        Ok(true)
+    }
+
+    fn create_branch_from_remote(
+        &self,
+        branch_name: &str,
+        remote: &str,
+        track_upstream: bool,
+    ) -> Result<()> {
+        self.fetch_branch(remote, &self.remote_branch_name(branch_name))?;
+        self.create_branch_from_fetched(branch_name, remote, track_upstream)
+    }
+
+    /// Creates `branch_name` at its remote-tracking branch for `remote`,
+    /// fetched beforehand (see [`fetch_branch_in`]).
+    pub fn create_branch_from_fetched(
+        &self,
+        branch_name: &str,
+        remote: &str,
+        track_upstream: bool,
+    ) -> Result<()> {
+        let remote_ref = format!("{remote}/{}", self.remote_branch_name(branch_name));
+        let remote = self.repo.find_branch(&remote_ref, BranchType::Remote)?;
+        let head = remote.get().peel_to_commit()?;
+        let mut branch = self.repo.branch(branch_name, &head, false)?;
+        if track_upstream {
+            branch.set_upstream(Some(&remote_ref))?;
         }
+        Ok(())
     }
 
-    pub fn create_worktree(&self, worktree_name: &str) -> Worktree {
+    /// Creates `branch_name` unless it exists: from the branch on `remote`
+    /// when given (see [`find_remote_branch`]), otherwise from the base.
+    pub fn ensure_branch(
+        &self,
+        branch_name: &str,
+        track_upstream: bool,
+        remote: Option<&str>,
+    ) -> Result<()> {
+        if self.has_branch(branch_name) {
+            debug!("branch '{branch_name}' exists locally");
+            return Ok(());
+        }
+        match remote {
+            Some(remote) => {
+                debug!(
+                    "creating branch '{branch_name}' from {remote}/{}",
+                    self.remote_branch_name(branch_name)
+                );
+                self.create_branch_from_remote(branch_name, remote, track_upstream)
+            }
+            None => {
+                debug!("creating branch '{branch_name}' from '{BASE_BRANCH}'");
+                self.create_branch(branch_name, track_upstream)
+            }
+        }
+    }
+
+    pub fn create_worktree(&self, branch_name: &str) -> Result<Worktree> {
+        let branch = self.repo.find_branch(branch_name, BranchType::Local)?;
+        let reference = branch.into_reference();
         let mut options = WorktreeAddOptions::new();
-        options.checkout_existing(true);
+        options.reference(Some(&reference));
 
-        let worktree_path = PathBuf::from(format!("{}/{}", WORKTREE_DIR, worktree_name));
-        println!("Creating worktree at path: {:?}", worktree_path);
+        let worktree_path = self.worktree_path(branch_name);
+        if let Some(parent) = worktree_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // A failed add can leave an empty administrative directory behind,
+        // which would make this one fail too.
+        let admin = self
+            .repo
+            .commondir()
+            .join("worktrees")
+            .join(worktree_name(branch_name));
+        if admin.is_dir() && !admin.join("gitdir").exists() {
+            debug!("removing leftover {}", admin.display());
+            fs::remove_dir_all(&admin)?;
+        }
+        info!("Creating worktree at {}", worktree_path.display());
 
-        self.repo
-            .worktree(worktree_name, &worktree_path, Some(&options))
-            .unwrap()
+        let worktree = if self.sparse.is_empty() {
+            self.repo
+                .worktree(&worktree_name(branch_name), &worktree_path, Some(&options))?
+        } else {
+            self.create_sparse_worktree(branch_name, &worktree_path)?
+        };
+        self.link_caches(&worktree_path);
+        self.update_submodules(&worktree_path);
+        self.allow_direnv(&worktree_path);
+        Ok(worktree)
+    }
+
+    /// Adds the worktree without a checkout, then checks out only the
+    /// `sparse` directories (and the files at the top), so nothing else is
+    /// ever written. libgit2 can't skip the checkout, so this goes through
+    /// the CLI. A worktree that fails halfway is removed again.
+    fn create_sparse_worktree(&self, branch_name: &str, path: &Path) -> Result<Worktree> {
+        let path_arg = path.to_string_lossy();
+        self.run_ok(&["worktree", "add", "--no-checkout", &path_arg, branch_name])?;
+        let dirs: Vec<String> = self
+            .sparse
+            .iter()
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .collect();
+        let mut args = vec!["sparse-checkout", "set", "--cone", "--"];
+        args.extend(dirs.iter().map(String::as_str));
+        let checked_out = run_ok_in(self.runner(), path, &args)
+            .and_then(|_| run_ok_in(self.runner(), path, &["checkout"]));
+        if let Err(err) = checked_out {
+            if let Err(cleanup) = self.run_ok(&["worktree", "remove", "--force", &path_arg]) {
+                warn!("could not remove the half-created worktree: {cleanup:#}");
+            }
+            return Err(err.context(format!("sparse checkout of {branch_name} failed")));
+        }
+        info!("Checked out only {}", dirs.join(", "));
+        self.lookup_worktree(branch_name)
+            .with_context(|| format!("git did not register a worktree at {}", path.display()))
+    }
+
+    pub fn has_worktree(&self, branch_name: &str) -> bool {
+        self.lookup_worktree(branch_name).is_some()
+    }
+
+    /// The worktree of `branch_name`, created when there is none. An entry
+    /// whose directory was deleted by hand is pruned and created again;
+    /// that entry only, as other prunable worktrees may belong to scripts
+    /// or checkouts on unmounted drives.
+    pub fn ensure_worktree(&self, branch_name: &str) -> Result<Worktree> {
+        self.recover_interrupted_add(branch_name)?;
+        match self.lookup_worktree(branch_name) {
+            Some(worktree) if !worktree.path().exists() => {
+                let path = worktree.path().display().to_string();
+                if let WorktreeLockStatus::Locked(_) = worktree.is_locked()? {
+                    bail!(
+                        "{path} is gone, but its worktree entry is locked; \
+                         run `git worktree unlock {path}` if it is not coming back"
+                    );
+                }
+                warn!("{path} was deleted without git knowing; checking it out again");
+                worktree.prune(None)?;
+                self.create_worktree(branch_name)
+            }
+            Some(worktree) => {
+                debug!("reusing worktree at {}", worktree.path().display());
+                Ok(worktree)
+            }
+            None => self.create_worktree(branch_name),
+        }
+    }
+
+    /// Cleans up after a `worktree add` that was killed or failed halfway,
+    /// so the next add starts fresh: an administrative entry whose checkout
+    /// never finished (the index is written last), or a directory whose
+    /// `.git` file points at an entry that was never created. Returns
+    /// whether there was anything to clean up.
+    pub fn recover_interrupted_add(&self, branch_name: &str) -> Result<bool> {
+        let name = worktree_name(branch_name);
+        let admin_dir = self.repo.commondir().join("worktrees");
+        let admin = admin_dir.join(&name);
+        let path = self.worktree_path(branch_name);
+        let interrupted = if self.repo.find_worktree(&name).is_ok() {
+            !admin.join("index").exists()
+        } else {
+            let gitdir = fs::read_to_string(path.join(".git"))
+                .ok()
+                .and_then(|link| Some(PathBuf::from(link.strip_prefix("gitdir:")?.trim())));
+            gitdir.is_some_and(|gitdir| {
+                !gitdir.exists()
+                    && gitdir.parent().and_then(|dir| dir.canonicalize().ok())
+                        == admin_dir.canonicalize().ok()
+            })
+        };
+        if !interrupted {
+            return Ok(false);
+        }
+        warn!(
+            "{} was left half-created by an interrupted worktree add; starting over",
+            path.display()
+        );
+        if admin.is_dir() {
+            fs::remove_dir_all(&admin)?;
+        }
+        if path.exists() {
+            if !self.within_worktree_dir(&path) {
+                bail!(
+                    "{} is outside {}; remove it by hand and try again",
+                    path.display(),
+                    self.worktree_dir().display()
+                );
+            }
+            fs::remove_dir_all(&path)?;
+        }
+        Ok(true)
+    }
+
+    /// Resolves a tag, commit or other revision to a commit id, fetching
+    /// from the remote once if it isn't known locally.
+    pub fn resolve_commit(&self, rev: &str) -> Result<git2::Oid> {
+        let lookup =
+            || -> Result<git2::Oid> { Ok(self.repo.revparse_single(rev)?.peel_to_commit()?.id()) };
+        match lookup() {
+            Ok(oid) => Ok(oid),
+            Err(_) if self.has_remote() => {
+                self.run_remote(&["fetch", "--tags", &self.fetch_remote])?;
+                lookup().with_context(|| format!("unknown revision '{rev}'"))
+            }
+            Err(err) => Err(err.context(format!("unknown revision '{rev}'"))),
+        }
+    }
+
+    pub fn short_id(&self, oid: git2::Oid) -> Result<String> {
+        let object = self.repo.find_object(oid, None)?;
+        let short = object.short_id()?;
+        Ok(short.as_str().unwrap_or_default().to_string())
+    }
+
+    /// Adds a worktree with a detached HEAD at `oid`. libgit2 always creates
+    /// a branch for new worktrees, so this goes through the CLI.
+    pub fn create_detached_worktree(&self, name: &str, oid: git2::Oid) -> Result<PathBuf> {
+        let path = self.worktree_path(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        info!("Creating detached worktree at {}", path.display());
+        let path_arg = path.to_string_lossy();
+        self.run_ok(&["worktree", "add", "--detach", &path_arg, &oid.to_string()])?;
+        self.link_caches(&path);
+        self.update_submodules(&path);
+        self.allow_direnv(&path);
+        Ok(path)
+    }
+
+    /// Applies `[cache_links]` to a new worktree: each listed directory of
+    /// the main checkout is symlinked, hardlink-copied or copied into it.
+    /// Paths the checkout already has are left alone. Failures are
+    /// warnings, as a worktree without its cache only builds slower.
+    fn link_caches(&self, worktree_path: &Path) {
+        let root = self.root();
+        for (path, link) in &self.cache_links {
+            let (source, target) = (root.join(path), worktree_path.join(path));
+            if *link == CacheLink::Separate || target.symlink_metadata().is_ok() {
+                continue;
+            }
+            let linked = match link {
+                CacheLink::Symlink => self.share_cache(worktree_path, path, &source, &target),
+                CacheLink::Hardlink if source.is_dir() => {
+                    info!("Hardlinking {} from the main checkout", path.display());
+                    copy_tree(&source, &target, true)
+                        .and_then(|()| exclude_untracked(worktree_path, path, "cache_links"))
+                }
+                CacheLink::Copy if source.is_dir() => {
+                    info!("Copying {} from the main checkout", path.display());
+                    copy_tree(&source, &target, false)
+                        .and_then(|()| exclude_untracked(worktree_path, path, "cache_links"))
+                }
+                _ => Ok(()),
+            };
+            if let Err(err) = linked {
+                warn!(
+                    "could not link {} into the worktree: {err:#}",
+                    path.display()
+                );
+            }
+        }
+    }
+
+    /// Points a new worktree's `target` at the main checkout's `source`,
+    /// creating `source` when the main checkout hasn't built it yet so
+    /// every worktree shares one. Where symlinks fail or `core.symlinks` is
+    /// false, as on Windows without developer mode, a junction stands in
+    /// (on Windows), else a hardlinked copy.
+    fn share_cache(
+        &self,
+        worktree_path: &Path,
+        path: &Path,
+        source: &Path,
+        target: &Path,
+    ) -> Result<()> {
+        fs::create_dir_all(source)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let symlinks = self
+            .repo
+            .config()
+            .and_then(|config| config.get_bool("core.symlinks"))
+            .unwrap_or(true);
+        let linked = if symlinks {
+            symlink_dir(source, target)
+                .inspect_err(|err| debug!("could not symlink {}: {err}", path.display()))
+                .is_ok()
+        } else {
+            debug!("core.symlinks is false; not symlinking {}", path.display());
+            false
+        };
+        #[cfg(windows)]
+        let linked = linked
+            || junction(self.runner(), source, target)
+                .inspect_err(|err| debug!("could not make a junction: {err:#}"))
+                .is_ok();
+        if linked {
+            debug!("linked {} to {}", target.display(), source.display());
+        } else {
+            info!(
+                "Hardlinking {} from the main checkout; symlinks are unavailable",
+                path.display()
+            );
+            copy_tree(source, target, true)?;
+        }
+        exclude_untracked(worktree_path, path, "cache_links")
+    }
+
+    /// Checks out a new worktree's submodules, which `git worktree add`
+    /// leaves empty. A failure is a warning: the worktree is there, and the
+    /// command to finish the job is in it.
+    fn update_submodules(&self, worktree_path: &Path) {
+        if !self.submodules || !worktree_path.join(".gitmodules").is_file() {
+            return;
+        }
+        info!("Checking out submodules");
+        let args = ["submodule", "update", "--init", "--recursive"];
+        if let Err(err) = run_remote_in(self.runner(), worktree_path, &args, self.network) {
+            warn!(
+                "submodules in {} are not checked out ({err:#}); run `git {}` there",
+                worktree_path.display(),
+                args.join(" ")
+            );
+        }
+    }
+
+    /// With `direnv = true`, links the main checkout's `.envrc` into a new
+    /// worktree when git didn't check one out (it is untracked), then runs
+    /// `direnv allow` on it. A linked `.envrc` goes into `info/exclude`, or
+    /// every worktree would have an untracked file that `gc` refuses to
+    /// lose. A checked-out `.envrc` is only allowed when it matches the main
+    /// checkout's byte for byte: one from someone else's branch runs
+    /// whatever it says the moment you `cd` in. Failures are warnings: the
+    /// worktree itself is fine.
+    fn allow_direnv(&self, worktree_path: &Path) {
+        if !self.direnv {
+            return;
+        }
+        let envrc = worktree_path.join(".envrc");
+        let source = self.root().join(".envrc");
+        if !envrc.exists() && source.is_file() {
+            if let Err(err) = link_envrc(worktree_path, &source, &envrc) {
+                return warn!(
+                    "could not link .envrc into {}: {err:#}",
+                    worktree_path.display()
+                );
+            }
+            debug!("linked {} to {}", envrc.display(), source.display());
+        }
+        if !envrc.exists() {
+            return;
+        }
+        if fs::read(&envrc).ok() != fs::read(&source).ok() {
+            return warn!(
+                "{} is not the main checkout's; review it, then run `direnv allow` there",
+                envrc.display()
+            );
+        }
+        let mut command = Cmd::new("direnv");
+        command.arg("allow").arg(worktree_path);
+        debug!("running {}", command);
+        match self.runner.output(&command) {
+            Ok(output) if output.status.success() => info!("Allowed .envrc for direnv"),
+            Ok(output) => warn!(
+                "direnv allow failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            Err(err) => warn!("could not run direnv: {err:#}"),
+        }
+    }
+
+    /// What is wrong with a registered worktree's checkout once something
+    /// other than git has been at it (sync tools, copies, restores): its
+    /// `.git` link, the administrative directory's link back, and HEAD.
+    /// A checkout that is gone altogether is [`Git::stale_worktrees`]'s.
+    pub fn worktree_damage(&self, worktree: &WorktreeInfo) -> Result<Vec<String>> {
+        let mut damage = Vec::new();
+        if !worktree.path.is_dir() {
+            return Ok(damage);
+        }
+        let admin = self.repo.commondir().join("worktrees").join(&worktree.name);
+        let dot_git = worktree.path.join(".git");
+        if dot_git.is_dir() {
+            damage.push(".git is a directory, not a link to this repository".to_string());
+        } else {
+            match fs::read_to_string(&dot_git) {
+                Ok(contents) => match contents.trim().strip_prefix("gitdir: ") {
+                    Some(target) if same_path(&worktree.path.join(target), &admin) => {}
+                    Some(target) => damage.push(format!(
+                        ".git points at {target} instead of {}",
+                        admin.display()
+                    )),
+                    None => damage.push(".git is not a `gitdir:` link".to_string()),
+                },
+                Err(_) => damage.push(".git is missing".to_string()),
+            }
+        }
+        let back_link = fs::read_to_string(admin.join("gitdir")).unwrap_or_default();
+        if !same_path(Path::new(back_link.trim()), &dot_git) {
+            damage.push(format!(
+                "{} does not point back at the checkout",
+                admin.join("gitdir").display()
+            ));
+        }
+        let worktree = self.repo.find_worktree(&worktree.name)?;
+        let head = Repository::open_from_worktree(&worktree)
+            .and_then(|repo| repo.head()?.peel_to_commit().map(drop));
+        if let Err(err) = head {
+            damage.push(format!("HEAD does not resolve: {}", err.message()));
+        }
+        Ok(damage)
+    }
+
+    /// Puts the worktree registered as `name` back on `branch_name` when
+    /// its HEAD doesn't resolve. Returns whether it did.
+    pub fn repair_head(&self, name: &str, branch_name: &str) -> Result<bool> {
+        let worktree = self.repo.find_worktree(name)?;
+        let resolves = Repository::open_from_worktree(&worktree)
+            .and_then(|repo| repo.head()?.peel_to_commit().map(drop))
+            .is_ok();
+        if resolves || !self.has_branch(branch_name) {
+            return Ok(false);
+        }
+        let head = self
+            .repo
+            .commondir()
+            .join("worktrees")
+            .join(name)
+            .join("HEAD");
+        fs::write(head, format!("ref: refs/heads/{branch_name}\n"))?;
+        Ok(true)
+    }
+
+    /// Runs `git worktree repair` for every checkout under the worktree
+    /// directory, which fixes the links in both directions after a move.
+    /// A checkout whose `.git` file is missing or mangled is found through
+    /// the link back to it and relinked first.
+    pub fn repair_worktrees(&self) -> Result<String> {
+        let admin_dir = self.repo.commondir().join("worktrees");
+        for name in self.repo.worktrees()?.iter().flatten() {
+            let admin = admin_dir.join(name);
+            let back_link = fs::read_to_string(admin.join("gitdir")).unwrap_or_default();
+            let dot_git = PathBuf::from(back_link.trim());
+            let Some(checkout) = dot_git.parent().filter(|checkout| checkout.is_dir()) else {
+                continue;
+            };
+            let linked = fs::read_to_string(&dot_git).is_ok_and(|contents| {
+                contents
+                    .trim()
+                    .strip_prefix("gitdir: ")
+                    .is_some_and(|target| same_path(&checkout.join(target), &admin))
+            });
+            if !linked && !dot_git.is_dir() {
+                fs::write(&dot_git, format!("gitdir: {}\n", admin.display()))?;
+                info!("repair: relinked {}", dot_git.display());
+            }
+        }
+        let mut args = vec!["worktree".to_string(), "repair".to_string()];
+        for path in find_checkouts(&self.worktree_dir()) {
+            // Older git aborts on links into a directory that no longer
+            // exists, so re-point both sides before handing over to it.
+            let dot_git = path.join(".git");
+            let contents = fs::read_to_string(&dot_git)?;
+            if let Some(target) = contents.trim().strip_prefix("gitdir: ")
+                && let Some(name) = Path::new(target).file_name()
+                && admin_dir.join(name).is_dir()
+            {
+                let admin = admin_dir.join(name);
+                let back_link = fs::read_to_string(admin.join("gitdir")).unwrap_or_default();
+                if Path::new(target) != admin || Path::new(back_link.trim()) != dot_git {
+                    fs::write(&dot_git, format!("gitdir: {}\n", admin.display()))?;
+                    fs::write(admin.join("gitdir"), format!("{}\n", dot_git.display()))?;
+                    info!("repair: relinked {}", path.display());
+                }
+                // libgit2 records `commondir` as an absolute path; git itself
+                // writes it relative, which survives moving the repository.
+                let common = fs::read_to_string(admin.join("commondir")).unwrap_or_default();
+                if !admin.join(common.trim()).is_dir() {
+                    fs::write(admin.join("commondir"), "../..\n")?;
+                }
+            }
+            args.push(path.to_string_lossy().into_owned());
+        }
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let out = run_ok_in(self.runner(), &self.root(), &args)?;
+        Ok(out)
+    }
+
+    /// Moves a worktree's checkout into `trash_path` (as `tree/`, next to a
+    /// copy of its index), leaving the stale administrative entry for
+    /// `remove_worktree`. Returns the HEAD commit and whether it was detached.
+    pub fn trash_worktree(&self, label: &str, trash_path: &Path) -> Result<(String, bool)> {
+        let worktree = self.find_worktree(label)?;
+        let checkout = Repository::open_from_worktree(&worktree)?;
+        let head = checkout.head()?;
+        let detached = !head.is_branch();
+        let head = head.peel_to_commit()?.id().to_string();
+
+        fs::create_dir_all(trash_path)?;
+        let index = checkout.path().join("index");
+        if index.exists() {
+            fs::copy(&index, trash_path.join("index"))?;
+        }
+        fs::rename(worktree.path(), trash_path.join("tree"))?;
+        Ok((head, detached))
+    }
+
+    /// Re-creates a trashed worktree: checks the branch (or `head`, when
+    /// detached) out again, then swaps the saved files and index back in.
+    pub fn restore_worktree(
+        &self,
+        label: &str,
+        head: &str,
+        detached: bool,
+        trash_path: &Path,
+    ) -> Result<PathBuf> {
+        let oid = git2::Oid::from_str(head)?;
+        let path = if detached {
+            self.create_detached_worktree(label, oid)?
+        } else {
+            if !self.has_branch(label) {
+                let commit = self.repo.find_commit(oid)?;
+                self.repo.branch(label, &commit, false)?;
+            }
+            self.create_worktree(label)?.path().to_path_buf()
+        };
+
+        for entry in fs::read_dir(&path)? {
+            let entry = entry?;
+            if entry.file_name() == ".git" {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                fs::remove_dir_all(entry.path())?;
+            } else {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        let tree = trash_path.join("tree");
+        for entry in fs::read_dir(&tree)? {
+            let entry = entry?;
+            if entry.file_name() != ".git" {
+                fs::rename(entry.path(), path.join(entry.file_name()))?;
+            }
+        }
+        let index = trash_path.join("index");
+        if index.exists() {
+            let checkout = Repository::open(&path)?;
+            fs::copy(&index, checkout.path().join("index"))?;
+        }
+        fs::remove_dir_all(trash_path)?;
+        Ok(path)
+    }
+
+    pub fn lock_worktree(&self, branch_name: &str, reason: Option<&str>) -> Result<()> {
+        let worktree = self.find_worktree(branch_name)?;
+        worktree.lock(reason)?;
+        Ok(())
+    }
+
+    pub fn unlock_worktree(&self, branch_name: &str) -> Result<()> {
+        let worktree = self.find_worktree(branch_name)?;
+        worktree.unlock()?;
+        Ok(())
+    }
+
+    /// `Some(reason)` if the worktree is locked; the reason may be empty.
+    pub fn lock_reason(&self, branch_name: &str) -> Result<Option<String>> {
+        let Some(worktree) = self.lookup_worktree(branch_name) else {
+            return Ok(None);
+        };
+        Ok(match worktree.is_locked()? {
+            WorktreeLockStatus::Unlocked => None,
+            WorktreeLockStatus::Locked(reason) => Some(reason.unwrap_or_default()),
+        })
+    }
+
+    fn find_worktree(&self, branch_name: &str) -> Result<Worktree> {
+        self.lookup_worktree(branch_name)
+            .with_context(|| format!("no worktree for '{branch_name}'"))
+    }
+
+    /// Removes the worktree's administrative entry and its checkout. The
+    /// checkout is only deleted when it is a registered worktree inside the
+    /// worktree directory, or anywhere when `managed` (graft opened or
+    /// adopted it); anything else is refused before touching disk.
+    pub fn remove_worktree(&self, branch_name: &str, managed: bool) -> Result<()> {
+        // Checked up front: libgit2 deletes the checkout along with the entry.
+        self.check_removable(branch_name, managed)?;
+        let path = self.worktree_path(branch_name);
+        if let Some(worktree) = self.lookup_worktree(branch_name) {
+            let mut options = WorktreePruneOptions::new();
+            options.valid(true).working_tree(true);
+            worktree.prune(Some(&mut options))?;
+        }
+        if path.exists() {
+            fs::remove_dir_all(&path)?;
+        }
+        self.remove_empty_parents(&path);
+        Ok(())
+    }
+
+    /// Fails where [`Git::remove_worktree`] would refuse to delete
+    /// `branch_name`'s checkout: it is not a registered worktree, or it lives
+    /// outside the worktree directory and graft did not create it (`managed`).
+    pub fn check_removable(&self, branch_name: &str, managed: bool) -> Result<()> {
+        let path = self.worktree_path(branch_name);
+        if path.exists() {
+            if self.lookup_worktree(branch_name).is_none() {
+                bail!(
+                    "{} is not a registered worktree; not deleting it",
+                    path.display()
+                );
+            }
+            if !(self.within_worktree_dir(&path) || managed && self.may_delete(&path)) {
+                bail!(
+                    "{} is outside {}; not deleting it (`graft adopt` it first, or \
+                     `git worktree remove` it, if it is really {branch_name}'s checkout)",
+                    path.display(),
+                    self.worktree_dir().display()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Nested branches (feature/foo) leave empty parent directories behind
+    /// when their checkout goes.
+    fn remove_empty_parents(&self, path: &Path) {
+        let base = self.worktree_dir();
+        let mut parent = path.parent().filter(|_| path.starts_with(&base));
+        while let Some(dir) = parent {
+            if dir == base || fs::remove_dir(dir).is_err() {
+                break;
+            }
+            parent = dir.parent();
+        }
+    }
+
+    /// Renames branch `old` to `new` and moves its worktree along: the
+    /// checkout to where `new`'s belongs and the administrative entry to
+    /// `new`'s name, which is how graft finds it. Undoes the rename when the
+    /// move fails. Returns the new path.
+    pub fn rename_worktree(&self, old: &str, new: &str) -> Result<PathBuf> {
+        let worktree = self.find_worktree(old)?;
+        let from = worktree.path().to_path_buf();
+        let to = self.worktree_path(new);
+        let admin_dir = self.repo.commondir().join("worktrees");
+        let (admin_from, admin_to) = (
+            admin_dir.join(worktree.name().unwrap_or_default()),
+            admin_dir.join(worktree_name(new)),
+        );
+        if to.exists() || admin_to.exists() {
+            bail!("{} is taken by another worktree", to.display());
+        }
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // The CLI, unlike libgit2, repoints every worktree's HEAD.
+        self.run_ok(&["branch", "-m", old, new])?;
+        let moved = self.run_ok(&[
+            "worktree",
+            "move",
+            &from.to_string_lossy(),
+            &to.to_string_lossy(),
+        ]);
+        if let Err(err) = moved {
+            if let Err(undo) = self.run_ok(&["branch", "-m", new, old]) {
+                warn!("could not rename {new} back to {old}: {undo:#}");
+            }
+            return Err(err);
+        }
+        self.remove_empty_parents(&from);
+        // `worktree move` keeps the entry's name; the checkout points at it.
+        fs::rename(&admin_from, &admin_to)?;
+        fs::write(to.join(".git"), format!("gitdir: {}\n", admin_to.display()))?;
+        Ok(to)
+    }
+
+    /// Whether `path` is anything but the main checkout or a directory
+    /// holding it, once symlinks and `..` are resolved.
+    fn may_delete(&self, path: &Path) -> bool {
+        let Ok(path) = path.canonicalize() else {
+            return false;
+        };
+        let root = self.root();
+        let root = root.canonicalize().unwrap_or(root);
+        !root.starts_with(&path)
+    }
+
+    /// Whether `path` may be deleted recursively: strictly inside the
+    /// worktree directory once symlinks and `..` are resolved, and not
+    /// holding the main checkout.
+    pub fn within_worktree_dir(&self, path: &Path) -> bool {
+        let (Ok(base), Ok(path)) = (self.worktree_dir().canonicalize(), path.canonicalize()) else {
+            return false;
+        };
+        let root = self.root();
+        let root = root.canonicalize().unwrap_or(root);
+        path != base && path.starts_with(&base) && !root.starts_with(&path)
+    }
+
+    /// Whether git ignores the worktree directory; `None` when it lies
+    /// outside the repository.
+    pub fn worktree_dir_ignored(&self) -> Result<Option<bool>> {
+        let dir = self.worktree_dir();
+        let Ok(relative) = dir.strip_prefix(self.root()) else {
+            return Ok(None);
+        };
+        // The trailing slash marks it as a directory even before it exists.
+        Ok(Some(self.repo.is_path_ignored(relative.join(""))?))
+    }
+
+    /// Adds the worktree directory to `.git/info/exclude` unless git already
+    /// ignores it or it lies outside the repository. Returns whether it did.
+    pub fn exclude_worktree_dir(&self) -> Result<bool> {
+        if self.worktree_dir_ignored()? != Some(false) {
+            return Ok(false);
+        }
+        let dir = self.worktree_dir();
+        let relative = dir.strip_prefix(self.root())?;
+        let pattern = format!("/{}/\n", relative.to_string_lossy().replace('\\', "/"));
+        let path = self.repo.commondir().join("info").join("exclude");
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read {}", path.display()));
+            }
+        };
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            contents.push('\n');
+        }
+        contents.push_str(&pattern);
+        fs::write(&path, contents)?;
+        Ok(true)
+    }
+
+    /// Makes the main checkout's tracked files read-only, or writable again
+    /// for their owner. Returns how many files changed.
+    pub fn set_main_read_only(&self, read_only: bool) -> Result<usize> {
+        let root = self.root();
+        let mut changed = 0;
+        for entry in self.repo.index()?.iter() {
+            let path = root.join(String::from_utf8_lossy(&entry.path).as_ref());
+            // Symlinks would pass the change on to their target.
+            let Ok(metadata) = fs::symlink_metadata(&path) else {
+                continue;
+            };
+            if !metadata.is_file() {
+                continue;
+            }
+            let mut permissions = metadata.permissions();
+            if set_writable(&mut permissions, !read_only) {
+                fs::set_permissions(&path, permissions)?;
+                changed += 1;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Worktrees whose administrative files outlived their checkout.
+    pub fn stale_worktrees(&self) -> Result<Vec<String>> {
+        let mut stale = Vec::new();
+        for name in self.repo.worktrees()?.iter().flatten() {
+            if self.repo.find_worktree(name)?.is_prunable(None)? {
+                stale.push(name.to_string());
+            }
+        }
+        Ok(stale)
+    }
+
+    /// Prunes administrative entries of worktrees whose directories are gone.
+    pub fn prune_worktrees(&self) -> Result<Vec<String>> {
+        let mut pruned = Vec::new();
+        for name in self.repo.worktrees()?.iter().flatten() {
+            let worktree = self.repo.find_worktree(name)?;
+            if worktree.is_prunable(None)? {
+                worktree.prune(None)?;
+                pruned.push(name.to_string());
+            }
+        }
+        Ok(pruned)
     }
 
-    pub fn ensure_worktree(&self, worktree_name: &str) -> Worktree {
-        match self.repo.find_worktree(worktree_name) {
-            Ok(worktree) => worktree,
-            Err(_) => self.create_worktree(worktree_name),
+    /// Writes the commits on `branch_name` that its upstream (else the base
+    /// branch, preferring the remote's copy) doesn't have to a bundle at
+    /// `file`, and checks it reads back.
+    pub fn bundle_unpushed(&self, branch_name: &str, file: &Path) -> Result<Bundled> {
+        let refname = format!("refs/heads/{branch_name}");
+        let branch = self.repo.find_branch(branch_name, BranchType::Local)?;
+        let head = branch.get().peel_to_commit()?.id();
+        let base_refs = [
+            format!("refs/remotes/{}/{}", self.fetch_remote, BASE_BRANCH),
+            format!("refs/heads/{BASE_BRANCH}"),
+        ];
+        let upstream = branch
+            .upstream()
+            .ok()
+            .and_then(|upstream| upstream.get().target());
+        let base = base_refs
+            .iter()
+            .filter(|base| **base != refname)
+            .find_map(|refname| self.repo.refname_to_id(refname).ok());
+        let basis = upstream
+            .or(base)
+            .and_then(|other| self.repo.merge_base(head, other).ok());
+
+        let file_arg = file.to_string_lossy();
+        let mut args = vec!["bundle", "create", &file_arg, &refname];
+        let exclude;
+        let commits = match basis {
+            Some(basis) => {
+                exclude = format!("^{basis}");
+                args.push(&exclude);
+                self.repo.graph_ahead_behind(head, basis)?.0
+            }
+            None => {
+                let mut walk = self.repo.revwalk()?;
+                walk.push(head)?;
+                walk.count()
+            }
+        };
+        if commits > 0 {
+            self.run_ok(&args)?;
+            self.run_ok(&["bundle", "verify", "--quiet", &file_arg])?;
         }
+        Ok(Bundled {
+            basis: basis.map(|oid| oid.to_string()),
+            commits,
+        })
     }
 
-    pub fn current_branch(&self) -> String {
-        todo!()
+    /// Adds the commits in a bundle from [`Git::bundle_unpushed`] to the
+    /// object store, without touching any ref.
+    pub fn unbundle(&self, file: &Path) -> Result<()> {
+        self.run_ok(&["bundle", "unbundle", &file.to_string_lossy()])?;
+        Ok(())
     }
 
-    pub fn delete_branch() -> () {
-        todo!()
+    /// Files in the worktree that differ from HEAD, staged or not, with
+    /// untracked ones: (still present, deleted). Relative to the worktree.
+    pub fn uncommitted_files(&self, worktree_path: &Path) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+        let repo = Repository::open(worktree_path)?;
+        let mut options = StatusOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .include_ignored(false);
+        let mut present = Vec::new();
+        let mut deleted = Vec::new();
+        for entry in repo.statuses(Some(&mut options))?.iter() {
+            let Some(path) = entry.path() else {
+                continue;
+            };
+            if worktree_path.join(path).symlink_metadata().is_ok() {
+                present.push(PathBuf::from(path));
+            } else {
+                deleted.push(PathBuf::from(path));
+            }
+        }
+        Ok((present, deleted))
+    }
+
+    pub fn delete_branch(&self, branch_name: &str) -> Result<()> {
+        let mut branch = self.repo.find_branch(branch_name, BranchType::Local)?;
+        branch.delete()?;
+        Ok(())
+    }
+}
+
+/// Tracked files with uncommitted changes and untracked paths in the
+/// checkout at `dir`, leaving out what happens inside nested repositories.
+fn count_changes(repo: &Repository, dir: &Path) -> Result<(usize, usize)> {
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .include_ignored(false)
+        .exclude_submodules(true);
+    let (mut dirty, mut untracked) = (0, 0);
+    for entry in repo.statuses(Some(&mut options))?.iter() {
+        if entry.status() != git2::Status::WT_NEW {
+            dirty += 1;
+        } else if !entry.path().is_some_and(|path| is_nested(dir, path)) {
+            untracked += 1;
+        }
+    }
+    // A submodule moved to another commit is a change here; what is
+    // modified inside it is not.
+    for submodule in repo.submodules()? {
+        let Some(name) = submodule.name() else {
+            continue;
+        };
+        let status = repo.submodule_status(name, SubmoduleIgnore::Dirty)?;
+        if status.intersects(SubmoduleStatus::INDEX_MODIFIED | SubmoduleStatus::WD_MODIFIED) {
+            dirty += 1;
+        }
+    }
+    Ok((dirty, untracked))
+}
+
+/// Whether `path`, as git status reports it inside `dir`, is a directory
+/// holding a repository of its own.
+fn is_nested(dir: &Path, path: &str) -> bool {
+    path.ends_with('/') && dir.join(path).join(".git").exists()
+}
+
+/// See [`Git::nested_repos`].
+fn find_nested(dir: &Path) -> Result<Vec<NestedRepo>> {
+    let repo = Repository::open(dir)?;
+    let mut paths = Vec::new();
+    for submodule in repo.submodules()? {
+        // Not initialized: nothing checked out to lose.
+        if dir.join(submodule.path()).join(".git").exists() {
+            paths.push((submodule.path().to_path_buf(), true));
+        }
+    }
+    // Untracked and ignored directories are reported whole; a clone shows up
+    // as one, unless it sits deeper inside an ignored directory.
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(true)
+        .exclude_submodules(true);
+    for entry in repo.statuses(Some(&mut options))?.iter() {
+        if let Some(path) = entry.path().filter(|path| is_nested(dir, path)) {
+            paths.push((PathBuf::from(path.trim_end_matches('/')), false));
+        }
+    }
+
+    let mut found = Vec::new();
+    for (path, submodule) in paths {
+        let checkout = dir.join(&path);
+        let Ok(nested) = Repository::open(&checkout) else {
+            continue;
+        };
+        let (dirty, untracked) = count_changes(&nested, &checkout)?;
+        let inner = find_nested(&checkout)?;
+        found.push(NestedRepo {
+            path: path.clone(),
+            submodule,
+            changed_files: dirty + untracked,
+            unpushed: unpushed(&nested),
+        });
+        found.extend(inner.into_iter().map(|mut inner| {
+            inner.path = path.join(inner.path);
+            inner
+        }));
+    }
+    Ok(found)
+}
+
+/// Commits on HEAD that no remote-tracking branch has; all of them in a
+/// repository without remotes.
+fn unpushed(repo: &Repository) -> usize {
+    let Ok(mut walk) = repo.revwalk() else {
+        return 0;
+    };
+    if walk.push_head().is_err() {
+        return 0;
+    }
+    let _ = walk.hide_glob("refs/remotes");
+    walk.count()
+}
+
+/// Directories below `dir` that hold a worktree checkout (a `.git` file).
+fn find_checkouts(dir: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return found;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_dir() || entry.file_name() == TRASH_DIR {
+            continue;
+        }
+        if path.join(".git").is_file() {
+            found.push(path);
+        } else {
+            found.extend(find_checkouts(&path));
+        }
+    }
+    found
+}
+
+/// Turns the owner's write permission on, or everyone's off. Returns
+/// whether anything changed.
+#[cfg(unix)]
+fn set_writable(permissions: &mut fs::Permissions, writable: bool) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = permissions.mode();
+    let new_mode = if writable {
+        mode | 0o200
+    } else {
+        mode & !0o222
+    };
+    permissions.set_mode(new_mode);
+    new_mode != mode
+}
+
+// Only the read-only attribute exists here; clearing it is what we want.
+#[cfg(not(unix))]
+#[allow(clippy::permissions_set_readonly_false)]
+fn set_writable(permissions: &mut fs::Permissions, writable: bool) -> bool {
+    if permissions.readonly() != writable {
+        return false;
+    }
+    permissions.set_readonly(!writable);
+    true
+}
+
+/// The checkout containing `dir`, main or linked; `None` outside a
+/// repository and in bare ones.
+pub fn checkout_at(dir: &Path) -> Result<Option<Checkout>> {
+    let repo = match Repository::discover(dir) {
+        Ok(repo) => repo,
+        Err(err) if err.code() == ErrorCode::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let Some(path) = repo.workdir() else {
+        return Ok(None);
+    };
+    let index_changed = fs::metadata(repo.path().join("index"))
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|since| since.as_millis() as u64);
+    Ok(Some(Checkout {
+        // libgit2 reports it with a trailing slash.
+        path: path.components().collect(),
+        branch: head_branch(&repo),
+        head: repo
+            .head()
+            .ok()
+            .and_then(|head| head.target())
+            .map(|oid| oid.to_string()),
+        index_changed,
+    }))
+}
+
+/// Asks `remotes`, in order, for `branch_name` (the remote's name for it,
+/// see [`Git::remote_branch_name`]) and returns the first that
+/// has it. An unreachable remote only fails the lookup if no other remote
+/// has the branch; one that doesn't answer in time fails it with an
+/// [`io::ErrorKind::TimedOut`] in the chain (see [`timed_out`]).
+/// A free function taking the repository root so it can run on another
+/// thread while libgit2 work (which isn't `Sync`) continues.
+pub fn find_remote_branch(
+    runner: &dyn ProcessRunner,
+    root: &Path,
+    remotes: &[String],
+    branch_name: &str,
+    network: Network,
+) -> Result<Option<String>> {
+    let refname = format!("refs/heads/{branch_name}");
+    let mut failure = None;
+    for remote in remotes {
+        let args = ["ls-remote", "--heads", remote, &refname];
+        match run_remote_in(runner, root, &args, network) {
+            Ok(out) if !out.trim().is_empty() => return Ok(Some(remote.clone())),
+            Ok(_) => debug!("'{branch_name}' is not on {remote}"),
+            Err(err) => {
+                debug!("could not ask {remote} for '{branch_name}': {err:#}");
+                failure.get_or_insert(err);
+            }
+        }
+    }
+    match failure {
+        Some(err) => Err(err),
+        None => Ok(None),
+    }
+}
+
+fn not_a_repository(path: &Path) -> anyhow::Error {
+    let dir = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let mut message = format!("not inside a git repository: {}", dir.display());
+    let nearby = nearby_repositories(&dir);
+    if !nearby.is_empty() {
+        message.push_str("\n\nRepositories nearby:");
+        for repo in nearby {
+            message.push_str(&format!("\n  {}", repo.display()));
+        }
+    }
+    let registered = Registry::load().map(|r| r.repos).unwrap_or_default();
+    if !registered.is_empty() {
+        message.push_str("\n\nRepositories registered with graft:");
+        for repo in registered {
+            message.push_str(&format!("\n  {}", repo.display()));
+        }
+    }
+    message.push_str(
+        "\n\nRun graft from inside a repository, point it at one with --repo <path>, \
+         or clone one first with `git clone <url>`.",
+    );
+    anyhow::anyhow!(message)
+}
+
+/// Repositories among the children of `dir` and of its parent.
+fn nearby_repositories(dir: &Path) -> Vec<PathBuf> {
+    const LIMIT: usize = 8;
+    let mut found = Vec::new();
+    for base in [Some(dir), dir.parent()].into_iter().flatten() {
+        let Ok(entries) = fs::read_dir(base) else {
+            continue;
+        };
+        let mut repos: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.join(".git").exists())
+            .collect();
+        repos.sort();
+        found.extend(repos);
+    }
+    found.dedup();
+    found.truncate(LIMIT);
+    found
+}
+
+/// Whether `err` came from a command that outlived its timeout.
+pub fn timed_out(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<io::Error>())
+        .any(|err| err.kind() == io::ErrorKind::TimedOut)
+}
+
+pub fn run_ok_in(runner: &dyn ProcessRunner, dir: &Path, args: &[&str]) -> Result<String> {
+    output_ok(runner, &git_in(dir, args), args)
+}
+
+/// What git says when the network, not the request, failed; lowercase.
+/// Missing refs, refused credentials and the like fail the same way again.
+const TRANSIENT_ERRORS: &[&str] = &[
+    "could not resolve host",
+    "temporary failure in name resolution",
+    "connection reset",
+    "connection refused",
+    "connection timed out",
+    "operation timed out",
+    "network is unreachable",
+    "the remote end hung up unexpectedly",
+    "early eof",
+    "unexpected disconnect",
+    "rpc failed",
+    "the requested url returned error: 5",
+    "gnutls_handshake",
+    "ssl_connect",
+];
+
+/// Whether another try of a remote command that failed with `err` might
+/// work: it timed out, or git blamed the network.
+fn transient(err: &anyhow::Error) -> bool {
+    let message = format!("{err:#}").to_lowercase();
+    timed_out(err) || TRANSIENT_ERRORS.iter().any(|error| message.contains(error))
+}
+
+/// [`run_ok_in`] for a command that talks to a remote: killed once it
+/// outlives its timeout from `network` (`ls-remote` has the lookup one),
+/// and run again after a timeout or network failure, up to
+/// `network.retries` times.
+pub fn run_remote_in(
+    runner: &dyn ProcessRunner,
+    dir: &Path,
+    args: &[&str],
+    network: Network,
+) -> Result<String> {
+    let timeout = match args.first() {
+        Some(&"ls-remote") => network.lookup_timeout,
+        _ => network.fetch_timeout,
+    };
+    let mut command = git_in(dir, args);
+    command.timeout(timeout);
+    let mut wait = Duration::from_secs(1);
+    for _ in 0..network.retries {
+        match output_ok(runner, &command, args) {
+            Err(err) if transient(&err) => {
+                debug!("{err:#}; trying again in {}s", wait.as_secs());
+                thread::sleep(wait);
+                wait *= 2;
+            }
+            result => return result,
+        }
+    }
+    output_ok(runner, &command, args)
+}
+
+/// Fetches `remote_name` from `remote` into its remote-tracking branch;
+/// [`Git::fetch_branch`] for callers on another thread.
+pub fn fetch_branch_in(
+    runner: &dyn ProcessRunner,
+    dir: &Path,
+    remote: &str,
+    remote_name: &str,
+    network: Network,
+) -> Result<()> {
+    let refspec = format!("+refs/heads/{remote_name}:refs/remotes/{remote}/{remote_name}");
+    run_remote_in(runner, dir, &["fetch", remote, &refspec], network)?;
+    Ok(())
+}
+
+fn git_in(dir: &Path, args: &[&str]) -> Cmd {
+    let mut command = Cmd::new("git");
+    command.arg("-C").arg(dir).args(args);
+    command
+}
+
+/// Runs `command`, git with `args`, and returns its stdout if it succeeded.
+fn output_ok(runner: &dyn ProcessRunner, command: &Cmd, args: &[&str]) -> Result<String> {
+    debug!("running {}", command);
+    let output = runner.output(command).context("failed to execute git")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Moves everything in `from` into `to`, merging directories and replacing
+/// files that exist in both, then removes `from`.
+pub fn overlay_dir(from: &Path, to: &Path) -> Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() && target.is_dir() {
+            overlay_dir(&entry.path(), &target)?;
+            continue;
+        }
+        if target.is_dir() {
+            fs::remove_dir_all(&target)?;
+        } else if target.exists() {
+            fs::remove_file(&target)?;
+        }
+        fs::rename(entry.path(), &target)?;
+    }
+    fs::remove_dir(from)?;
+    Ok(())
+}
+
+/// Name of the worktree's administrative entry; git forbids `/` there.
+pub fn worktree_name(branch_name: &str) -> String {
+    branch_name.replace('/', "-")
+}
+
+/// Whether both paths exist and are the same once symlinks are resolved.
+fn same_path(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn head_branch(repo: &Repository) -> Option<String> {
+    let head = repo.head().ok()?;
+    if !head.is_branch() {
+        return None;
+    }
+    head.shorthand().map(str::to_string)
+}
+
+/// Links (copies, off unix) the main checkout's `.envrc` to `target`, and
+/// has git ignore it unless something already does.
+fn link_envrc(worktree_path: &Path, source: &Path, target: &Path) -> Result<()> {
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(source, target)?;
+    #[cfg(not(unix))]
+    fs::copy(source, target)?;
+    exclude_untracked(worktree_path, Path::new(".envrc"), "direnv = true")
+}
+
+/// Adds `path` (relative to the worktree) to the repository's
+/// `info/exclude` when git sees it, or anything in it, as untracked there,
+/// noting `why`. A symlink needs this even when a `dir/` pattern ignores
+/// the directory it points at: git doesn't follow it.
+pub fn exclude_untracked(worktree_path: &Path, path: &Path, why: &str) -> Result<()> {
+    let repo = Repository::open(worktree_path)?;
+    // Untracked means neither in the index nor ignored; a status call
+    // would walk every file of a copied directory.
+    let name = path.to_string_lossy().replace('\\', "/");
+    let tracked = repo.index()?.iter().any(|entry| {
+        let entry = String::from_utf8_lossy(&entry.path);
+        entry == name || entry.starts_with(&format!("{name}/"))
+    });
+    if tracked || repo.status_should_ignore(path)? {
+        return Ok(());
+    }
+    let exclude = repo.commondir().join("info").join("exclude");
+    let mut contents = fs::read_to_string(&exclude).unwrap_or_default();
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    let pattern = format!("/{}", path.to_string_lossy());
+    contents.push_str(&format!(
+        "# linked into worktrees by graft ({why})\n{pattern}\n"
+    ));
+    if let Some(dir) = exclude.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&exclude, contents)?;
+    info!("Added {} to {}", pattern, exclude.display());
+    Ok(())
+}
+
+/// Points `target` at `source` with a symbolic link.
+fn symlink_dir(source: &Path, target: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    return std::os::unix::fs::symlink(source, target);
+    #[cfg(windows)]
+    return std::os::windows::fs::symlink_dir(source, target);
+}
+
+/// Points `target` at `source` with an NTFS junction, which needs neither
+/// developer mode nor the symlink privilege.
+#[cfg(windows)]
+fn junction(runner: &dyn ProcessRunner, source: &Path, target: &Path) -> Result<()> {
+    let mut command = Cmd::new("cmd");
+    command.args(["/C", "mklink", "/J"]).arg(target).arg(source);
+    let output = runner.output(&command)?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Recreates the directory tree at `source` under `target`, with every file
+/// hard-linked when `hardlink` is set (no extra space) and copied
+/// otherwise. Files are copied where linking fails, e.g. across
+/// filesystems.
+///
+/// Copies are copy-on-write clones where the filesystem has them:
+/// `fs::copy` goes through `copy_file_range` on Linux, which btrfs and XFS
+/// answer with a reflink when both ends are on the same filesystem, and
+/// through `fclonefileat` on APFS. Elsewhere it falls back to copying the
+/// bytes.
+pub fn copy_tree(source: &Path, target: &Path, hardlink: bool) -> Result<()> {
+    fs::create_dir_all(target)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let (from, to) = (entry.path(), target.join(entry.file_name()));
+        let kind = entry.file_type()?;
+        if kind.is_dir() {
+            copy_tree(&from, &to, hardlink)?;
+        } else if kind.is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(fs::read_link(&from)?, &to)?;
+            #[cfg(not(unix))]
+            fs::copy(&from, &to)?;
+        } else if !hardlink || fs::hard_link(&from, &to).is_err() {
+            fs::copy(&from, &to).with_context(|| format!("could not copy {}", from.display()))?;
+        }
     }
+    Ok(())
 }
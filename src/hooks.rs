@@ -0,0 +1,79 @@
+//! Repository hooks: executables in `.graft/hooks/`, run in the worktree
+//! with `GRAFT_BRANCH`, `GRAFT_WORKTREE` and `GRAFT_ROOT` set.
+
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use tracing::{debug, info};
+
+use crate::config::{REPO_DIR, SecretsConfig};
+use crate::process::{Cmd, ProcessRunner};
+use crate::secrets;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Hook {
+    /// After a new worktree is checked out, e.g. to install dependencies.
+    PostCreate,
+    /// Before a worktree is removed; failing stops the removal.
+    PreRemove,
+}
+
+impl Hook {
+    pub const ALL: [Hook; 2] = [Hook::PostCreate, Hook::PreRemove];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::PostCreate => "post-create",
+            Hook::PreRemove => "pre-remove",
+        }
+    }
+}
+
+/// Runs `hook` for `branch` if the repository at `root` has one, with `env`
+/// set on top. Fails when it exits non-zero.
+pub fn run(
+    runner: &dyn ProcessRunner,
+    root: &Path,
+    hook: Hook,
+    branch: &str,
+    worktree: &Path,
+    env: &[(String, String)],
+) -> Result<()> {
+    let path = root.join(REPO_DIR).join("hooks").join(hook.name());
+    if !path.is_file() {
+        return Ok(());
+    }
+    info!("Running the {} hook", hook.name());
+    let mut command = Cmd::new(&path);
+    command
+        .current_dir(worktree)
+        .env("GRAFT_BRANCH", branch)
+        .env("GRAFT_WORKTREE", worktree)
+        .env("GRAFT_ROOT", root);
+    for (key, value) in env {
+        command.env(key, value);
+    }
+    debug!("running {}", command);
+    let status = runner
+        .status(&command)
+        .with_context(|| format!("failed to run {}", path.display()))?;
+    if !status.success() {
+        bail!("the {} hook failed ({status})", hook.name());
+    }
+    Ok(())
+}
+
+/// Sets up a new worktree: the `[secrets]` command first, since the
+/// post-create hook may need what it writes, then the hook.
+pub fn post_create(
+    runner: &dyn ProcessRunner,
+    root: &Path,
+    secrets: &SecretsConfig,
+    branch: &str,
+    worktree: &Path,
+    env: &[(String, String)],
+) -> Result<()> {
+    secrets::provision(runner, secrets, root, branch, worktree, env)
+        .context("skipped the post-create hook")?;
+    run(runner, root, Hook::PostCreate, branch, worktree, env)
+}
@@ -0,0 +1,244 @@
+//! Issue trackers for `graft new --issue`: the issue's title, slugified,
+//! names the branch, and its link is kept with the worktree. GitHub goes
+//! through `gh`, which has its own login; Jira and Linear through `curl`,
+//! with a token from the environment or `[issues] token_command`.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use serde_json::{Value, json};
+use tracing::debug;
+
+use crate::config::{IssueTracker, IssuesConfig};
+use crate::process::{Cmd, ProcessRunner};
+
+/// How long a tracker gets to answer.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest slug taken from a title; cut at a word.
+const SLUG_LENGTH: usize = 50;
+
+const LINEAR_API: &str = "https://api.linear.app/graphql";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// What the tracker calls it: `1234`, `PROJ-12`, `ENG-7`.
+    pub key: String,
+    pub title: String,
+    pub url: String,
+}
+
+/// Something issues can be looked up in.
+pub trait Tracker {
+    fn lookup(&self, runner: &dyn ProcessRunner, id: &str) -> Result<Issue>;
+}
+
+/// GitHub issues of the repository's remote, through `gh`.
+pub struct GitHub {
+    pub root: PathBuf,
+}
+
+/// A Jira site, e.g. `https://acme.atlassian.net`, with `JIRA_USER` (the
+/// account's email) and `JIRA_API_TOKEN`.
+pub struct Jira {
+    pub url: String,
+    pub token_command: Option<String>,
+}
+
+/// Linear, with `LINEAR_API_KEY`.
+pub struct Linear {
+    pub token_command: Option<String>,
+}
+
+impl Tracker for GitHub {
+    fn lookup(&self, runner: &dyn ProcessRunner, id: &str) -> Result<Issue> {
+        let number = id.trim_start_matches('#');
+        let mut command = Cmd::new("gh");
+        command
+            .args(["issue", "view", number, "--json", "number,title,url"])
+            .current_dir(&self.root)
+            .timeout(TIMEOUT);
+        let issue = fetch_json(runner, &command, "gh")?;
+        Ok(Issue {
+            key: issue["number"]
+                .as_u64()
+                .map_or(number.to_string(), |n| n.to_string()),
+            title: field(&issue, "title")?,
+            url: field(&issue, "url")?,
+        })
+    }
+}
+
+impl Tracker for Jira {
+    fn lookup(&self, runner: &dyn ProcessRunner, id: &str) -> Result<Issue> {
+        let Some(user) = env::var("JIRA_USER").ok().filter(|user| !user.is_empty()) else {
+            bail!("set JIRA_USER to the email of the Jira account");
+        };
+        let token = token(runner, "JIRA_API_TOKEN", self.token_command.as_deref())?;
+        let base = self.url.trim_end_matches('/');
+        let url = format!("{base}/rest/api/2/issue/{id}?fields=summary");
+        let mut command = curl(
+            r#"curl -fsS -u "$JIRA_USER:$GRAFT_ISSUE_TOKEN" "$1""#,
+            &token,
+        );
+        command.arg(&url).env("JIRA_USER", user);
+        let issue = fetch_json(runner, &command, "Jira")?;
+        let key = field(&issue, "key")?;
+        Ok(Issue {
+            title: field(&issue["fields"], "summary")?,
+            url: format!("{base}/browse/{key}"),
+            key,
+        })
+    }
+}
+
+impl Tracker for Linear {
+    fn lookup(&self, runner: &dyn ProcessRunner, id: &str) -> Result<Issue> {
+        let token = token(runner, "LINEAR_API_KEY", self.token_command.as_deref())?;
+        let query = json!({
+            "query": "query($id: String!) { issue(id: $id) { identifier title url } }",
+            "variables": { "id": id },
+        });
+        let mut command = curl(
+            r#"curl -fsS -H "Authorization: $GRAFT_ISSUE_TOKEN" -H 'Content-Type: application/json' -d "$1" "$2""#,
+            &token,
+        );
+        command.arg(query.to_string()).arg(LINEAR_API);
+        let response = fetch_json(runner, &command, "Linear")?;
+        if let Some(message) = response["errors"][0]["message"].as_str() {
+            bail!("Linear: {message}");
+        }
+        let issue = &response["data"]["issue"];
+        Ok(Issue {
+            key: field(issue, "identifier")?,
+            title: field(issue, "title")?,
+            url: field(issue, "url")?,
+        })
+    }
+}
+
+/// The tracker `[issues]` names, else one guessed from `id`: numbers are
+/// GitHub's, keys like `PROJ-12` Jira's when a Jira site is configured and
+/// Linear's otherwise.
+pub fn tracker(config: &IssuesConfig, root: &Path, id: &str) -> Result<Box<dyn Tracker>> {
+    let kind = config.tracker.unwrap_or_else(|| {
+        if id
+            .trim_start_matches('#')
+            .chars()
+            .all(|c| c.is_ascii_digit())
+        {
+            IssueTracker::Github
+        } else if config.jira_url.is_some() {
+            IssueTracker::Jira
+        } else {
+            IssueTracker::Linear
+        }
+    });
+    let token_command = config.token_command.clone();
+    Ok(match kind {
+        IssueTracker::Github => Box::new(GitHub {
+            root: root.to_path_buf(),
+        }),
+        IssueTracker::Jira => {
+            let Some(url) = config.jira_url.clone() else {
+                bail!("set issues.jira_url to the Jira site, e.g. https://acme.atlassian.net");
+            };
+            Box::new(Jira { url, token_command })
+        }
+        IssueTracker::Linear => Box::new(Linear { token_command }),
+    })
+}
+
+/// Looks `id` up in the tracker [`tracker`] picks.
+pub fn lookup(
+    runner: &dyn ProcessRunner,
+    config: &IssuesConfig,
+    root: &Path,
+    id: &str,
+) -> Result<Issue> {
+    tracker(config, root, id)?
+        .lookup(runner, id)
+        .with_context(|| format!("could not look up issue {id}"))
+}
+
+/// The `[issues] branch` template filled in: `{key}` and `{slug}`, the
+/// title in lowercase words joined by `-`.
+pub fn branch_name(template: &str, issue: &Issue) -> String {
+    template
+        .replace("{key}", &issue.key)
+        .replace("{slug}", &slugify(&issue.title))
+}
+
+fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for word in title
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        if !slug.is_empty() && slug.len() + 1 + word.len() > SLUG_LENGTH {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&word.to_ascii_lowercase());
+    }
+    slug.truncate(SLUG_LENGTH);
+    slug
+}
+
+/// A `curl` run through `sh`, so the token travels in the environment
+/// rather than on a command line anyone can list.
+fn curl(script: &str, token: &str) -> Cmd {
+    let mut command = Cmd::new("sh");
+    command
+        .args(["-c", script, "graft"])
+        .env("GRAFT_ISSUE_TOKEN", token)
+        .timeout(TIMEOUT);
+    command
+}
+
+/// The token in `var`, else what `command` prints (a keychain lookup such
+/// as `security find-generic-password -s linear -w`).
+fn token(runner: &dyn ProcessRunner, var: &str, command: Option<&str>) -> Result<String> {
+    if let Some(token) = env::var(var).ok().filter(|token| !token.is_empty()) {
+        return Ok(token);
+    }
+    let Some(script) = command else {
+        bail!("set {var}, or issues.token_command to read the token from a keychain");
+    };
+    let mut command = Cmd::new("sh");
+    command.arg("-c").arg(script);
+    let output = runner
+        .output(&command)
+        .context("failed to run issues.token_command")?;
+    if !output.status.success() {
+        bail!("issues.token_command failed ({})", output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn fetch_json(runner: &dyn ProcessRunner, command: &Cmd, tracker: &str) -> Result<Value> {
+    debug!("running {}", command);
+    let output = runner
+        .output(command)
+        .with_context(|| format!("failed to ask {tracker}"))?;
+    if !output.status.success() {
+        bail!(
+            "{tracker} answered with an error ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    serde_json::from_slice(&output.stdout)
+        .with_context(|| format!("{tracker} sent something other than JSON"))
+}
+
+fn field(value: &Value, name: &str) -> Result<String> {
+    match value[name].as_str() {
+        Some(text) => Ok(text.to_string()),
+        None => bail!("the answer has no {name}"),
+    }
+}
@@ -0,0 +1,21 @@
+//! graft as a library: the binary is a thin `main` over [`commands::dispatch`],
+//! and `tests/` drives the same code with a [`process::FakeRunner`].
+
+pub mod capabilities;
+pub mod cli;
+pub mod commands;
+pub mod config;
+pub mod git;
+pub mod hooks;
+pub mod issues;
+pub mod logging;
+pub mod process;
+pub mod progress;
+pub mod registry;
+pub mod secrets;
+pub mod state;
+pub mod terminal;
+pub mod timesheet;
+pub mod timings;
+pub mod units;
+pub mod zellij;
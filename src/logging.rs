@@ -0,0 +1,98 @@
+//! Diagnostics go through `tracing`: progress at info, problems at warn,
+//! spawned commands and provisioning decisions at debug. Reports (`ls`,
+//! `du`, ...) are plain stdout and unaffected by verbosity.
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::path::Path;
+use std::sync::Mutex;
+
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{Layer, fmt as tfmt};
+
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::git::Git;
+use crate::progress;
+
+const LOG_FILE: &str = "graft.log";
+/// `graft.log` moves to `graft.log.1` once it grows past this.
+const LOG_FILE_MAX: u64 = 1024 * 1024;
+
+/// Sets up stderr output at the level `-v`/`-q` select and, if the
+/// repository's config enables `log_file`, a debug-level log file.
+pub fn init(cli: &Cli) {
+    let level = match (cli.quiet, cli.verbose) {
+        (true, _) => LevelFilter::WARN,
+        (false, 0) => LevelFilter::INFO,
+        (false, 1) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    };
+    let stderr = tfmt::layer()
+        .with_writer(|| progress::Stderr)
+        .event_format(Terse)
+        .with_filter(level);
+
+    let file = log_file(cli.repo.as_deref().unwrap_or(Path::new("."))).map(|file| {
+        tfmt::layer()
+            .with_writer(Mutex::new(file))
+            .with_ansi(false)
+            .with_target(false)
+            .with_filter(LevelFilter::DEBUG)
+    });
+
+    tracing_subscriber::registry()
+        .with(stderr)
+        .with(file)
+        .init();
+}
+
+/// Opens `.git/graft/graft.log` for appending when `log_file` is set,
+/// rotating it first if it got too big.
+fn log_file(repo: &Path) -> Option<File> {
+    let git = Git::new(repo).ok()?;
+    if !Config::load(&git.root()).ok()?.log_file {
+        return None;
+    }
+    let dir = git.state_dir();
+    fs::create_dir_all(&dir).ok()?;
+    let path = dir.join(LOG_FILE);
+    if fs::metadata(&path).is_ok_and(|meta| meta.len() > LOG_FILE_MAX) {
+        let _ = fs::rename(&path, dir.join(format!("{LOG_FILE}.1")));
+    }
+    OpenOptions::new().create(true).append(true).open(path).ok()
+}
+
+/// Info messages as-is, everything else prefixed with its level, the way
+/// hand-written `warning: …` lines looked.
+struct Terse;
+
+impl<S, N> FormatEvent<S, N> for Terse
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let prefix = match *event.metadata().level() {
+            Level::INFO => "",
+            Level::WARN => "warning: ",
+            Level::ERROR => "error: ",
+            Level::DEBUG => "debug: ",
+            Level::TRACE => "trace: ",
+        };
+        write!(writer, "{prefix}")?;
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
@@ -0,0 +1,336 @@
+//! Every external program graft runs (git, zellij, hooks, shells) goes
+//! through a [`ProcessRunner`], so tests can swap the system for a
+//! [`FakeRunner`] that scripts failures and records what was run.
+
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A program to run: what `std::process::Command` holds, minus the process.
+#[derive(Clone, Debug)]
+pub struct Cmd {
+    program: OsString,
+    args: Vec<OsString>,
+    dir: Option<PathBuf>,
+    env: Vec<(OsString, OsString)>,
+    timeout: Option<Duration>,
+}
+
+impl Cmd {
+    pub fn new(program: impl AsRef<OsStr>) -> Self {
+        Cmd {
+            program: program.as_ref().to_os_string(),
+            args: Vec::new(),
+            dir: None,
+            env: Vec::new(),
+            timeout: None,
+        }
+    }
+
+    pub fn arg(&mut self, arg: impl AsRef<OsStr>) -> &mut Self {
+        self.args.push(arg.as_ref().to_os_string());
+        self
+    }
+
+    pub fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_os_string()));
+        self
+    }
+
+    pub fn current_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn env(&mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> &mut Self {
+        self.env
+            .push((key.as_ref().to_os_string(), value.as_ref().to_os_string()));
+        self
+    }
+
+    /// Kills the process if it runs longer than `timeout`; see
+    /// [`ProcessRunner::output`].
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn program(&self) -> &OsStr {
+        &self.program
+    }
+
+    pub fn get_args(&self) -> impl Iterator<Item = &OsStr> {
+        self.args.iter().map(OsString::as_os_str)
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    pub fn get_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn get_env(&self, key: &str) -> Option<&OsStr> {
+        self.env
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_os_str())
+    }
+
+    fn to_command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args).envs(self.env.iter().cloned());
+        if let Some(dir) = &self.dir {
+            command.current_dir(dir);
+        }
+        command
+    }
+}
+
+/// The command line, for logs and assertions: `git -C /repo fetch origin`.
+impl fmt::Display for Cmd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.program.to_string_lossy())?;
+        for arg in &self.args {
+            write!(f, " {}", arg.to_string_lossy())?;
+        }
+        Ok(())
+    }
+}
+
+/// How a process ended: its exit code, or `None` when a signal killed it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Status(pub Option<i32>);
+
+impl Status {
+    pub fn success(self) -> bool {
+        self.0 == Some(0)
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(code) => write!(f, "exit code {code}"),
+            None => f.write_str("killed by a signal"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Output {
+    pub status: Status,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+pub trait ProcessRunner: Send + Sync {
+    /// Runs `cmd` to completion with its output captured. Fails only when
+    /// it can't be started, or with [`io::ErrorKind::TimedOut`] when it
+    /// outlived its timeout; a non-zero exit is in the `Output`.
+    fn output(&self, cmd: &Cmd) -> io::Result<Output>;
+
+    /// Runs `cmd` attached to the terminal (sessions, shells, hooks) and
+    /// waits for it.
+    fn status(&self, cmd: &Cmd) -> io::Result<Status>;
+}
+
+/// Runs commands for real.
+pub struct SystemRunner;
+
+impl ProcessRunner for SystemRunner {
+    fn output(&self, cmd: &Cmd) -> io::Result<Output> {
+        let Some(timeout) = cmd.timeout else {
+            let output = cmd.to_command().output()?;
+            return Ok(Output {
+                status: Status(output.status.code()),
+                stdout: output.stdout,
+                stderr: output.stderr,
+            });
+        };
+        let mut child = cmd
+            .to_command()
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // Drained on threads so a chatty process can't block on a full pipe.
+        let stdout = drain(child.stdout.take());
+        let stderr = drain(child.stderr.take());
+        let deadline = Instant::now() + timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("{cmd} did not finish within {}s", timeout.as_secs()),
+                ));
+            }
+            thread::sleep(Duration::from_millis(20));
+        };
+        Ok(Output {
+            status: Status(status.code()),
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        })
+    }
+
+    fn status(&self, cmd: &Cmd) -> io::Result<Status> {
+        Ok(Status(cmd.to_command().status()?.code()))
+    }
+}
+
+fn drain(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/// What a [`FakeRunner`] answers a matching command with.
+#[derive(Clone, Debug)]
+pub enum Response {
+    /// The program isn't installed.
+    Missing,
+    /// The command outlived its timeout and was killed.
+    TimedOut,
+    Exit {
+        code: i32,
+        stdout: String,
+        stderr: String,
+    },
+}
+
+impl Response {
+    pub fn ok(stdout: &str) -> Self {
+        Response::Exit {
+            code: 0,
+            stdout: stdout.to_string(),
+            stderr: String::new(),
+        }
+    }
+
+    pub fn fail(code: i32, stderr: &str) -> Self {
+        Response::Exit {
+            code,
+            stdout: String::new(),
+            stderr: stderr.to_string(),
+        }
+    }
+}
+
+struct Rule {
+    program: String,
+    args: Vec<String>,
+    response: Response,
+}
+
+impl Rule {
+    /// Same program (or file name, for paths), and `args` appear as a
+    /// contiguous run in the command's arguments.
+    fn matches(&self, cmd: &Cmd) -> bool {
+        let program = Path::new(cmd.program());
+        let same_program = program == Path::new(&self.program)
+            || program.file_name() == Some(OsStr::new(&self.program));
+        let args: Vec<&OsStr> = cmd.get_args().collect();
+        same_program
+            && (self.args.is_empty()
+                || args
+                    .windows(self.args.len())
+                    .any(|window| window.iter().zip(&self.args).all(|(a, b)| *a == b.as_str())))
+    }
+}
+
+/// Answers from scripted rules instead of running anything, and records
+/// every command. Commands no rule matches behave like a missing program.
+#[derive(Default)]
+pub struct FakeRunner {
+    rules: Mutex<Vec<Rule>>,
+    calls: Mutex<Vec<Cmd>>,
+}
+
+impl FakeRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers `program` with `response` when `args` appear in its
+    /// arguments, e.g. `on("zellij", &["list-sessions"], …)`. Later rules
+    /// take precedence.
+    pub fn on(&self, program: &str, args: &[&str], response: Response) -> &Self {
+        self.rules.lock().expect("fake runner poisoned").push(Rule {
+            program: program.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            response,
+        });
+        self
+    }
+
+    /// Everything run so far, oldest first.
+    pub fn calls(&self) -> Vec<Cmd> {
+        self.calls.lock().expect("fake runner poisoned").clone()
+    }
+
+    fn respond(&self, cmd: &Cmd) -> io::Result<Output> {
+        self.calls
+            .lock()
+            .expect("fake runner poisoned")
+            .push(cmd.clone());
+        let rules = self.rules.lock().expect("fake runner poisoned");
+        match rules.iter().rev().find(|rule| rule.matches(cmd)) {
+            Some(Rule {
+                response:
+                    Response::Exit {
+                        code,
+                        stdout,
+                        stderr,
+                    },
+                ..
+            }) => Ok(Output {
+                status: Status(Some(*code)),
+                stdout: stdout.clone().into_bytes(),
+                stderr: stderr.clone().into_bytes(),
+            }),
+            Some(Rule {
+                response: Response::TimedOut,
+                ..
+            }) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("{cmd} did not finish in time"),
+            )),
+            Some(_) | None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} not found", cmd.program().to_string_lossy()),
+            )),
+        }
+    }
+}
+
+impl ProcessRunner for FakeRunner {
+    fn output(&self, cmd: &Cmd) -> io::Result<Output> {
+        self.respond(cmd)
+    }
+
+    fn status(&self, cmd: &Cmd) -> io::Result<Status> {
+        self.respond(cmd).map(|output| output.status)
+    }
+}
@@ -0,0 +1,49 @@
+//! Feedback for operations that can run for a minute without a word: a
+//! fetch, or checking out a worktree of a large repository. git's own
+//! progress output is captured (and libgit2 reports none for worktree
+//! creation), so a spinner with the elapsed time runs on stderr instead.
+//! It only draws on a terminal; log lines printed meanwhile go above it.
+
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// The spinner currently shown, so log output can step around it.
+static ACTIVE: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+/// Runs `f` behind a spinner saying `message`.
+pub fn spin<T>(message: &str, f: impl FnOnce() -> T) -> T {
+    let bar = ProgressBar::new_spinner().with_message(message.to_string());
+    if let Ok(style) = ProgressStyle::with_template("{spinner} {msg} ({elapsed})") {
+        bar.set_style(style);
+    }
+    bar.enable_steady_tick(Duration::from_millis(100));
+    let outer = ACTIVE.lock().map(|mut active| active.replace(bar.clone()));
+    let result = f();
+    bar.finish_and_clear();
+    if let Ok(outer) = outer
+        && let Ok(mut active) = ACTIVE.lock()
+    {
+        *active = outer;
+    }
+    result
+}
+
+/// Stderr for the log: hides the spinner while a line is written.
+pub struct Stderr;
+
+impl Write for Stderr {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let active = ACTIVE.lock().ok().and_then(|active| active.clone());
+        match active {
+            Some(bar) => bar.suspend(|| io::stderr().write(buf)),
+            None => io::stderr().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
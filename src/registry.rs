@@ -0,0 +1,62 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::user_config_dir;
+
+const REGISTRY_FILE: &str = "repos.toml";
+
+/// The repositories graft manages, kept next to the user config so that
+/// `graft ls --all` can work from anywhere.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Registry {
+    /// Repository roots, in the order they were added.
+    #[serde(default)]
+    pub repos: Vec<PathBuf>,
+
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Registry {
+    pub fn load() -> Result<Registry> {
+        let dir = user_config_dir().context("cannot locate the config directory; set $HOME")?;
+        let path = dir.join(REGISTRY_FILE);
+        let mut registry: Registry = match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .with_context(|| format!("failed to parse {}", path.display()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Registry::default(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read {}", path.display()));
+            }
+        };
+        registry.path = path;
+        Ok(registry)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, toml::to_string(self)?)
+            .with_context(|| format!("failed to write {}", self.path.display()))
+    }
+
+    /// Returns `false` if the repository was already registered.
+    pub fn add(&mut self, root: PathBuf) -> bool {
+        if self.repos.contains(&root) {
+            return false;
+        }
+        self.repos.push(root);
+        true
+    }
+
+    /// Returns `false` if the repository wasn't registered.
+    pub fn remove(&mut self, root: &Path) -> bool {
+        let before = self.repos.len();
+        self.repos.retain(|repo| repo != root);
+        self.repos.len() != before
+    }
+}
@@ -0,0 +1,103 @@
+//! `[secrets]`: files such as `.env` that a configured command decrypts
+//! into each new worktree (`op inject`, `sops -d`), and that are
+//! overwritten before the worktree is removed.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use tracing::{debug, info, warn};
+
+use crate::config::SecretsConfig;
+use crate::process::{Cmd, ProcessRunner};
+
+/// Runs the `[secrets]` command in `worktree`, with the same variables as
+/// hooks, then locks the files it wrote down to their owner. Nothing to do
+/// without a command.
+pub fn provision(
+    runner: &dyn ProcessRunner,
+    secrets: &SecretsConfig,
+    root: &Path,
+    branch: &str,
+    worktree: &Path,
+    env: &[(String, String)],
+) -> Result<()> {
+    let Some(script) = &secrets.command else {
+        return Ok(());
+    };
+    info!("Fetching secrets");
+    let mut command = Cmd::new("sh");
+    command
+        .arg("-c")
+        .arg(script)
+        .current_dir(worktree)
+        .env("GRAFT_BRANCH", branch)
+        .env("GRAFT_WORKTREE", worktree)
+        .env("GRAFT_ROOT", root);
+    for (key, value) in env {
+        command.env(key, value);
+    }
+    debug!("running {}", command);
+    // Inherits the terminal: password managers may ask to unlock.
+    let status = runner
+        .status(&command)
+        .context("failed to run the secrets command")?;
+    if !status.success() {
+        bail!("the secrets command failed ({status})");
+    }
+    for file in &secrets.files {
+        let path = worktree.join(file);
+        if !path.is_file() {
+            warn!("The secrets command did not write {}", file.display());
+            continue;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    Ok(())
+}
+
+/// Overwrites the secret `files` in `worktree` with zeros and deletes
+/// them; returns how many there were. Symlinks are only removed. On
+/// copy-on-write filesystems and SSDs the old blocks may survive, so this
+/// narrows what is left on disk rather than guaranteeing nothing is.
+pub fn shred(files: &[PathBuf], worktree: &Path) -> Result<usize> {
+    let mut shredded = 0;
+    for file in files {
+        let path = worktree.join(file);
+        let Ok(meta) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+        if meta.is_file() {
+            overwrite(&path, meta.len())
+                .with_context(|| format!("could not overwrite {}", path.display()))?;
+        }
+        if meta.is_dir() {
+            continue;
+        }
+        fs::remove_file(&path).with_context(|| format!("could not delete {}", path.display()))?;
+        shredded += 1;
+    }
+    Ok(shredded)
+}
+
+fn overwrite(path: &Path, len: u64) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let zeros = [0u8; 8192];
+    let mut left = len;
+    while left > 0 {
+        let n = left.min(zeros.len() as u64) as usize;
+        file.write_all(&zeros[..n])?;
+        left -= n as u64;
+    }
+    file.sync_all()
+}
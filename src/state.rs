@@ -0,0 +1,352 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::zellij;
+
+const STATE_FILE: &str = "state.json";
+/// Locked by the graft run changing worktrees, which writes its pid in it;
+/// see [`lock`].
+const LOCK_FILE: &str = "lock";
+/// How long a run waits for another to finish before giving up.
+const LOCK_WAIT: Duration = Duration::from_secs(60);
+
+/// What a worktree is for. Set by the command that creates it.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Purpose {
+    #[default]
+    Feature,
+    Review,
+    Hotfix,
+    Scratch,
+    Bisect,
+}
+
+impl Purpose {
+    /// Guesses the purpose from a conventional branch prefix (`hotfix/…`).
+    pub fn infer(branch: &str) -> Purpose {
+        let prefix = branch.split('/').next().unwrap_or_default();
+        match prefix {
+            "review" => Purpose::Review,
+            "hotfix" => Purpose::Hotfix,
+            "scratch" | "tmp" => Purpose::Scratch,
+            "bisect" => Purpose::Bisect,
+            _ => Purpose::Feature,
+        }
+    }
+}
+
+impl fmt::Display for Purpose {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Purpose::Feature => "feature",
+            Purpose::Review => "review",
+            Purpose::Hotfix => "hotfix",
+            Purpose::Scratch => "scratch",
+            Purpose::Bisect => "bisect",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorktreeRecord {
+    pub path: PathBuf,
+    pub purpose: Purpose,
+    /// Unix timestamp (seconds).
+    pub created_at: u64,
+    /// Last time graft opened the worktree (Unix seconds).
+    #[serde(default)]
+    pub last_used: Option<u64>,
+    /// Its Zellij session: the name graft gave it when the worktree was
+    /// first recorded, or a session adopted with `graft attach --adopt`.
+    /// Records from before graft kept names have none and use
+    /// [`zellij::session_name`].
+    #[serde(default)]
+    pub session: Option<String>,
+    /// When `graft gc` may remove the worktree (Unix seconds), from `--ttl`.
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// What its session was first created with, reused when it is created
+    /// again.
+    #[serde(default)]
+    pub layout: Option<SessionLayout>,
+    /// Left with `graft note`, oldest first.
+    #[serde(default)]
+    pub notes: Vec<String>,
+    /// Link to the issue it was opened for with `graft new --issue`.
+    #[serde(default)]
+    pub issue: Option<String>,
+}
+
+/// The layout a worktree's session was created with.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionLayout {
+    /// None: zellij's default.
+    Plain,
+    /// The copy in `.git/graft/layouts/`, named after the session.
+    Saved,
+}
+
+/// A worktree removed in trash mode, restorable with `graft undo`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashEntry {
+    /// Branch, or the label of a detached worktree.
+    pub name: String,
+    /// Where the worktree lived.
+    pub path: PathBuf,
+    /// `.worktrees/.trash/<timestamp>-<name>`.
+    pub trash_path: PathBuf,
+    pub head: String,
+    pub detached: bool,
+    pub trashed_at: u64,
+    pub record: Option<WorktreeRecord>,
+}
+
+/// Graft's per-repository metadata, stored in `.git/graft/state.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    /// Keyed by branch name.
+    #[serde(default)]
+    pub worktrees: BTreeMap<String, WorktreeRecord>,
+
+    /// Oldest first.
+    #[serde(default)]
+    pub trash: Vec<TrashEntry>,
+
+    /// Names of recorded worktrees, most recently used first, for
+    /// `graft last` and `graft recent`.
+    #[serde(default)]
+    pub recent: Vec<String>,
+
+    /// The main checkout's tracked files are read-only (`graft protect`).
+    #[serde(default)]
+    pub main_protected: bool,
+
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl State {
+    pub fn load(state_dir: &Path) -> Result<State> {
+        let path = state_dir.join(STATE_FILE);
+        let mut state: State = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("corrupt state file {}", path.display()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => State::default(),
+            Err(err) => return Err(err).context("failed to read graft state"),
+        };
+        state.path = path;
+        Ok(state)
+    }
+
+    /// The Zellij session for worktree `name`: the recorded one, else
+    /// `wt-…`.
+    pub fn session(&self, name: &str) -> String {
+        self.worktrees
+            .get(name)
+            .and_then(|record| record.session.clone())
+            .unwrap_or_else(|| zellij::session_name(name))
+    }
+
+    /// The session name to record for new worktree `name`:
+    /// [`zellij::session_name`], or when another worktree's session already
+    /// has that, the same with a hash of the branch appended.
+    pub fn new_session(&self, name: &str) -> String {
+        let session = zellij::session_name(name);
+        let taken = self
+            .worktrees
+            .keys()
+            .any(|other| other != name && self.session(other) == session);
+        if taken {
+            zellij::unique_session_name(name)
+        } else {
+            session
+        }
+    }
+
+    /// Moves the record of `old` to `new`. A session graft named is named
+    /// anew for `new`; returns the old and new names then, for the caller
+    /// to rename the running session. An adopted one keeps its name.
+    pub fn move_record(&mut self, old: &str, new: &str) -> Option<(String, String)> {
+        let old_session = self.session(old);
+        let adopted = !zellij::is_graft_session(&old_session);
+        if let Some(mut record) = self.worktrees.remove(old) {
+            if !adopted {
+                record.session = Some(self.new_session(new));
+            }
+            self.worktrees.insert(new.to_string(), record);
+        }
+        for name in self.recent.iter_mut().filter(|name| *name == old) {
+            *name = new.to_string();
+        }
+        if adopted {
+            return None;
+        }
+        Some((old_session, self.session(new)))
+    }
+
+    /// Marks worktree `name` as used just now, moving it to the front of
+    /// [`State::recent`]. Names no longer recorded drop out.
+    pub fn touch(&mut self, name: &str) {
+        if let Some(record) = self.worktrees.get_mut(name) {
+            record.last_used = Some(now());
+        }
+        let worktrees = &self.worktrees;
+        self.recent
+            .retain(|other| other != name && worktrees.contains_key(other));
+        self.recent.insert(0, name.to_string());
+    }
+
+    /// The worktree a session belongs to; exact even for shortened names.
+    pub fn name_for_session(&self, session: &str) -> Option<&str> {
+        self.worktrees
+            .keys()
+            .find(|name| self.session(name) == session)
+            .map(String::as_str)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Write-then-rename so a crash never leaves a truncated file behind.
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// The lock files this process holds, by path; only the owner's outermost
+/// [`StateLock`] releases one.
+static HELD: Mutex<BTreeMap<PathBuf, Held>> = Mutex::new(BTreeMap::new());
+/// Signalled whenever an entry leaves [`HELD`].
+static RELEASED: Condvar = Condvar::new();
+
+struct Held {
+    owner: ThreadId,
+    count: usize,
+    /// The file the OS lock is held on.
+    _file: File,
+}
+
+/// Keeps other graft runs from changing worktrees, sessions or the state
+/// file until dropped.
+pub struct StateLock {
+    path: PathBuf,
+}
+
+impl Drop for StateLock {
+    fn drop(&mut self) {
+        let mut held = HELD.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(entry) = held.get_mut(&self.path) {
+            entry.count -= 1;
+            if entry.count == 0 {
+                // Closing the file releases the lock; the file stays.
+                held.remove(&self.path);
+                RELEASED.notify_all();
+            }
+        }
+    }
+}
+
+/// Takes an OS lock (`flock`, `LockFileEx`) on `state_dir/lock`, waiting
+/// for another graft run that holds it. The OS lets go of it when a run
+/// dies, so there is never a stale lock to take over. Nested calls on one
+/// thread share its first lock; other threads of the process wait for it
+/// like another run would.
+pub fn lock(state_dir: &Path) -> Result<StateLock> {
+    let path = state_dir.join(LOCK_FILE);
+    let start = Instant::now();
+    let mut held = HELD.lock().unwrap_or_else(|err| err.into_inner());
+    let mut announced = false;
+    while let Some(entry) = held.get_mut(&path) {
+        if entry.owner == thread::current().id() {
+            entry.count += 1;
+            return Ok(StateLock { path });
+        }
+        if !announced {
+            info!("Waiting for another request to this graft run to finish...");
+            announced = true;
+        }
+        let Some(left) = LOCK_WAIT.checked_sub(start.elapsed()) else {
+            bail!(
+                "another graft run is still changing worktrees after {}s",
+                LOCK_WAIT.as_secs()
+            );
+        };
+        held = RELEASED
+            .wait_timeout(held, left)
+            .unwrap_or_else(|err| err.into_inner())
+            .0;
+    }
+    drop(held);
+    fs::create_dir_all(state_dir)?;
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    loop {
+        match file.try_lock() {
+            Ok(()) => break,
+            Err(TryLockError::WouldBlock) => {}
+            Err(TryLockError::Error(err)) => {
+                return Err(err).with_context(|| format!("failed to lock {}", path.display()));
+            }
+        }
+        if !announced {
+            // Unreadable where the lock is mandatory (Windows).
+            match fs::read_to_string(&path)
+                .ok()
+                .and_then(|contents| contents.trim().parse::<u32>().ok())
+            {
+                Some(pid) => info!("Waiting for another graft run (pid {pid}) to finish..."),
+                None => info!("Waiting for another graft run to finish..."),
+            }
+            announced = true;
+        }
+        if start.elapsed() > LOCK_WAIT {
+            bail!(
+                "another graft run is still changing worktrees after {}s",
+                LOCK_WAIT.as_secs()
+            );
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    file.set_len(0)?;
+    writeln!(file, "{}", std::process::id())?;
+    HELD.lock().unwrap_or_else(|err| err.into_inner()).insert(
+        path.clone(),
+        Held {
+            owner: thread::current().id(),
+            count: 1,
+            _file: file,
+        },
+    );
+    Ok(StateLock { path })
+}
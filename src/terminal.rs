@@ -0,0 +1,84 @@
+//! Tabs in terminals that multiplex on their own, WezTerm and kitty, for
+//! `attach = "wezterm"` / `"kitty"`: the worktree opens next to the current
+//! one instead of in a Zellij session. Both hand the tab off and return.
+
+use std::env;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use tracing::debug;
+
+use crate::process::{Cmd, ProcessRunner};
+
+/// Opens a WezTerm tab in `dir` with `wezterm cli spawn`, titled `name`,
+/// running `command` (else the shell). The pane is started by the WezTerm
+/// server rather than by us, so `env` goes in through `env(1)`.
+pub fn wezterm_tab(
+    runner: &dyn ProcessRunner,
+    name: &str,
+    dir: &Path,
+    command: &[String],
+    env: &[(String, String)],
+) -> Result<()> {
+    let mut spawn = Cmd::new("wezterm");
+    spawn.args(["cli", "spawn", "--cwd"]).arg(dir);
+    if !(command.is_empty() && env.is_empty()) {
+        spawn.arg("--");
+        if !env.is_empty() {
+            spawn.arg("env");
+            spawn.args(env.iter().map(|(key, value)| format!("{key}={value}")));
+        }
+        if command.is_empty() {
+            spawn.arg(env::var_os("SHELL").unwrap_or_else(|| "sh".into()));
+        } else {
+            spawn.args(command);
+        }
+    }
+    let pane = run(runner, &spawn, "open a WezTerm tab")?;
+
+    let mut title = Cmd::new("wezterm");
+    title
+        .args(["cli", "set-tab-title", "--pane-id", pane.trim()])
+        .arg(name);
+    if let Err(err) = run(runner, &title, "title the WezTerm tab") {
+        debug!("no title for the tab of {name}: {err:#}");
+    }
+    Ok(())
+}
+
+/// Opens a kitty tab in `dir` through its remote control, which has to be
+/// on (`allow_remote_control`), titled `name` and running `command`.
+pub fn kitty_tab(
+    runner: &dyn ProcessRunner,
+    name: &str,
+    dir: &Path,
+    command: &[String],
+    env: &[(String, String)],
+) -> Result<()> {
+    let mut launch = Cmd::new("kitty");
+    launch
+        .args(["@", "launch", "--type=tab", "--cwd"])
+        .arg(dir)
+        .args(["--tab-title", name]);
+    for (key, value) in env {
+        launch.arg("--env").arg(format!("{key}={value}"));
+    }
+    launch.args(command);
+    run(runner, &launch, "open a kitty tab")?;
+    Ok(())
+}
+
+/// Runs `cmd`, returning its stdout; errors say it failed to `action`.
+fn run(runner: &dyn ProcessRunner, cmd: &Cmd, action: &str) -> Result<String> {
+    debug!("running {}", cmd);
+    let output = runner
+        .output(cmd)
+        .with_context(|| format!("failed to run {:?} to {action}", cmd.program()))?;
+    if !output.status.success() {
+        bail!(
+            "could not {action}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,51 @@
+//! Time spent attached to worktrees, for `graft report`: one line per
+//! session or shell graft attached to, appended to
+//! `.git/graft/timesheet.jsonl` once it is left.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+const TIMESHEET_FILE: &str = "timesheet.jsonl";
+
+/// One stretch attached to a worktree.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Span {
+    /// Branch, or the label of a detached worktree.
+    pub name: String,
+    /// When it was attached and left (Unix seconds).
+    pub start: u64,
+    pub end: u64,
+}
+
+pub fn record(state_dir: &Path, name: &str, start: u64, end: u64) -> Result<()> {
+    fs::create_dir_all(state_dir)?;
+    let span = Span {
+        name: name.to_string(),
+        start,
+        end,
+    };
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(state_dir.join(TIMESHEET_FILE))?;
+    writeln!(file, "{}", serde_json::to_string(&span)?)?;
+    Ok(())
+}
+
+/// All spans, oldest first. Lines that don't parse (a write cut short) are
+/// skipped.
+pub fn load(state_dir: &Path) -> Result<Vec<Span>> {
+    let path = state_dir.join(TIMESHEET_FILE);
+    match fs::read_to_string(&path) {
+        Ok(contents) => Ok(contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
@@ -0,0 +1,63 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::state;
+
+const TIMINGS_FILE: &str = "timings.jsonl";
+/// Past this size the file is cut down to the newest [`KEEP_SAMPLES`].
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+const KEEP_SAMPLES: usize = 5000;
+
+/// How long one operation took, appended to `.git/graft/timings.jsonl`
+/// when the `timings` config key is on.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Sample {
+    /// `open`, `fetch` or `worktree-add`.
+    pub op: String,
+    /// When it finished (Unix seconds).
+    pub at: u64,
+    pub ms: u64,
+}
+
+pub fn record(state_dir: &Path, op: &str, elapsed: Duration) -> Result<()> {
+    fs::create_dir_all(state_dir)?;
+    let path = state_dir.join(TIMINGS_FILE);
+    let sample = Sample {
+        op: op.to_string(),
+        at: state::now(),
+        ms: elapsed.as_millis() as u64,
+    };
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{}", serde_json::to_string(&sample)?)?;
+
+    if file.metadata()?.len() > MAX_FILE_SIZE {
+        let samples = load(state_dir)?;
+        let keep = &samples[samples.len().saturating_sub(KEEP_SAMPLES)..];
+        let mut contents = String::new();
+        for sample in keep {
+            contents.push_str(&serde_json::to_string(sample)?);
+            contents.push('\n');
+        }
+        fs::write(&path, contents)?;
+    }
+    Ok(())
+}
+
+/// All samples, oldest first. Lines that don't parse (a write cut short)
+/// are skipped.
+pub fn load(state_dir: &Path) -> Result<Vec<Sample>> {
+    let path = state_dir.join(TIMINGS_FILE);
+    match fs::read_to_string(&path) {
+        Ok(contents) => Ok(contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::{Result, bail};
+
+const UNITS: [&str; 5] = ["B", "K", "M", "G", "T"];
+
+/// Parses sizes like `20G`, `512M`, `1.5T` or plain bytes (powers of 1024).
+pub fn parse_size(input: &str) -> Result<u64> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+    let Ok(number) = number.parse::<f64>() else {
+        bail!("invalid size '{input}'");
+    };
+    let unit = unit
+        .trim()
+        .trim_end_matches(['b', 'B'])
+        .to_ascii_uppercase();
+    let power = match unit.as_str() {
+        "" => 0,
+        "K" | "KI" => 1,
+        "M" | "MI" => 2,
+        "G" | "GI" => 3,
+        "T" | "TI" => 4,
+        _ => bail!("invalid size unit in '{input}' (expected K, M, G or T)"),
+    };
+    Ok((number * 1024f64.powi(power)) as u64)
+}
+
+pub fn format_size(bytes: u64) -> String {
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes}B")
+    } else {
+        format!("{value:.1}{}", UNITS[unit])
+    }
+}
+
+/// Parses durations like `30m`, `12h`, `7d` or `2w` into seconds.
+pub fn parse_duration(input: &str) -> Result<u64> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+    let Ok(number) = number.parse::<u64>() else {
+        bail!("invalid duration '{input}'");
+    };
+    let scale = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => bail!("invalid duration unit in '{input}' (expected s, m, h, d or w)"),
+    };
+    let Some(seconds) = number.checked_mul(scale) else {
+        bail!("invalid duration '{input}': too long");
+    };
+    Ok(seconds)
+}
+
+/// `seconds` after the Unix timestamp `start`, or an error where that is
+/// past what a timestamp can hold.
+pub fn after(start: u64, seconds: u64) -> Result<u64> {
+    match start.checked_add(seconds) {
+        Some(at) => Ok(at),
+        None => bail!("invalid duration: {seconds}s from now is too far off"),
+    }
+}
+
+/// Compact age like `45s`, `12m`, `5h` or `3d`.
+pub fn format_age(seconds: u64) -> String {
+    match seconds {
+        0..60 => format!("{seconds}s"),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+/// Time spent, like `45s`, `12m` or `3h05m`.
+pub fn format_hours(seconds: u64) -> String {
+    match seconds {
+        0..60 => format!("{seconds}s"),
+        60..3600 => format!("{}m", seconds / 60),
+        _ => format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+/// Durations like `850ms`, `4.2s` or `2m05s`.
+pub fn format_millis(ms: u64) -> String {
+    match ms {
+        0..1000 => format!("{ms}ms"),
+        1000..60_000 => format!("{:.1}s", ms as f64 / 1000.0),
+        _ => format!("{}m{:02}s", ms / 60_000, ms % 60_000 / 1000),
+    }
+}
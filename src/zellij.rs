@@ -1,29 +1,513 @@
-pub fn sessions() -> Vec<String> {
-    let output = std::process::Command::new("zellij")
-        .arg("list-sessions")
-        .arg("--short")
-        .output()
-        .expect("Failed to execute zellij command");
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    stdout.lines().map(|line| line.trim().to_string()).collect()
-}
-
-pub fn start_session(session_name: &str, dir: &str) {
-    std::process::Command::new("zellij")
-        .arg("attach-session")
-        .arg("--session")
-        .current_dir(dir)
-        .arg(session_name)
-        .status()
-        .expect("Failed to start zellij session");
-}
-
-pub fn kill_session(session_name: &str) {
-    std::process::Command::new("zellij")
-        .arg("kill-session")
-        .arg("--session")
-        .arg(session_name)
-        .status()
-        .expect("Failed to kill zellij session");
+use std::borrow::Cow;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use tracing::debug;
+
+use crate::config::ZellijConfig;
+use crate::process::{Cmd, ProcessRunner};
+
+pub const DEFAULT_SESSION_PREFIX: &str = "wt-";
+/// Set from `[zellij] session_prefix`. Process-wide, because session names
+/// are worked out in many places that only know a branch.
+static SESSION_PREFIX: RwLock<Cow<'static, str>> =
+    RwLock::new(Cow::Borrowed(DEFAULT_SESSION_PREFIX));
+/// Longest session name graft generates. Zellij puts the name in its socket
+/// path, which most systems cap at about 108 bytes.
+const MAX_SESSION_NAME: usize = 48;
+
+pub fn set_session_prefix(prefix: &str) {
+    *SESSION_PREFIX
+        .write()
+        .expect("session prefix lock poisoned") = Cow::Owned(prefix.to_string());
+}
+
+/// What every session graft starts is named with (`wt-` by default).
+pub fn session_prefix() -> String {
+    SESSION_PREFIX
+        .read()
+        .expect("session prefix lock poisoned")
+        .to_string()
+}
+
+/// Whether `session` carries graft's prefix, i.e. graft named it rather
+/// than someone whose session was adopted.
+pub fn is_graft_session(session: &str) -> bool {
+    session.starts_with(&session_prefix())
+}
+
+/// `wt-<branch>` with everything but ASCII letters, digits, `-` and `_`
+/// as `-`. Longer names are cut short and end in a hash of the full
+/// branch, so they stay distinct and stable across runs. Different
+/// branches can come out the same (`a/b`, `a-b`): new worktrees get their
+/// name from [`crate::state::State::new_session`], which tells them apart.
+pub fn session_name(branch: &str) -> String {
+    let name = format!("{}{}", session_prefix(), sanitize(branch));
+    if name.len() <= MAX_SESSION_NAME {
+        return name;
+    }
+    hashed(&name, branch)
+}
+
+/// [`session_name`] ending in a hash of the full branch whatever its
+/// length, for a branch whose plain name another one already has.
+pub fn unique_session_name(branch: &str) -> String {
+    hashed(&format!("{}{}", session_prefix(), sanitize(branch)), branch)
+}
+
+/// `name`, cut to fit, then `-` and the hash of `branch`.
+fn hashed(name: &str, branch: &str) -> String {
+    let hash = format!("{:08x}", fnv1a(branch.as_bytes()));
+    let mut end = (MAX_SESSION_NAME - hash.len() - 1).min(name.len());
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}-{}", name[..end].trim_end_matches('-'), hash)
+}
+
+/// Zellij puts the name in a socket path and rejects some characters
+/// outright, so only a safe few pass.
+fn sanitize(branch: &str) -> String {
+    branch
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+/// 32-bit FNV-1a: tiny, and unlike std's hasher fixed across Rust releases.
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// A layout like zellij's default (tab bar, one pane, status bar) whose
+/// pane runs `command` instead of a plain shell.
+pub fn command_layout(command: &[String]) -> String {
+    let (program, args) = command.split_first().expect("empty startup command");
+    let args: Vec<String> = args.iter().map(|arg| kdl_string(arg)).collect();
+    let args = if args.is_empty() {
+        String::new()
+    } else {
+        format!(" {{\n        args {}\n    }}", args.join(" "))
+    };
+    format!(
+        r#"layout {{
+    pane size=1 borderless=true {{
+        plugin location="zellij:tab-bar"
+    }}
+    pane command={program}{args}
+    pane size=2 borderless=true {{
+        plugin location="zellij:status-bar"
+    }}
+}}
+"#,
+        program = kdl_string(program),
+    )
+}
+
+fn kdl_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The working directory a session was started in, read from the layout
+/// zellij serializes for session resurrection
+/// (`<cache>/zellij/<version>/session_info/<name>/session-layout.kdl`).
+pub fn session_cwd(session_name: &str) -> Option<PathBuf> {
+    for version in fs::read_dir(cache_dir()?).ok()?.flatten() {
+        let layout = version
+            .path()
+            .join("session_info")
+            .join(session_name)
+            .join("session-layout.kdl");
+        let Ok(contents) = fs::read_to_string(layout) else {
+            continue;
+        };
+        // The layout-level `cwd "…"` comes first; pane cwds are relative to it.
+        let cwd = contents
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("cwd \""))?
+            .strip_suffix('"')?;
+        return Some(PathBuf::from(
+            cwd.replace("\\\"", "\"").replace("\\\\", "\\"),
+        ));
+    }
+    None
+}
+
+/// The session graft runs inside, from the variable zellij sets in its panes.
+pub fn current_session() -> Option<String> {
+    env::var("ZELLIJ_SESSION_NAME")
+        .ok()
+        .filter(|name| !name.is_empty())
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    if cfg!(target_os = "macos") {
+        return Some(home?.join("Library/Caches/org.Zellij-Contributors.Zellij"));
+    }
+    let base = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| Some(home?.join(".cache")))?;
+    Some(base.join("zellij"))
+}
+
+/// One line of `zellij list-sessions --no-formatting`, e.g.
+/// `wt-foo [Created 2h 3m 10s ago] (EXITED - attach to resurrect)`.
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub name: String,
+    /// Seconds since the session was created, when zellij says.
+    pub age: Option<u64>,
+    /// Dead but resurrectable; attaching brings it back.
+    pub exited: bool,
+}
+
+impl SessionInfo {
+    fn parse(line: &str) -> Option<SessionInfo> {
+        let line = line.trim();
+        let (name, rest) = line.split_once(" [").unwrap_or((line, ""));
+        if name.is_empty() {
+            return None;
+        }
+        let age = rest
+            .strip_prefix("Created ")
+            .and_then(|rest| rest.split_once(" ago]"))
+            .and_then(|(age, _)| parse_age(age));
+        Some(SessionInfo {
+            name: name.to_string(),
+            age,
+            exited: rest.contains("(EXITED"),
+        })
+    }
+}
+
+/// Reads zellij's humantime ages: `3days 2h 4m 5s`, `1month 2days`.
+fn parse_age(age: &str) -> Option<u64> {
+    let mut total = 0;
+    for part in age.split_whitespace() {
+        let split = part.find(|c: char| !c.is_ascii_digit())?;
+        let (number, unit) = part.split_at(split);
+        let number: u64 = number.parse().ok()?;
+        let scale = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            "day" | "days" => 86400,
+            "week" | "weeks" => 7 * 86400,
+            "month" | "months" => 2_630_016,
+            "year" | "years" => 31_557_600,
+            _ => 0,
+        };
+        total += number * scale;
+    }
+    Some(total)
+}
+
+#[derive(Clone)]
+pub struct Zellij {
+    bin: String,
+    args: Vec<String>,
+    attach_args: Vec<String>,
+    runner: Arc<dyn ProcessRunner>,
+}
+
+impl Zellij {
+    pub fn new(config: &ZellijConfig, runner: Arc<dyn ProcessRunner>) -> Self {
+        Zellij {
+            bin: config.bin.clone(),
+            args: config.args.clone(),
+            attach_args: config.attach_args.clone(),
+            runner,
+        }
+    }
+
+    fn command(&self) -> Cmd {
+        let mut command = Cmd::new(&self.bin);
+        command.args(&self.args);
+        command
+    }
+
+    pub fn sessions(&self) -> Vec<String> {
+        let mut command = self.command();
+        command.arg("list-sessions").arg("--short");
+        debug!("running {}", command);
+        let Ok(output) = self.runner.output(&command) else {
+            return Vec::new();
+        };
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        stdout.lines().map(|line| line.trim().to_string()).collect()
+    }
+
+    /// Sessions with their age and whether they exited.
+    pub fn session_list(&self) -> Vec<SessionInfo> {
+        let mut command = self.command();
+        command.arg("list-sessions").arg("--no-formatting");
+        debug!("running {}", command);
+        let Ok(output) = self.runner.output(&command) else {
+            return Vec::new();
+        };
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        stdout.lines().filter_map(SessionInfo::parse).collect()
+    }
+
+    /// How many clients are attached to a running session; `None` if zellij
+    /// couldn't say.
+    pub fn client_count(&self, session_name: &str) -> Option<usize> {
+        let mut command = self.command();
+        command
+            .arg("--session")
+            .arg(session_name)
+            .arg("action")
+            .arg("list-clients");
+        debug!("running {}", command);
+        let output = self.runner.output(&command).ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        // A header line, then one line per client.
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Some(
+            stdout
+                .lines()
+                .skip(1)
+                .filter(|l| !l.trim().is_empty())
+                .count(),
+        )
+    }
+
+    /// Attaches to `session_name`, creating it in `dir` if needed. A layout
+    /// and `env` only apply when the session is new; existing sessions keep
+    /// theirs.
+    pub fn start_session(
+        &self,
+        session_name: &str,
+        dir: &Path,
+        layout: Option<&Path>,
+        env: &[(String, String)],
+    ) -> Result<()> {
+        let mut command = self.command();
+        match layout {
+            Some(layout) if !self.sessions().iter().any(|s| s == session_name) => {
+                command
+                    .arg("--new-session-with-layout")
+                    .arg(layout)
+                    .arg("--session")
+                    .arg(session_name);
+            }
+            _ => {
+                command
+                    .arg("attach")
+                    .arg("--create")
+                    .args(&self.attach_args)
+                    .arg(session_name);
+            }
+        }
+        command.current_dir(dir);
+        for (key, value) in env {
+            command.env(key, value);
+        }
+        debug!("running {}", command);
+        self.runner
+            .status(&command)
+            .with_context(|| format!("failed to start zellij session {session_name}"))?;
+        Ok(())
+    }
+
+    /// Starts `session_name` in `dir` without attaching to it, unless it is
+    /// already running. `env` applies as with [`Zellij::start_session`].
+    pub fn start_background(
+        &self,
+        session_name: &str,
+        dir: &Path,
+        env: &[(String, String)],
+    ) -> Result<()> {
+        let mut command = self.command();
+        command
+            .arg("attach")
+            .arg("--create-background")
+            .arg(session_name)
+            .current_dir(dir);
+        for (key, value) in env {
+            command.env(key, value);
+        }
+        debug!("running {}", command);
+        let output = self
+            .runner
+            .output(&command)
+            .with_context(|| format!("failed to start zellij session {session_name}"))?;
+        if !output.status.success() {
+            bail!(
+                "zellij could not start session {session_name}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    /// Kills `session_name`. A session that is already gone is no error;
+    /// failing to run zellij is.
+    pub fn kill_session(&self, session_name: &str) -> Result<()> {
+        let mut command = self.command();
+        command.arg("kill-session").arg(session_name);
+        debug!("running {}", command);
+        self.runner
+            .status(&command)
+            .with_context(|| format!("failed to kill zellij session {session_name}"))?;
+        Ok(())
+    }
+
+    /// Renames a running session in place; its panes and clients stay.
+    pub fn rename_session(&self, session_name: &str, new_name: &str) -> Result<()> {
+        let mut command = self.command();
+        command
+            .arg("--session")
+            .arg(session_name)
+            .arg("action")
+            .arg("rename-session")
+            .arg(new_name);
+        debug!("running {}", command);
+        let output = self
+            .runner
+            .output(&command)
+            .with_context(|| format!("failed to rename zellij session {session_name}"))?;
+        if !output.status.success() {
+            bail!(
+                "zellij could not rename session {session_name}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    /// Shows `text` in a floating pane named `title` in `session_name` until
+    /// Enter is pressed there. A session still starting up gets a few
+    /// seconds to come up first.
+    pub fn show_floating(&self, session_name: &str, title: &str, text: &str) -> Result<()> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !self
+            .session_list()
+            .iter()
+            .any(|session| session.name == session_name && !session.exited)
+        {
+            if Instant::now() > deadline {
+                bail!("session {session_name} did not come up");
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+        let mut command = self.command();
+        command
+            .arg("--session")
+            .arg(session_name)
+            .arg("action")
+            .arg("new-pane")
+            .arg("--floating")
+            .arg("--close-on-exit")
+            .arg("--name")
+            .arg(title)
+            .arg("--")
+            .args(["sh", "-c", "printf '%s\\n' \"$1\"; read -r _", "sh", text]);
+        debug!("running {}", command);
+        let output = self
+            .runner
+            .output(&command)
+            .with_context(|| format!("failed to open a pane in zellij session {session_name}"))?;
+        if !output.status.success() {
+            bail!(
+                "zellij could not open a pane in session {session_name}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    /// Goes to the tab named `name` in the session graft runs in, opening
+    /// it with its panes starting in `dir` if there is none. Only works from
+    /// inside a session.
+    pub fn open_tab(&self, name: &str, dir: &Path) -> Result<()> {
+        let mut query = self.command();
+        query.arg("action").arg("query-tab-names");
+        let exists = self.runner.output(&query).is_ok_and(|output| {
+            output.status.success()
+                && String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .any(|tab| tab == name)
+        });
+        let mut command = self.command();
+        command.arg("action");
+        if exists {
+            command.arg("go-to-tab-name").arg(name);
+        } else {
+            command
+                .arg("new-tab")
+                .arg("--cwd")
+                .arg(dir)
+                .arg("--name")
+                .arg(name);
+        }
+        debug!("running {}", command);
+        let output = self
+            .runner
+            .output(&command)
+            .with_context(|| format!("failed to open a zellij tab for {name}"))?;
+        if !output.status.success() {
+            bail!(
+                "zellij could not open a tab for {name}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    /// Moves the client graft runs in over to `session_name`, leaving the
+    /// session it was in running. Only works from inside a session.
+    pub fn switch_session(&self, session_name: &str) -> Result<()> {
+        let mut command = self.command();
+        command
+            .arg("action")
+            .arg("switch-session")
+            .arg(session_name);
+        debug!("running {}", command);
+        let output = self
+            .runner
+            .output(&command)
+            .with_context(|| format!("failed to switch to zellij session {session_name}"))?;
+        if !output.status.success() {
+            bail!(
+                "zellij could not switch to session {session_name}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    /// Kills `session_name` and forgets it, so zellij can't resurrect it
+    /// with its old directories. Needs zellij 0.39, which is also when
+    /// [`session_cwd`] starts finding anything.
+    pub fn delete_session(&self, session_name: &str) -> Result<()> {
+        let mut command = self.command();
+        command
+            .arg("delete-session")
+            .arg("--force")
+            .arg(session_name);
+        debug!("running {}", command);
+        self.runner
+            .status(&command)
+            .with_context(|| format!("failed to delete zellij session {session_name}"))?;
+        Ok(())
+    }
 }
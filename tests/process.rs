@@ -0,0 +1,250 @@
+//! git and zellij failing in ways a real system rarely reproduces on demand,
+//! through a `FakeRunner`.
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use graft_rs::capabilities::{Capabilities, Feature};
+use graft_rs::config::{IssuesConfig, ZellijConfig};
+use graft_rs::git::{Network, find_remote_branch, run_ok_in, run_remote_in, timed_out};
+use graft_rs::issues;
+use graft_rs::process::{Cmd, FakeRunner, ProcessRunner, Response, SystemRunner};
+use graft_rs::zellij::Zellij;
+
+fn zellij(runner: &Arc<FakeRunner>) -> Zellij {
+    let config = ZellijConfig {
+        args: vec!["--config-dir".to_string(), "/cfg".to_string()],
+        ..ZellijConfig::default()
+    };
+    Zellij::new(&config, runner.clone())
+}
+
+#[test]
+fn missing_zellij_lists_no_sessions_and_fails_to_attach() {
+    let runner = Arc::new(FakeRunner::new());
+    let zellij = zellij(&runner);
+
+    assert!(zellij.sessions().is_empty());
+    assert!(zellij.session_list().is_empty());
+    let err = zellij
+        .start_session("wt-feature", Path::new("/tmp"), None, &[])
+        .unwrap_err();
+    assert!(format!("{err:#}").contains("failed to start zellij session wt-feature"));
+}
+
+#[test]
+fn zellij_commands_carry_the_configured_arguments() {
+    let runner = Arc::new(FakeRunner::new());
+    runner.on("zellij", &[], Response::ok(""));
+    let zellij = zellij(&runner);
+
+    zellij.kill_session("wt-old").unwrap();
+    let calls = runner.calls();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(
+        calls[0].to_string(),
+        "zellij --config-dir /cfg kill-session wt-old"
+    );
+}
+
+#[test]
+fn garbage_session_output_is_ignored() {
+    let runner = Arc::new(FakeRunner::new());
+    runner.on(
+        "zellij",
+        &["list-sessions", "--no-formatting"],
+        Response::ok("\u{1b}[0m\n\nwt-a [Created 2h 3m ago]\n"),
+    );
+    runner.on(
+        "zellij",
+        &["list-clients"],
+        Response::fail(1, "There is no active session!"),
+    );
+    let zellij = zellij(&runner);
+
+    let sessions = zellij.session_list();
+    let names: Vec<&str> = sessions.iter().map(|s| s.name.as_str()).collect();
+    assert!(names.contains(&"wt-a"));
+    assert_eq!(zellij.client_count("wt-a"), None);
+}
+
+#[test]
+fn a_refused_session_switch_says_why() {
+    let runner = Arc::new(FakeRunner::new());
+    runner.on(
+        "zellij",
+        &["switch-session"],
+        Response::fail(1, "There is no active session!"),
+    );
+    let zellij = zellij(&runner);
+
+    let err = zellij.switch_session("wt-b").unwrap_err();
+    assert!(format!("{err:#}").contains("There is no active session!"));
+    assert_eq!(
+        runner.calls()[0].to_string(),
+        "zellij --config-dir /cfg action switch-session wt-b"
+    );
+}
+
+#[test]
+fn git_failures_carry_stderr() {
+    let runner = FakeRunner::new();
+    runner.on(
+        "git",
+        &["fetch"],
+        Response::fail(
+            128,
+            "fatal: 'origin' does not appear to be a git repository\n",
+        ),
+    );
+
+    let err = run_ok_in(&runner, Path::new("/repo"), &["fetch", "origin"]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "git fetch origin failed: fatal: 'origin' does not appear to be a git repository"
+    );
+    let calls = runner.calls();
+    assert_eq!(calls[0].to_string(), "git -C /repo fetch origin");
+}
+
+#[test]
+fn missing_git_is_reported() {
+    let runner = FakeRunner::new();
+    let err = run_ok_in(&runner, Path::new("/repo"), &["status"]).unwrap_err();
+    assert_eq!(err.to_string(), "failed to execute git");
+}
+
+#[test]
+fn remote_lookup_survives_one_unreachable_remote() {
+    let runner = FakeRunner::new();
+    runner.on(
+        "git",
+        &["ls-remote", "--heads", "fork"],
+        Response::fail(128, "timeout"),
+    );
+    runner.on(
+        "git",
+        &["ls-remote", "--heads", "origin"],
+        Response::ok("0123abcd\trefs/heads/feature\n"),
+    );
+    let remotes = ["fork".to_string(), "origin".to_string()];
+    let network = Network {
+        retries: 0,
+        ..Network::default()
+    };
+
+    let found =
+        find_remote_branch(&runner, Path::new("/repo"), &remotes, "feature", network).unwrap();
+    assert_eq!(found.as_deref(), Some("origin"));
+
+    let err = find_remote_branch(
+        &runner,
+        Path::new("/repo"),
+        &remotes[..1],
+        "feature",
+        network,
+    );
+    assert!(err.is_err());
+}
+
+#[test]
+fn failed_fetches_are_retried_a_bounded_number_of_times() {
+    let runner = FakeRunner::new();
+    runner.on("git", &["fetch"], Response::fail(128, "Connection reset"));
+    let network = Network {
+        retries: 1,
+        ..Network::default()
+    };
+
+    let err = run_remote_in(&runner, Path::new("/repo"), &["fetch", "origin"], network);
+    assert!(err.is_err());
+    assert_eq!(runner.calls().len(), 2);
+}
+
+#[test]
+fn only_timeouts_and_network_failures_are_retried() {
+    let network = Network {
+        retries: 1,
+        ..Network::default()
+    };
+    let runs = |response: Response| {
+        let runner = FakeRunner::new();
+        runner.on("git", &["fetch"], response);
+        let result = run_remote_in(&runner, Path::new("/repo"), &["fetch", "origin"], network);
+        (result, runner.calls().len())
+    };
+
+    let (timeout, calls) = runs(Response::TimedOut);
+    assert!(timed_out(&timeout.unwrap_err()));
+    assert_eq!(calls, 2);
+    let (_, calls) = runs(Response::fail(
+        128,
+        "fatal: unable to access 'https://example.com/repo.git/': Could not resolve host: example.com",
+    ));
+    assert_eq!(calls, 2);
+    let (missing, calls) = runs(Response::fail(
+        128,
+        "fatal: couldn't find remote ref refs/pull/7/head",
+    ));
+    assert!(format!("{:#}", missing.unwrap_err()).contains("couldn't find remote ref"));
+    assert_eq!(calls, 1);
+    let (_, calls) = runs(Response::fail(
+        128,
+        "git@example.com: Permission denied (publickey).\nfatal: Could not read from remote repository.",
+    ));
+    assert_eq!(calls, 1);
+}
+
+#[test]
+fn capabilities_follow_reported_versions() {
+    let runner = Arc::new(FakeRunner::new());
+    runner.on("git", &["--version"], Response::ok("git version 2.20.1\n"));
+    runner.on("zellij", &["--version"], Response::ok("not a version\n"));
+    let capabilities = Capabilities::new("zellij", runner.clone());
+
+    assert_eq!(capabilities.missing(Feature::Worktrees), None);
+    assert_eq!(
+        capabilities.missing(Feature::WorktreeRepair).as_deref(),
+        Some("git 2.20.1 is older than 2.30.0")
+    );
+    assert_eq!(
+        capabilities.missing(Feature::Sessions).as_deref(),
+        Some("zellij was not found")
+    );
+    assert!(capabilities.require(Feature::Docker).is_err());
+}
+
+#[test]
+fn commands_outliving_their_timeout_are_killed() {
+    let mut sleep = Cmd::new("sleep");
+    sleep.arg("5").timeout(Duration::from_millis(200));
+    let start = Instant::now();
+
+    let err = SystemRunner.output(&sleep).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn github_issues_name_branches_after_their_titles() {
+    let runner = Arc::new(FakeRunner::new());
+    runner.on(
+        "gh",
+        &["issue", "view", "1234"],
+        Response::ok(
+            r#"{"number":1234,"title":"Login page: crashes on empty password!","url":"https://github.com/o/r/issues/1234"}"#,
+        ),
+    );
+    let config = IssuesConfig::default();
+
+    let issue = issues::lookup(runner.as_ref(), &config, Path::new("/repo"), "#1234").unwrap();
+
+    assert_eq!(issue.url, "https://github.com/o/r/issues/1234");
+    assert_eq!(
+        issues::branch_name(&config.branch, &issue),
+        "1234-login-page-crashes-on-empty-password"
+    );
+    assert_eq!(runner.calls()[0].dir(), Some(Path::new("/repo")));
+}
@@ -0,0 +1,354 @@
+//! Worktree creation, listing and removal against real repositories.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use common::{Fixture, commit, git};
+use graft_rs::config::{Config, NestedRepos};
+use graft_rs::git::find_remote_branch;
+use graft_rs::state;
+
+#[test]
+fn new_branch_gets_a_worktree_tracking_the_remote() {
+    let fixture = Fixture::new();
+    let ctx = fixture.context();
+
+    ctx.git.ensure_branch("feature", true, None).unwrap();
+    let worktree = ctx.git.ensure_worktree("feature").unwrap();
+
+    let expected = fixture.repo.join(".worktrees/feature");
+    assert_eq!(worktree.path(), expected);
+    assert!(expected.join("README.md").is_file());
+    assert!(
+        fixture
+            .porcelain_worktrees()
+            .contains(&(expected, Some("feature".to_string())))
+    );
+    let merge = git(&fixture.repo, &["config", "branch.feature.merge"]);
+    assert_eq!(merge.trim(), "refs/heads/feature");
+}
+
+#[test]
+fn branch_on_the_remote_is_checked_out_from_it() {
+    let fixture = Fixture::new();
+    fixture.remote_branch("from-remote");
+    let ctx = fixture.context();
+
+    let remotes = ctx.git.remotes();
+    let remote = find_remote_branch(
+        ctx.git.runner(),
+        &ctx.git.root(),
+        &remotes,
+        "from-remote",
+        ctx.git.network(),
+    )
+    .unwrap();
+    assert_eq!(remote.as_deref(), Some("origin"));
+    ctx.git
+        .ensure_branch("from-remote", true, remote.as_deref())
+        .unwrap();
+    let worktree = ctx.git.ensure_worktree("from-remote").unwrap();
+
+    let head = git(worktree.path(), &["rev-parse", "HEAD"]);
+    let remote_head = git(&fixture.remote, &["rev-parse", "refs/heads/from-remote"]);
+    assert_eq!(head, remote_head);
+    let upstream = git(
+        worktree.path(),
+        &["rev-parse", "--abbrev-ref", "@{upstream}"],
+    );
+    assert_eq!(upstream.trim(), "origin/from-remote");
+}
+
+#[test]
+fn ensure_worktree_reuses_an_existing_worktree() {
+    let fixture = Fixture::new();
+    let ctx = fixture.context();
+    ctx.git.ensure_branch("again", true, None).unwrap();
+
+    let first = ctx
+        .git
+        .ensure_worktree("again")
+        .unwrap()
+        .path()
+        .to_path_buf();
+    let second = ctx
+        .git
+        .ensure_worktree("again")
+        .unwrap()
+        .path()
+        .to_path_buf();
+
+    assert_eq!(first, second);
+    assert_eq!(fixture.porcelain_worktrees().len(), 2);
+}
+
+#[test]
+fn nested_branch_names_nest_and_are_listed_by_branch() {
+    let fixture = Fixture::new();
+    let ctx = fixture.context();
+    ctx.git.ensure_branch("review/login", true, None).unwrap();
+    ctx.git.ensure_worktree("review/login").unwrap();
+
+    assert!(
+        fixture
+            .repo
+            .join(".worktrees/review/login/README.md")
+            .is_file()
+    );
+    let labels: Vec<String> = ctx
+        .git
+        .worktrees()
+        .unwrap()
+        .iter()
+        .map(|worktree| worktree.label().to_string())
+        .collect();
+    assert_eq!(labels, ["review/login"]);
+}
+
+#[test]
+fn removal_deletes_the_checkout_and_its_empty_parents() {
+    let fixture = Fixture::new();
+    let ctx = fixture.context();
+    ctx.git.ensure_branch("review/gone", true, None).unwrap();
+    ctx.git.ensure_worktree("review/gone").unwrap();
+
+    ctx.git.remove_worktree("review/gone", false).unwrap();
+
+    assert!(!fixture.repo.join(".worktrees/review").exists());
+    assert!(fixture.repo.join(".worktrees").is_dir());
+    assert_eq!(fixture.porcelain_worktrees().len(), 1);
+    assert!(!ctx.git.has_worktree("review/gone"));
+    assert!(ctx.git.has_branch("review/gone"));
+}
+
+#[test]
+fn worktree_root_places_worktrees_outside_the_repository() {
+    let fixture = Fixture::new();
+    let config = Config {
+        worktree_root: Some(format!(
+            "{}/trees/{{repo}}/{{branch}}",
+            fixture.root.display()
+        )),
+        ..Config::default()
+    };
+    let ctx = fixture.context_with(config);
+    ctx.git.ensure_branch("outside", true, None).unwrap();
+
+    let worktree = ctx.git.ensure_worktree("outside").unwrap();
+
+    assert_eq!(worktree.path(), fixture.root.join("trees/repo/outside"));
+    assert_eq!(ctx.git.worktree_dir_ignored().unwrap(), None);
+}
+
+#[test]
+fn removal_leaves_an_unregistered_directory_alone() {
+    let fixture = Fixture::new();
+    let ctx = fixture.context();
+    let stray = fixture.repo.join(".worktrees/stray");
+    fs::create_dir_all(&stray).unwrap();
+    fs::write(stray.join("notes.txt"), "keep me\n").unwrap();
+
+    let err = ctx.git.remove_worktree("stray", false).unwrap_err();
+
+    assert!(
+        err.to_string().contains("not a registered worktree"),
+        "{err}"
+    );
+    assert!(stray.join("notes.txt").is_file());
+}
+
+#[test]
+fn removal_refuses_a_worktree_outside_the_worktree_directory() {
+    let fixture = Fixture::new();
+    // Named like graft would, so it is found as the branch's worktree.
+    let elsewhere = fixture.root.join("elsewhere/moved");
+    git(
+        &fixture.repo,
+        &[
+            "worktree",
+            "add",
+            "-q",
+            "-b",
+            "moved",
+            elsewhere.to_str().unwrap(),
+        ],
+    );
+    let ctx = fixture.context();
+
+    assert!(ctx.git.remove_worktree("moved", false).is_err());
+    assert!(elsewhere.join("README.md").is_file());
+    assert!(ctx.git.has_worktree("moved"));
+}
+
+#[test]
+fn an_interrupted_add_is_cleaned_up_and_redone() {
+    let fixture = Fixture::new();
+    let ctx = fixture.context();
+    ctx.git.ensure_branch("killed", true, None).unwrap();
+    let path = ctx
+        .git
+        .ensure_worktree("killed")
+        .unwrap()
+        .path()
+        .to_path_buf();
+    // What an add killed during checkout leaves: no index, few files.
+    fs::remove_file(fixture.repo.join(".git/worktrees/killed/index")).unwrap();
+    fs::remove_file(path.join("README.md")).unwrap();
+
+    ctx.git.ensure_worktree("killed").unwrap();
+
+    assert!(path.join("README.md").is_file());
+    assert!(fixture.repo.join(".git/worktrees/killed/index").is_file());
+    assert_eq!(git(&path, &["status", "--porcelain"]), "");
+}
+
+#[test]
+fn a_checkout_left_by_a_failed_add_is_replaced() {
+    let fixture = Fixture::new();
+    let ctx = fixture.context();
+    ctx.git.ensure_branch("failed", true, None).unwrap();
+    let path = fixture.repo.join(".worktrees/failed");
+    fs::create_dir_all(&path).unwrap();
+    let admin = fixture.repo.join(".git/worktrees/failed");
+    fs::write(path.join(".git"), format!("gitdir: {}\n", admin.display())).unwrap();
+
+    ctx.git.ensure_worktree("failed").unwrap();
+
+    assert!(path.join("README.md").is_file());
+    assert!(ctx.git.has_worktree("failed"));
+}
+
+#[test]
+fn a_mangled_dot_git_file_is_reported_and_relinked() {
+    let fixture = Fixture::new();
+    let ctx = fixture.context();
+    ctx.git.ensure_branch("feature", true, None).unwrap();
+    let path = ctx
+        .git
+        .ensure_worktree("feature")
+        .unwrap()
+        .path()
+        .to_path_buf();
+    fs::write(path.join(".git"), "synced over\n").unwrap();
+
+    let worktrees = ctx.git.worktrees().unwrap();
+    let damage = ctx.git.worktree_damage(&worktrees[0]).unwrap();
+    assert!(
+        damage.iter().any(|d| d.contains(".git is not")),
+        "{damage:?}"
+    );
+
+    ctx.git.repair_worktrees().unwrap();
+    let worktrees = ctx.git.worktrees().unwrap();
+    assert_eq!(worktrees[0].branch.as_deref(), Some("feature"));
+    assert!(ctx.git.worktree_damage(&worktrees[0]).unwrap().is_empty());
+}
+
+#[test]
+fn the_lock_is_held_until_the_outermost_one_drops() {
+    let fixture = Fixture::new();
+    let ctx = fixture.context();
+    let lock_file = ctx.git.state_dir().join("lock");
+    fs::create_dir_all(ctx.git.state_dir()).unwrap();
+    // A dead run's pid is no lock: the OS released it with the run.
+    fs::write(&lock_file, "999999999\n").unwrap();
+    let other_run = || fs::File::open(&lock_file).unwrap().try_lock();
+
+    let lock = ctx.lock().unwrap();
+    let nested = ctx.lock().unwrap();
+    drop(nested);
+    assert!(matches!(other_run(), Err(fs::TryLockError::WouldBlock)));
+    drop(lock);
+    assert!(other_run().is_ok());
+}
+
+#[test]
+fn another_thread_waits_for_the_lock() {
+    let fixture = Fixture::new();
+    let state_dir = fixture.context().git.state_dir();
+    let lock = state::lock(&state_dir).unwrap();
+    let taken = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        let waiter = scope.spawn(|| {
+            let _lock = state::lock(&state_dir).unwrap();
+            taken.store(true, Ordering::SeqCst);
+        });
+        thread::sleep(Duration::from_millis(300));
+        assert!(!taken.load(Ordering::SeqCst));
+        drop(lock);
+        waiter.join().unwrap();
+    });
+    assert!(taken.load(Ordering::SeqCst));
+}
+
+#[test]
+fn work_in_a_nested_clone_counts_unless_skipped() {
+    let fixture = Fixture::new();
+    let ctx = fixture.context();
+    ctx.git.ensure_branch("feature", true, None).unwrap();
+    let path = ctx
+        .git
+        .ensure_worktree("feature")
+        .unwrap()
+        .path()
+        .to_path_buf();
+    let clone = path.join("vendor-lib");
+    fs::create_dir(&clone).unwrap();
+    git(&clone, &["init", "-q"]);
+    fs::write(clone.join("lib.rs"), "").unwrap();
+    git(&clone, &["add", "."]);
+    commit(&clone, "Local only");
+    fs::write(clone.join("scratch.txt"), "").unwrap();
+
+    let status = ctx.git.inspect(&path).unwrap();
+    assert_eq!(
+        (
+            status.dirty_files,
+            status.untracked_files,
+            status.nested_unpushed
+        ),
+        (1, 0, 1)
+    );
+    let nested = ctx.git.nested_repos(&path).unwrap();
+    assert_eq!(nested.len(), 1);
+    assert_eq!(nested[0].path, Path::new("vendor-lib"));
+    assert!(!nested[0].submodule && nested[0].has_work());
+
+    let skip = fixture.context_with(Config {
+        nested_repos: NestedRepos::Skip,
+        ..Config::default()
+    });
+    let status = skip.git.inspect(&path).unwrap();
+    assert_eq!(
+        (
+            status.dirty_files,
+            status.untracked_files,
+            status.nested_unpushed
+        ),
+        (0, 0, 0)
+    );
+}
+
+#[test]
+fn a_deleted_worktree_is_recreated_without_pruning_others() {
+    let fixture = Fixture::new();
+    let ctx = fixture.context();
+    for branch in ["mine", "someone-elses"] {
+        ctx.git.ensure_branch(branch, false, None).unwrap();
+        ctx.git.ensure_worktree(branch).unwrap();
+    }
+    fs::remove_dir_all(fixture.repo.join(".worktrees/mine")).unwrap();
+    fs::remove_dir_all(fixture.repo.join(".worktrees/someone-elses")).unwrap();
+
+    let worktree = ctx.git.ensure_worktree("mine").unwrap();
+
+    assert!(worktree.path().join("README.md").is_file());
+    let listed = git(&fixture.repo, &["worktree", "list", "--porcelain"]);
+    assert!(listed.contains("someone-elses"), "{listed}");
+}